@@ -0,0 +1,21 @@
+/* A native extension, built by `./setup_ffi.sh` and used by `native.lyr`. */
+
+#include <stdio.h>
+
+#include "lyron.h"
+
+static int greet(const LyronValue *args, size_t nargs, LyronValue *result) {
+    static char buffer[256];
+    if (args[0].tag != LYRON_STR)
+        return lyron_error(result, "greet takes a Str");
+    snprintf(buffer, sizeof buffer, "Hello, %s!", args[0].as.str.ptr);
+    *result = lyron_str(buffer);
+    return LYRON_OK;
+}
+
+int lyron_init(const LyronRegistrar *r) {
+    if (r->abi_version != LYRON_ABI_VERSION)
+        return 1;
+    r->register_fn(r->ctx, "greet", 1, greet);
+    return 0;
+}
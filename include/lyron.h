@@ -0,0 +1,152 @@
+/*
+ * The C ABI of Lyron native extensions.
+ *
+ * An extension is a shared library loaded with `extern "path/libname.so"`.
+ * Lyron calls its `lyron_init` function once, which registers the functions
+ * the library provides; Lyron code then calls them like builtins.
+ *
+ *     #include "lyron.h"
+ *
+ *     static int add(const LyronValue *args, size_t nargs, LyronValue *result) {
+ *         if (args[0].tag != LYRON_INT || args[1].tag != LYRON_INT)
+ *             return lyron_error(result, "add takes two Ints");
+ *         *result = lyron_int(args[0].as.i + args[1].as.i);
+ *         return LYRON_OK;
+ *     }
+ *
+ *     int lyron_init(const LyronRegistrar *r) {
+ *         if (r->abi_version != LYRON_ABI_VERSION)
+ *             return 1;
+ *         r->register_fn(r->ctx, "add", 2, add);
+ *         return 0;
+ *     }
+ *
+ * Build it with `cc -shared -fPIC -I include add.c -o libadd.so`.
+ */
+
+#ifndef LYRON_H
+#define LYRON_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Bumped whenever a type below changes. */
+#define LYRON_ABI_VERSION 1
+
+typedef enum LyronTag {
+    LYRON_NONE = 0,
+    LYRON_BOOL = 1,
+    LYRON_INT = 2,
+    LYRON_DOUBLE = 3,
+    LYRON_STR = 4,
+} LyronTag;
+
+/*
+ * A value passed to or returned from a native function. Only these types
+ * cross the boundary; passing anything else is an error on the Lyron side,
+ * and Ints must fit in 64 bits.
+ *
+ * Strings are UTF-8 and `len` bytes long. Those passed in are also NUL
+ * terminated and live until the function returns. Those returned are copied
+ * by Lyron straight away, so they may point to static or reused memory.
+ */
+typedef struct LyronValue {
+    LyronTag tag;
+    union {
+        bool b;
+        int64_t i;
+        double d;
+        struct {
+            const char *ptr;
+            size_t len;
+        } str;
+    } as;
+} LyronValue;
+
+/* What native functions return. */
+#define LYRON_OK 0
+#define LYRON_ERROR 1
+
+/*
+ * A native function. It gets its arguments and stores what it returns in
+ * `result`, which starts out as `none`. On failure it returns LYRON_ERROR
+ * with a Str in `result` saying why; that becomes an error in Lyron.
+ */
+typedef int (*LyronNativeFn)(const LyronValue *args, size_t nargs, LyronValue *result);
+
+/* Passed to `lyron_init`. */
+typedef struct LyronRegistrar {
+    uint32_t abi_version;
+    /* Passed back to `register_fn`. */
+    void *ctx;
+    /*
+     * Make `fn` callable as `name` (copied), taking `arity` arguments, or any
+     * number of them if `arity` is -1.
+     */
+    void (*register_fn)(void *ctx, const char *name, int32_t arity, LyronNativeFn fn);
+} LyronRegistrar;
+
+/*
+ * The entry point every extension defines. It returns 0 on success, anything
+ * else makes the `extern` fail.
+ */
+int lyron_init(const LyronRegistrar *registrar);
+
+static inline LyronValue lyron_none(void) {
+    LyronValue v;
+    v.tag = LYRON_NONE;
+    return v;
+}
+
+static inline LyronValue lyron_bool(bool b) {
+    LyronValue v;
+    v.tag = LYRON_BOOL;
+    v.as.b = b;
+    return v;
+}
+
+static inline LyronValue lyron_int(int64_t i) {
+    LyronValue v;
+    v.tag = LYRON_INT;
+    v.as.i = i;
+    return v;
+}
+
+static inline LyronValue lyron_double(double d) {
+    LyronValue v;
+    v.tag = LYRON_DOUBLE;
+    v.as.d = d;
+    return v;
+}
+
+static inline LyronValue lyron_str_n(const char *ptr, size_t len) {
+    LyronValue v;
+    v.tag = LYRON_STR;
+    v.as.str.ptr = ptr;
+    v.as.str.len = len;
+    return v;
+}
+
+static inline LyronValue lyron_str(const char *s) {
+    size_t len = 0;
+    while (s[len])
+        len++;
+    return lyron_str_n(s, len);
+}
+
+/* Fail with `message` (a string that outlives the call). */
+static inline int lyron_error(LyronValue *result, const char *message) {
+    *result = lyron_str(message);
+    return LYRON_ERROR;
+}
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -1,14 +1,7 @@
-sudo cp ./lyvalue.cpp /usr/include/
-echo "====COPIED INCLUDE FILE==="
-echo "====BUILDING liblyron FILE==="
-g++ -fPIC -c -Wall lyvalue.cpp -fpermissive
-echo "====BUILT liblyron FILE==="
-echo "====LINKING liblyron FILE==="
-ld -shared lyvalue.o -o liblyron.so
-echo "====LINKED liblyron FILE==="
-echo "====liblyron.so successfully created==="
-sudo mv liblyron.so /usr/lib/
-echo "====liblyron.so placed in global lib directory==="
-echo "cleaning up..."
-rm lyvalue.o
-echo "all done"
\ No newline at end of file
+#!/bin/sh
+# Build the example native extension, examples/native/libgreet.so.
+# Extensions include `include/lyron.h`, which documents the ABI.
+set -e
+cd "$(dirname "$0")"
+cc -shared -fPIC -Wall -I include examples/native/greet.c -o examples/native/libgreet.so
+echo "built examples/native/libgreet.so, try: lyronc examples/native.lyr"
@@ -0,0 +1,549 @@
+//! Functions implemented in Rust that every program can call.
+//!
+//! Each [`Builtin`] has a signature like a `def`, which the resolver checks
+//! calls against and the interpreter binds arguments with, and a Rust closure
+//! doing the work. [`Registry::standard`] holds the ones scripts and the
+//! standard library use; new native functions are added there.
+
+use crate::Result;
+use crate::interpreter::{Interpreter, Value, gc};
+use crate::parser::Args;
+use crate::sandbox::Capability;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+
+/// The Rust side of a builtin. It gets one value per parameter, followed by
+/// the extra arguments of a variadic builtin.
+pub type NativeFn = Rc<dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value>>;
+
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: String,
+    /// Name and type of each parameter. `Any` accepts every value and `Double`
+    /// accepts ints too.
+    pub params: Vec<(String, String)>,
+    /// Takes any number of extra arguments, like a `*rest: Any` parameter.
+    pub variadic: bool,
+    pub return_type: String,
+    /// Needed to call the builtin in a sandboxed interpreter.
+    pub capability: Option<Capability>,
+    pub run: NativeFn,
+}
+
+impl Builtin {
+    pub fn new(
+        name: &str,
+        params: &[(&str, &str)],
+        return_type: &str,
+        run: impl Fn(&mut Interpreter, Vec<Value>) -> Result<Value> + 'static,
+    ) -> Self {
+        Builtin {
+            name: name.to_string(),
+            params: params
+                .iter()
+                .map(|(n, t)| (n.to_string(), t.to_string()))
+                .collect(),
+            variadic: false,
+            return_type: return_type.to_string(),
+            capability: None,
+            run: Rc::new(run),
+        }
+    }
+
+    /// Accept extra arguments after the parameters.
+    pub fn variadic(mut self) -> Self {
+        self.variadic = true;
+        self
+    }
+
+    /// Only callable with `capability` granted.
+    pub fn requires(mut self, capability: Capability) -> Self {
+        self.capability = Some(capability);
+        self
+    }
+
+    /// The parameters as a `def` would declare them.
+    pub fn args(&self) -> Args {
+        Args {
+            name: self.params.iter().map(|(n, _)| n.clone()).collect(),
+            type_: self.params.iter().map(|(_, t)| t.clone()).collect(),
+            default: vec![None; self.params.len()],
+            vararg: self
+                .variadic
+                .then(|| ("values".to_string(), "Any".to_string())),
+            kwarg: None,
+        }
+    }
+
+    /// Bind the arguments of a call, check their types and run the builtin.
+    pub fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value> {
+        if let Some(capability) = self.capability {
+            interpreter.require(capability, &format!("`{}`", self.name))?;
+        }
+        let bound = self
+            .args()
+            .bind(args, kwargs)
+            .map_err(|e| format!("in call to `{}`: {}", self.name, e))?;
+        let mut values = bound.params.into_iter().flatten().collect::<Vec<_>>();
+        for (value, (param, type_)) in values.iter().zip(&self.params) {
+            if !accepts(type_, value) {
+                return Err(format!(
+                    "in call to `{}`: expected {} for `{}` but got {}",
+                    self.name,
+                    type_,
+                    param,
+                    value.type_name()
+                ));
+            }
+        }
+        values.extend(bound.rest);
+        (self.run)(interpreter, values)
+    }
+}
+
+/// Whether a parameter of type `type_` takes `value`. Ints pass for doubles.
+fn accepts(type_: &str, value: &Value) -> bool {
+    match (type_, value) {
+        ("Any", _) => true,
+        ("Double", Value::Integer(_) | Value::BigInteger(_)) => true,
+        _ => value.type_name() == type_,
+    }
+}
+
+/// `def name(params) -> Type`, for showing to users.
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut params = self
+            .params
+            .iter()
+            .map(|(n, t)| format!("{}: {}", n, t))
+            .collect::<Vec<_>>();
+        if self.variadic {
+            params.push("*values: Any".to_string());
+        }
+        write!(
+            f,
+            "def {}({}) -> {}",
+            self.name,
+            params.join(", "),
+            self.return_type
+        )
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Builtin({})", self)
+    }
+}
+
+/// Builtins by name.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    builtins: BTreeMap<String, Builtin>,
+}
+
+impl Registry {
+    /// A registry without any builtins.
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// The builtins lyronc comes with.
+    pub fn standard() -> Self {
+        let mut registry = Registry::new();
+        for builtin in [
+            Builtin::new("print", &[], "None", print).variadic(),
+            Builtin::new(
+                "setattr",
+                &[("obj", "Any"), ("name", "Str"), ("value", "Any")],
+                "Any",
+                setattr,
+            ),
+            Builtin::new(
+                "getattr",
+                &[("obj", "Any"), ("name", "Str")],
+                "Any",
+                |i, a| i.get_attr(&a[0], a[1].as_str()?),
+            ),
+            Builtin::new("len", &[("value", "Any")], "Int", len),
+            Builtin::new("str", &[("value", "Any")], "Str", |_, a| {
+                Ok(Value::Str(a[0].to_string()))
+            }),
+            Builtin::new("type", &[("value", "Any")], "Str", |_, a| {
+                Ok(Value::Str(a[0].type_name()))
+            }),
+            Builtin::new("read_file", &[("path", "Str")], "Str", |_, a| {
+                let path = a[0].as_str()?;
+                let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+                Ok(Value::Str(text))
+            })
+            .requires(Capability::ReadFiles),
+            Builtin::new(
+                "write_file",
+                &[("path", "Str"), ("data", "Str")],
+                "None",
+                |_, a| {
+                    let path = a[0].as_str()?;
+                    fs::write(path, a[1].as_str()?).map_err(|e| format!("{}: {}", path, e))?;
+                    Ok(Value::None)
+                },
+            )
+            .requires(Capability::WriteFiles),
+            Builtin::new("json_dumps", &[("obj", "Any")], "Str", |_, a| {
+                Ok(Value::Str(to_json(&a[0], &mut vec![])?.to_string()))
+            }),
+            Builtin::new("json_parse", &[("data", "Str")], "Any", |_, a| {
+                let json = serde_json::from_str(a[0].as_str()?).map_err(|e| e.to_string())?;
+                Ok(from_json(json))
+            }),
+            Builtin::new(
+                "start_tcp_server",
+                &[("handler", "Function"), ("port", "Str"), ("host", "Str")],
+                "None",
+                |i, a| {
+                    let address = format!("{}:{}", a[2].as_str()?, a[1].as_str()?);
+                    let listener =
+                        TcpListener::bind(&address).map_err(|e| format!("{}: {}", address, e))?;
+                    serve(i, &listener, &a[0], None)
+                },
+            )
+            .requires(Capability::Network),
+            Builtin::new("gc_collect", &[], "Int", |i, _| {
+                Ok(Value::Integer(i.collect_garbage() as i64))
+            }),
+            Builtin::new("gc_stats", &[], "Dict", |i, _| {
+                let stats = gc::stats();
+                let entries = [
+                    ("collections", stats.collections),
+                    ("allocated", stats.allocated),
+                    ("freed", stats.freed),
+                    ("live", stats.live),
+                    ("threshold", i.gc_threshold()),
+                ];
+                Ok(Value::dict(
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| (k.to_string(), Value::Integer(v as i64)))
+                        .collect(),
+                ))
+            }),
+        ] {
+            registry.register(builtin);
+        }
+        registry
+    }
+
+    /// Add `builtin`, replacing any builtin with the same name.
+    pub fn register(&mut self, builtin: Builtin) {
+        self.builtins.insert(builtin.name.clone(), builtin);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.builtins.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Builtin> {
+        self.builtins.values()
+    }
+}
+
+fn print(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let line = args.iter().map(Value::to_string).collect::<Vec<_>>();
+    writeln!(interpreter.output, "{}", line.join(" ")).map_err(|e| e.to_string())?;
+    Ok(Value::None)
+}
+
+/// Returns the object, so `self = setattr(self, "a", 1)` works.
+fn setattr(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let key = args[1].as_str()?.to_string();
+    match &args[0] {
+        Value::Object(o) => o.borrow_mut().attrs.insert(key, args[2].clone()),
+        Value::Dict(d) => d.borrow_mut().insert(key, args[2].clone()),
+        v => return Err(format!("cannot set attributes on {}", v.type_name())),
+    };
+    Ok(args[0].clone())
+}
+
+fn len(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let len = match &args[0] {
+        Value::Str(s) => s.chars().count(),
+        Value::Array(a) => a.borrow().len(),
+        Value::Tuple(t) => t.len(),
+        Value::Dict(d) => d.borrow().len(),
+        v => return Err(format!("{} has no length", v.type_name())),
+    };
+    Ok(Value::Integer(len as i64))
+}
+
+/// `value` as JSON. Arrays, dicts and objects in `seen` are being converted
+/// further up, so finding one again means the value contains itself.
+fn to_json(value: &Value, seen: &mut Vec<*const ()>) -> Result<serde_json::Value> {
+    use serde_json::Value as J;
+    let container: Option<*const ()> = match value {
+        Value::Array(a) => Some(Rc::as_ptr(a).cast()),
+        Value::Dict(d) => Some(Rc::as_ptr(d).cast()),
+        Value::Object(o) => Some(Rc::as_ptr(o).cast()),
+        _ => None,
+    };
+    if let Some(p) = container {
+        if seen.contains(&p) {
+            return Err(format!(
+                "cannot convert {} to JSON, it contains itself",
+                value.type_name()
+            ));
+        }
+        seen.push(p);
+    }
+    let mut list = |values: &[Value]| {
+        values
+            .iter()
+            .map(|v| to_json(v, seen))
+            .collect::<Result<_>>()
+    };
+    let json = match value {
+        Value::None => J::Null,
+        Value::Boolean(b) => J::Bool(*b),
+        Value::Integer(i) => J::from(*i),
+        Value::Double(d) => serde_json::Number::from_f64(*d)
+            .map(J::Number)
+            .ok_or_else(|| format!("{} is not valid in JSON", d))?,
+        Value::Str(s) => J::String(s.clone()),
+        Value::Array(a) => J::Array(list(&a.borrow())?),
+        Value::Tuple(t) => J::Array(list(t)?),
+        Value::Dict(d) => J::Object(to_json_map(&d.borrow(), seen)?),
+        Value::Object(o) => J::Object(to_json_map(&o.borrow().attrs, seen)?),
+        v => return Err(format!("cannot convert {} to JSON", v.type_name())),
+    };
+    if container.is_some() {
+        seen.pop();
+    }
+    Ok(json)
+}
+
+fn to_json_map(
+    entries: &BTreeMap<String, Value>,
+    seen: &mut Vec<*const ()>,
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    entries
+        .iter()
+        .map(|(k, v)| Ok((k.clone(), to_json(v, seen)?)))
+        .collect()
+}
+
+fn from_json(json: serde_json::Value) -> Value {
+    use serde_json::Value as J;
+    match json {
+        J::Null => Value::None,
+        J::Bool(b) => Value::Boolean(b),
+        J::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => match n.as_u64() {
+                Some(u) => Value::from_bigint(u.into()),
+                None => Value::Double(n.as_f64().unwrap_or(f64::NAN)),
+            },
+        },
+        J::String(s) => Value::Str(s),
+        J::Array(values) => Value::array(values.into_iter().map(from_json).collect()),
+        J::Object(entries) => Value::dict(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k, from_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// Answer HTTP requests on `listener` with what `handler(data, request)`
+/// returns, where `data` has the `method` and `path` of the request and
+/// `request` is its text. Stops after `limit` connections, if given.
+///
+/// A request that can't be read gets a 400 and one the handler fails on a
+/// 500, with the error logged to stderr; the server carries on either way.
+fn serve(
+    interpreter: &mut Interpreter,
+    listener: &TcpListener,
+    handler: &Value,
+    limit: Option<usize>,
+) -> Result<Value> {
+    for stream in listener.incoming().take(limit.unwrap_or(usize::MAX)) {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("start_tcp_server: {}", e);
+                continue;
+            }
+        };
+        let (status, body) = match read_request(&stream) {
+            Ok((data, request)) => {
+                match interpreter.call(handler.clone(), vec![data, Value::Str(request)], vec![]) {
+                    Ok(body) => ("200 OK", body.to_string()),
+                    Err(e) => {
+                        eprintln!("start_tcp_server: {}", e);
+                        ("500 Internal Server Error", String::new())
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("start_tcp_server: {}", e);
+                ("400 Bad Request", String::new())
+            }
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        // A client that went away isn't the server's problem.
+        let _ = stream.write_all(response.as_bytes());
+    }
+    Ok(Value::None)
+}
+
+/// The request line and headers of an HTTP request.
+fn read_request(stream: &TcpStream) -> std::io::Result<(Value, String)> {
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        request.push_str(&line);
+    }
+    let mut parts = request.split_whitespace();
+    let mut data = BTreeMap::new();
+    for key in ["method", "path"] {
+        let part = parts.next().unwrap_or_default();
+        data.insert(key.to_string(), Value::Str(part.to_string()));
+    }
+    Ok((Value::dict(data), request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Registry, serve};
+    use crate::interpreter::Interpreter;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+
+    fn run(src: &str) -> crate::Result<String> {
+        Interpreter::new().run_text(src, "<test>").map(|v| v.repr())
+    }
+
+    #[test]
+    fn signatures() {
+        let registry = Registry::standard();
+        assert_eq!(
+            registry.get("write_file").unwrap().to_string(),
+            "def write_file(path: Str, data: Str) -> None"
+        );
+        assert_eq!(
+            registry.get("print").unwrap().to_string(),
+            "def print(*values: Any) -> None"
+        );
+        let e = run("read_file(1)").unwrap_err();
+        assert!(
+            e.starts_with("in call to `read_file`: expected Str for `path` but got Int"),
+            "{}",
+            e
+        );
+        assert!(
+            run("len()")
+                .unwrap_err()
+                .contains("missing argument `value`")
+        );
+        assert_eq!(run("len(value=\"abc\")").unwrap(), "3");
+    }
+
+    #[test]
+    fn files_and_json() {
+        let path = std::env::temp_dir().join(format!("lyron-builtins-{}", std::process::id()));
+        let path = path.to_str().unwrap().replace('\\', "/");
+        let src = format!("write_file(\"{0}\", \"hi\")\nread_file(\"{0}\")", path);
+        assert_eq!(run(&src).unwrap(), "\"hi\"");
+
+        assert_eq!(
+            run("json_dumps([1, 2.5, \"a\", none, (true,)])").unwrap(),
+            "\"[1,2.5,\\\"a\\\",null,[true]]\""
+        );
+        assert_eq!(
+            run("json_parse(\"{\\\"a\\\": [1, {\\\"b\\\": null}]}\")").unwrap(),
+            "{\"a\": [1, {\"b\": none}]}"
+        );
+        assert_eq!(
+            run("use \"std:json\"\nJSON().loads(JSON().dumps(2 ** 62))").unwrap(),
+            "4611686018427387904"
+        );
+        assert!(run("json_dumps(print)").is_err());
+        assert!(run("json_parse(\"{\")").is_err());
+
+        let e = run("d = json_parse(\"{}\")\nsetattr(d, \"me\", [d])\njson_dumps(d)").unwrap_err();
+        assert!(
+            e.contains("cannot convert Dict to JSON, it contains itself"),
+            "{}",
+            e
+        );
+        // Shared values that don't contain themselves are fine.
+        assert_eq!(
+            run("d = json_parse(\"{}\")\njson_dumps([d, d])").unwrap(),
+            "\"[{},{}]\""
+        );
+    }
+
+    /// Send `request` to `address` and read the whole response.
+    fn fetch(address: SocketAddr, request: &[u8]) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn tcp_server() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .run_text(
+                "def handler(data: Dict, req: Str) -> Str \
+                 if data.path == \"/fail\" then 1 / 0 else \"you visited \" + data.path",
+                "<test>",
+            )
+            .unwrap();
+        let handler = interpreter.global("handler").unwrap().clone();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            [
+                &b"GET /cats HTTP/1.1\r\nHost: x\r\n\r\n"[..],
+                b"GET /\xff HTTP/1.1\r\n\r\n",
+                b"GET /fail HTTP/1.1\r\n\r\n",
+            ]
+            .map(|request| fetch(address, request))
+        });
+        // Bad requests and failing handlers don't stop the server.
+        serve(&mut interpreter, &listener, &handler, Some(3)).unwrap();
+        let [ok, bad, failed] = client.join().unwrap();
+        assert!(ok.starts_with("HTTP/1.1 200 OK\r\n"), "{}", ok);
+        assert!(ok.ends_with("\r\n\r\nyou visited /cats"), "{}", ok);
+        assert!(bad.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", bad);
+        assert!(
+            failed.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+            "{}",
+            failed
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! Conversions between Rust types and Lyron values.
+
+use crate::Result;
+use crate::builtins::NativeFn;
+use crate::interpreter::Value;
+
+use num_bigint::BigInt;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+/// A Rust value that can be passed to Lyron.
+pub trait IntoValue {
+    /// The Lyron type of the values, as written in signatures.
+    const TYPE: &'static str = "Any";
+
+    fn into_value(self) -> Value;
+}
+
+/// A Rust value that can be taken from Lyron.
+pub trait FromValue: Sized {
+    /// The Lyron type accepted, as written in signatures.
+    const TYPE: &'static str = "Any";
+
+    fn from_value(value: Value) -> Result<Self>;
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for () {
+    const TYPE: &'static str = "None";
+
+    fn into_value(self) -> Value {
+        Value::None
+    }
+}
+
+impl FromValue for () {
+    const TYPE: &'static str = "None";
+
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::None => Ok(()),
+            v => Err(mismatch("None", &v)),
+        }
+    }
+}
+
+impl IntoValue for bool {
+    const TYPE: &'static str = "Bool";
+
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromValue for bool {
+    const TYPE: &'static str = "Bool";
+
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            v => Err(mismatch("Bool", &v)),
+        }
+    }
+}
+
+impl IntoValue for i64 {
+    const TYPE: &'static str = "Int";
+
+    fn into_value(self) -> Value {
+        Value::Integer(self)
+    }
+}
+
+impl FromValue for i64 {
+    const TYPE: &'static str = "Int";
+
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Integer(i) => Ok(i),
+            Value::BigInteger(i) => Err(format!("{} does not fit in 64 bits", i)),
+            v => Err(mismatch("Int", &v)),
+        }
+    }
+}
+
+impl IntoValue for i32 {
+    const TYPE: &'static str = "Int";
+
+    fn into_value(self) -> Value {
+        Value::Integer(self.into())
+    }
+}
+
+impl FromValue for i32 {
+    const TYPE: &'static str = "Int";
+
+    fn from_value(value: Value) -> Result<Self> {
+        let i = i64::from_value(value)?;
+        i32::try_from(i).map_err(|_| format!("{} does not fit in 32 bits", i))
+    }
+}
+
+impl IntoValue for BigInt {
+    const TYPE: &'static str = "Int";
+
+    fn into_value(self) -> Value {
+        Value::from_bigint(self)
+    }
+}
+
+impl FromValue for BigInt {
+    const TYPE: &'static str = "Int";
+
+    fn from_value(value: Value) -> Result<Self> {
+        value.as_int().ok_or_else(|| mismatch("Int", &value))
+    }
+}
+
+impl IntoValue for f64 {
+    const TYPE: &'static str = "Double";
+
+    fn into_value(self) -> Value {
+        Value::Double(self)
+    }
+}
+
+/// Ints are taken too, like Lyron's arithmetic does.
+impl FromValue for f64 {
+    const TYPE: &'static str = "Double";
+
+    fn from_value(value: Value) -> Result<Self> {
+        value.as_double().ok_or_else(|| mismatch("Double", &value))
+    }
+}
+
+impl IntoValue for String {
+    const TYPE: &'static str = "Str";
+
+    fn into_value(self) -> Value {
+        Value::Str(self)
+    }
+}
+
+impl IntoValue for &str {
+    const TYPE: &'static str = "Str";
+
+    fn into_value(self) -> Value {
+        Value::Str(self.to_string())
+    }
+}
+
+impl FromValue for String {
+    const TYPE: &'static str = "Str";
+
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Str(s) => Ok(s),
+            v => Err(mismatch("Str", &v)),
+        }
+    }
+}
+
+/// `None` is Lyron's `none`.
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        self.map_or(Value::None, T::into_value)
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::None => Ok(None),
+            v => T::from_value(v).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    const TYPE: &'static str = "Array";
+
+    fn into_value(self) -> Value {
+        Value::array(self.into_iter().map(T::into_value).collect())
+    }
+}
+
+/// Tuples are taken too.
+impl<T: FromValue> FromValue for Vec<T> {
+    const TYPE: &'static str = "Array";
+
+    fn from_value(value: Value) -> Result<Self> {
+        let values = match &value {
+            Value::Array(a) => a.borrow().clone(),
+            Value::Tuple(t) => t.to_vec(),
+            v => return Err(mismatch("Array", v)),
+        };
+        values.into_iter().map(T::from_value).collect()
+    }
+}
+
+impl<T: IntoValue> IntoValue for BTreeMap<String, T> {
+    const TYPE: &'static str = "Dict";
+
+    fn into_value(self) -> Value {
+        Value::dict(self.into_iter().map(|(k, v)| (k, v.into_value())).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for BTreeMap<String, T> {
+    const TYPE: &'static str = "Dict";
+
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Dict(d) => d
+                .borrow()
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), T::from_value(v.clone())?)))
+                .collect(),
+            v => Err(mismatch("Dict", &v)),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    const TYPE: &'static str = "Dict";
+
+    fn into_value(self) -> Value {
+        Value::dict(self.into_iter().map(|(k, v)| (k, v.into_value())).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    const TYPE: &'static str = "Dict";
+
+    fn from_value(value: Value) -> Result<Self> {
+        BTreeMap::from_value(value).map(|m| m.into_iter().collect())
+    }
+}
+
+fn mismatch(expected: &str, got: &Value) -> String {
+    format!("expected {} but got {}", expected, got.type_name())
+}
+
+/// The arguments of a call from Rust: a tuple of [`IntoValue`]s, or the values
+/// themselves.
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<Value>;
+}
+
+impl IntoArgs for Vec<Value> {
+    fn into_args(self) -> Vec<Value> {
+        self
+    }
+}
+
+/// What a Rust function registered with Lyron returns: a value, or a
+/// `Result` whose error becomes a Lyron error.
+pub trait IntoResult {
+    const TYPE: &'static str;
+
+    fn into_result(self) -> Result<Value>;
+}
+
+impl<T: IntoValue> IntoResult for T {
+    const TYPE: &'static str = T::TYPE;
+
+    fn into_result(self) -> Result<Value> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue> IntoResult for Result<T> {
+    const TYPE: &'static str = T::TYPE;
+
+    fn into_result(self) -> Result<Value> {
+        self.map(T::into_value)
+    }
+}
+
+/// A Rust closure that can be registered as a builtin. It's implemented for
+/// closures of up to six [`FromValue`] parameters, and `Args` is the tuple of
+/// their types.
+pub trait NativeFunction<Args> {
+    /// Lyron type of each parameter.
+    fn param_types() -> Vec<&'static str>;
+
+    fn return_type() -> &'static str;
+
+    fn into_native(self) -> NativeFn;
+}
+
+macro_rules! tuples {
+    ($($arg:ident),*) => {
+        impl<$($arg: IntoValue),*> IntoArgs for ($($arg,)*) {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Vec<Value> {
+                let ($($arg,)*) = self;
+                vec![$($arg.into_value()),*]
+            }
+        }
+
+        impl<F, R, $($arg),*> NativeFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoResult,
+            $($arg: FromValue),*
+        {
+            fn param_types() -> Vec<&'static str> {
+                vec![$($arg::TYPE),*]
+            }
+
+            fn return_type() -> &'static str {
+                R::TYPE
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_native(self) -> NativeFn {
+                Rc::new(move |_, args| {
+                    let mut args = args.into_iter();
+                    $(
+                        let $arg = $arg::from_value(
+                            args.next().expect("arguments are bound before the call"),
+                        )?;
+                    )*
+                    self($($arg),*).into_result()
+                })
+            }
+        }
+    };
+}
+
+tuples!();
+tuples!(A);
+tuples!(A, B);
+tuples!(A, B, C);
+tuples!(A, B, C, D);
+tuples!(A, B, C, D, E);
+tuples!(A, B, C, D, E, G);
@@ -0,0 +1,487 @@
+//! Running Lyron from Rust programs.
+//!
+//! An [`Engine`] evaluates source text and files, keeping their definitions
+//! and globals, so a host can load a script once and then call into it:
+//!
+//! ```
+//! use lyronc::Engine;
+//!
+//! let mut engine = Engine::new();
+//! engine.register_fn("twice", |x: i64| x * 2);
+//! engine.eval("def add(a: Int, b: Int) -> Int twice(a) + b").unwrap();
+//! let sum: i64 = engine.call("add", (20, 2)).unwrap();
+//! assert_eq!(sum, 42);
+//! ```
+//!
+//! Values cross between the two languages through [`IntoValue`] and
+//! [`FromValue`], and every failure is an [`Error`] telling which stage it
+//! happened in and where.
+
+mod convert;
+
+pub use convert::{FromValue, IntoArgs, IntoResult, IntoValue, NativeFunction};
+
+use crate::builtins::Builtin;
+use crate::interpreter::trace::Frame;
+use crate::interpreter::{Interpreter, StackTrace, Value};
+use crate::lexer::Lexer;
+use crate::modules::ModuleLoader;
+use crate::optimizer;
+use crate::parser::{AstNode, NodePosition, Parser};
+use crate::resolver::Resolver;
+use crate::sandbox::{Capability, Limits};
+
+use serde_json::json;
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// The stage of running a program that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Reading a source file.
+    Io,
+    Lexing,
+    Parsing,
+    /// Loading the modules a program uses.
+    Loading,
+    Resolving,
+    Running,
+    /// Taking a Rust value out of a Lyron one.
+    Converting,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorKind::Io => "IO",
+            ErrorKind::Lexing => "Lexing",
+            ErrorKind::Parsing => "Parsing",
+            ErrorKind::Loading => "Loading",
+            ErrorKind::Resolving => "Resolving",
+            ErrorKind::Running => "Running",
+            ErrorKind::Converting => "Converting",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// What went wrong, without the position.
+    pub message: String,
+    /// Where it went wrong, when that's known.
+    pub position: Option<NodePosition>,
+    /// The calls that were being made, outermost first, for errors while
+    /// running.
+    pub trace: Vec<Frame>,
+    /// The error as the command line prints it.
+    report: String,
+}
+
+impl Error {
+    fn new(kind: ErrorKind, message: String) -> Self {
+        Error {
+            kind,
+            report: message.clone(),
+            message,
+            position: None,
+            trace: vec![],
+        }
+    }
+
+    fn at(mut self, message: &str, position: NodePosition) -> Self {
+        self.message = message.to_string();
+        self.position = Some(position);
+        self
+    }
+
+    /// Report the error with `trace`, at its innermost frame.
+    fn traced(mut self, trace: &StackTrace) -> Self {
+        if let Some(frame) = trace.frames.last() {
+            self.position = Some(NodePosition {
+                pos: frame.column,
+                line_no: frame.line,
+                file: frame.file.clone(),
+            });
+            self.report = trace.render();
+        }
+        self.message.clone_from(&trace.message);
+        self.trace.clone_from(&trace.frames);
+        self
+    }
+
+    /// The error as JSON, for tools: its kind, message, position and the
+    /// frames of its trace, outermost first.
+    pub fn to_json(&self) -> String {
+        let position = self
+            .position
+            .as_ref()
+            .map(|p| json!({ "file": p.file, "line": p.line_no, "column": p.pos }));
+        let frames = self.trace.iter().map(Frame::to_json).collect::<Vec<_>>();
+        json!({
+            "kind": self.kind.to_string(),
+            "message": self.message,
+            "position": position,
+            "trace": frames,
+        })
+        .to_string()
+    }
+}
+
+/// The full report, with the position and any source snippet.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.report)
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// File name given to the text passed to [`Engine::eval`].
+const EVAL_FILE: &str = "<eval>";
+
+pub struct Engine {
+    interpreter: Interpreter,
+    /// Run [`optimizer::optimize`] on programs before running them.
+    optimize: bool,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            interpreter: Interpreter::new(),
+            optimize: true,
+        }
+    }
+
+    /// Send the output of `print` to `output` instead of stdout.
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.interpreter = self.interpreter.with_output(output);
+        self
+    }
+
+    /// Load modules with `loader`.
+    pub fn with_loader(mut self, loader: ModuleLoader) -> Self {
+        self.interpreter = self.interpreter.with_loader(loader);
+        self
+    }
+
+    /// Collect garbage every `threshold` allocations of arrays, dicts, objects
+    /// and tuples.
+    pub fn with_gc_threshold(mut self, threshold: usize) -> Self {
+        self.interpreter = self.interpreter.with_gc_threshold(threshold);
+        self
+    }
+
+    /// Stop runs, and calls into the code run, that use up more than `limits`.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.interpreter = self.interpreter.with_limits(limits);
+        self
+    }
+
+    /// Sandbox the engine: only the builtins needing no capability, or one
+    /// in `capabilities`, can be called.
+    pub fn with_capabilities(mut self, capabilities: impl IntoIterator<Item = Capability>) -> Self {
+        self.interpreter = self.interpreter.with_capabilities(capabilities);
+        self
+    }
+
+    /// Optimize programs and the modules they use before running them, which
+    /// is the default, or not.
+    pub fn with_optimizations(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self.interpreter.loader_mut().set_optimizations(optimize);
+        self
+    }
+
+    /// Run `source`, returning the value of its last expression. Its `use`
+    /// paths are relative to the working directory.
+    pub fn eval(&mut self, source: &str) -> Result<Value> {
+        let ast = parse(source, EVAL_FILE)?;
+        self.interpreter.add_source(EVAL_FILE, source);
+        self.run(&ast, EVAL_FILE)
+    }
+
+    /// Run the file at `path`, returning the value of its last expression.
+    pub fn eval_file(&mut self, path: impl AsRef<Path>) -> Result<Value> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|e| Error::new(ErrorKind::Io, format!("{}: {}", path.display(), e)))?;
+        let file = path.to_string_lossy();
+        let ast = parse(&source, &file)?;
+        self.run(&ast, &file)
+    }
+
+    /// Load the modules `ast` uses, check it and run it. `ast` is the program
+    /// in `file`.
+    pub fn run(&mut self, ast: &[(AstNode, NodePosition)], file: &str) -> Result<Value> {
+        self.interpreter
+            .loader_mut()
+            .load_imports(ast, Path::new(file))
+            .map_err(|e| Error::new(ErrorKind::Loading, e))?;
+
+        let mut resolver = Resolver::with_builtins(self.interpreter.builtins());
+        if let Err(e) = resolver.resolve_program(ast) {
+            let mut error = Error::new(ErrorKind::Resolving, e);
+            if let Some((message, position)) = resolver.check_program(ast).into_iter().next() {
+                error = error.at(&message, position);
+            }
+            return Err(error);
+        }
+
+        let ast = if self.optimize {
+            let mut ast = ast.to_vec();
+            optimizer::optimize(&mut ast);
+            Cow::Owned(ast)
+        } else {
+            Cow::Borrowed(ast)
+        };
+        self.interpreter.run_program(&ast).map_err(|e| {
+            let error = Error::new(ErrorKind::Running, e);
+            let error = match self.interpreter.last_error() {
+                Some((message, position)) => error.at(message, position.clone()),
+                None => error,
+            };
+            match self.interpreter.last_trace() {
+                Some(trace) => error.traced(trace),
+                None => error,
+            }
+        })
+    }
+
+    /// Call the function or class `name` defined by the code run so far, or
+    /// the builtin `name`.
+    pub fn call<R: FromValue>(&mut self, name: &str, args: impl IntoArgs) -> Result<R> {
+        let callee = match self.interpreter.global(name) {
+            Some(callee) => callee.clone(),
+            None if self.interpreter.builtins().get(name).is_some() => {
+                Value::Builtin(name.to_string())
+            }
+            None => {
+                let message = format!("undefined function `{}`", name);
+                return Err(Error::new(ErrorKind::Running, message));
+            }
+        };
+        let value = self
+            .interpreter
+            .call(callee, args.into_args(), vec![])
+            .map_err(|e| {
+                let error = Error::new(ErrorKind::Running, e);
+                match self.interpreter.last_trace() {
+                    Some(trace) => error.traced(trace),
+                    None => error,
+                }
+            })?;
+        convert(value, name)
+    }
+
+    /// The global `name` of the code run so far.
+    pub fn get<T: FromValue>(&self, name: &str) -> Result<T> {
+        match self.interpreter.global(name) {
+            Some(value) => convert(value.clone(), name),
+            None => {
+                let message = format!("undefined variable `{}`", name);
+                Err(Error::new(ErrorKind::Running, message))
+            }
+        }
+    }
+
+    /// Set the global `name`, for the code run from now on.
+    pub fn set(&mut self, name: &str, value: impl IntoValue) {
+        self.interpreter.set_global(name, value.into_value());
+    }
+
+    /// Make the Rust function `f` callable as `name`. Its parameters and
+    /// result are converted with [`FromValue`] and [`IntoValue`]; returning
+    /// `Err(message)` raises an error in the calling Lyron code.
+    pub fn register_fn<Args, F: NativeFunction<Args>>(&mut self, name: &str, f: F) {
+        let params = F::param_types()
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| (format!("arg{}", i + 1), t.to_string()))
+            .collect();
+        self.register(Builtin {
+            name: name.to_string(),
+            params,
+            variadic: false,
+            return_type: F::return_type().to_string(),
+            capability: None,
+            run: f.into_native(),
+        });
+    }
+
+    /// Make `builtin` callable, for functions taking the interpreter or any
+    /// number of arguments.
+    pub fn register(&mut self, builtin: Builtin) {
+        self.interpreter.register(builtin);
+    }
+
+    pub fn interpreter_mut(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lex and parse `source`, read from `file`.
+fn parse(source: &str, file: &str) -> Result<Vec<(AstNode, NodePosition)>> {
+    let tokens = Lexer::from_text(source, file)
+        .tokenize()
+        .map_err(|errors| Error::new(ErrorKind::Lexing, errors.join("\n")))?;
+    let mut parser = Parser::new(tokens.into_iter().peekable(), file).with_source(source);
+    parser.parse_program().map_err(|e| {
+        let error = Error::new(ErrorKind::Parsing, e);
+        match parser.last_error() {
+            Some((message, (line_no, pos))) => error.at(
+                message,
+                NodePosition {
+                    pos: *pos,
+                    line_no: *line_no,
+                    file: file.to_string(),
+                },
+            ),
+            None => error,
+        }
+    })
+}
+
+fn convert<T: FromValue>(value: Value, name: &str) -> Result<T> {
+    T::from_value(value)
+        .map_err(|e| Error::new(ErrorKind::Converting, format!("`{}`: {}", name, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Engine, ErrorKind};
+    use crate::interpreter::Value;
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    #[test]
+    fn eval_and_call() {
+        let mut engine = Engine::new();
+        let src = "
+            limit = 10
+            def clamp(x: Double) -> Double if x > limit then limit else x
+            def pair(a: Any, b: Any) -> Any (a, b)
+        ";
+        engine.eval(src).unwrap();
+
+        assert_eq!(engine.call::<f64>("clamp", (2.5,)).unwrap(), 2.5);
+        assert_eq!(engine.call::<i64>("clamp", (20,)).unwrap(), 10);
+        let pair: Vec<String> = engine.call("pair", ("a", "b".to_string())).unwrap();
+        assert_eq!(pair, ["a", "b"]);
+        assert_eq!(engine.call::<i64>("len", (vec![1, 2, 3],)).unwrap(), 3);
+
+        engine.set("limit", 1);
+        assert_eq!(engine.get::<i64>("limit").unwrap(), 1);
+        assert_eq!(engine.call::<f64>("clamp", (2.5,)).unwrap(), 1.0);
+        assert_eq!(engine.eval("clamp(0.5)").unwrap().repr(), "0.5");
+
+        let e = engine.call::<i64>("nope", ()).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Running);
+        let e = engine.call::<i64>("pair", (1, 2)).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Converting);
+        assert_eq!(e.message, "`pair`: expected Int but got Tuple");
+    }
+
+    #[test]
+    fn conversions() {
+        let mut engine = Engine::new();
+        let dict = BTreeMap::from([("a".to_string(), vec![Some(1), None])]);
+        engine.set("d", dict.clone());
+        assert_eq!(engine.eval("d").unwrap().repr(), "{\"a\": [1, none]}");
+        assert_eq!(
+            engine
+                .get::<BTreeMap<String, Vec<Option<i64>>>>("d")
+                .unwrap(),
+            dict
+        );
+        assert_eq!(engine.eval("x = 2 ** 70").unwrap(), Value::None);
+        assert!(engine.get::<i64>("x").is_err());
+        assert_eq!(
+            engine.get::<num_bigint::BigInt>("x").unwrap().to_string(),
+            "1180591620717411303424"
+        );
+        assert!(engine.get::<bool>("d").is_err());
+    }
+
+    #[test]
+    fn native_functions() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let mut engine = Engine::new();
+        engine.register_fn("count", move || counter.set(counter.get() + 1));
+        engine.register_fn("greet", |name: String, times: i64| {
+            name.repeat(times as usize)
+        });
+        engine.register_fn("root", |x: f64| {
+            if x < 0.0 {
+                Err(format!("{} has no square root", x))
+            } else {
+                Ok(x.sqrt())
+            }
+        });
+
+        engine.eval("count()\ncount()").unwrap();
+        assert_eq!(calls.get(), 2);
+        assert_eq!(engine.eval("greet(\"ab\", 2)").unwrap().repr(), "\"abab\"");
+        assert_eq!(engine.eval("root(4)").unwrap().repr(), "2.0");
+
+        // Signatures are checked like those of the standard builtins.
+        let builtins = engine.interpreter_mut().builtins();
+        assert_eq!(
+            builtins.get("greet").unwrap().to_string(),
+            "def greet(arg1: Str, arg2: Int) -> Str"
+        );
+        let e = engine.eval("greet(\"ab\")").unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Resolving);
+        let e = engine.eval("greet(1, 2)").unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Running);
+        assert!(e.message.contains("expected Str for `arg1`"), "{}", e);
+
+        let e = engine.eval("x = 1\nroot(-1)").unwrap_err();
+        assert_eq!(e.message, "-1 has no square root");
+        assert_eq!(e.position.unwrap().line_no, 2);
+    }
+
+    #[test]
+    fn errors() {
+        let mut engine = Engine::new().with_output(std::io::sink());
+        let e = engine.eval("x = \"open").unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Lexing);
+        let e = engine.eval("def f(").unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Parsing);
+        assert!(e.position.is_some());
+        let e = engine.eval("use \"nowhere/nothing\"").unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Loading);
+        let e = engine.eval("print(1)\n\n1 / 0").unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Running);
+        assert_eq!(e.message, "division by zero");
+        assert!(e.to_string().contains("at 3:"), "{}", e);
+        let position = e.position.unwrap();
+        assert_eq!((position.line_no, position.file.as_str()), (3, "<eval>"));
+        engine
+            .eval("def half(x: Any) -> Any do\nx / 0\nend")
+            .unwrap();
+        let e = engine.call::<Value>("half", (1,)).unwrap_err();
+        assert_eq!(e.trace[0].function.as_deref(), Some("half"));
+        let json: serde_json::Value = serde_json::from_str(&e.to_json()).unwrap();
+        assert_eq!(json["kind"], "Running");
+        assert_eq!(json["position"]["line"], 2);
+        assert_eq!(json["trace"][0]["function"], "half");
+        let e = engine.eval_file("/nowhere/main.lyr").unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Io);
+    }
+}
@@ -0,0 +1,284 @@
+//! Reprints source files in the canonical style.
+//!
+//! The formatter works on the lossless tree of [`crate::syntax`], so comments are
+//! kept and line breaks chosen by the author stay where they are (runs of blank
+//! lines shrink to one). Everything else is normalized:
+//!
+//! - four spaces of indentation per `do`/`end` block, class body and open bracket,
+//!   plus one level for an expression continued on the next line;
+//! - one space around binary operators, `=`, `->` and after `,` and `:`;
+//! - no space inside brackets, before a call's arguments, after a unary operator
+//!   or around the `=` of a keyword argument;
+//! - no trailing whitespace and a single newline at the end of the file.
+
+use crate::Result;
+use crate::format_error;
+use crate::syntax::{SyntaxKind, SyntaxNode, SyntaxToken, parse};
+
+use rowan::TextSize;
+use std::collections::HashMap;
+
+use SyntaxKind as K;
+
+const INDENT: &str = "    ";
+
+/// Format `text`, read from `file`. Fails with the syntax errors of the source,
+/// since text the parser doesn't understand can't be laid out safely.
+pub fn format(text: &str, file: &str) -> Result<String> {
+    let parse = parse(text);
+    if !parse.errors.is_empty() {
+        return Err(parse
+            .errors
+            .iter()
+            .map(|e| {
+                let (line, line_no, pos) = locate(text, e.range.start().into());
+                format_error(line, &e.message, line_no, pos, file)
+            })
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+    Ok(Formatter::default().run(&parse.syntax()))
+}
+
+/// The source line holding byte `offset`, its 1-based number and the column.
+fn locate(text: &str, offset: usize) -> (&str, i32, i32) {
+    let start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = text[start..].lines().next().unwrap_or("");
+    let line_no = text[..offset].matches('\n').count() + 1;
+    let pos = text[start..offset].chars().count();
+    (line, line_no as i32, pos as i32)
+}
+
+#[derive(Default)]
+struct Formatter {
+    out: String,
+    /// The last token or comment written.
+    prev: Option<SyntaxToken>,
+    /// Newlines seen in the source since `prev`.
+    newlines: usize,
+    /// Indentation level of the line being written.
+    line_indent: usize,
+    /// For each open block: the level of its contents and of the line opening it.
+    blocks: Vec<(usize, usize)>,
+    /// Indentation level of the line each token was written on, by source offset.
+    lines: HashMap<TextSize, usize>,
+}
+
+impl Formatter {
+    fn run(mut self, root: &SyntaxNode) -> String {
+        self.blocks.push((0, 0));
+        for t in root
+            .descendants_with_tokens()
+            .filter_map(|e| e.into_token())
+        {
+            match t.kind() {
+                K::Newline => self.newlines += t.text().matches('\n').count(),
+                K::Whitespace => {}
+                _ => self.token(t),
+            }
+        }
+        let mut out = self.out.trim_end().to_string();
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+
+    fn token(&mut self, t: SyntaxToken) {
+        let closer = is_closer(&t);
+        let closed = if closer { self.blocks.pop() } else { None };
+
+        if self.prev.is_none() {
+            self.line_indent = 0;
+        } else if self.newlines > 0 {
+            // Blank lines are dropped right inside brackets and blocks.
+            let max = if closer || self.prev.as_ref().is_some_and(is_opener) {
+                1
+            } else {
+                2
+            };
+            self.out
+                .truncate(self.out.trim_end_matches([' ', '\t']).len());
+            self.out.push_str(&"\n".repeat(self.newlines.min(max)));
+            self.line_indent = match closed {
+                Some((_, opener_line)) => opener_line,
+                None => self.indent_of(&t),
+            };
+            self.out.push_str(&INDENT.repeat(self.line_indent));
+        } else if self.prev.as_ref().is_some_and(|p| space_between(p, &t)) {
+            self.out.push(' ');
+        }
+
+        if t.kind() == K::Comment {
+            self.out.push_str(t.text().trim_end());
+        } else {
+            self.out.push_str(t.text());
+            self.lines.insert(t.text_range().start(), self.line_indent);
+        }
+        if is_opener(&t) {
+            self.blocks.push((self.line_indent + 1, self.line_indent));
+        }
+        self.newlines = 0;
+        self.prev = Some(t);
+    }
+
+    /// Indentation level of `t` when it starts a line.
+    fn indent_of(&self, t: &SyntaxToken) -> usize {
+        let block = self.blocks.last().map_or(0, |b| b.0);
+        if t.kind() == K::Comment {
+            return block;
+        }
+        // Find the node `t` continues: the innermost one it doesn't start.
+        let mut node = t.parent();
+        while let Some(n) = node {
+            if is_block(n.kind()) {
+                return block;
+            }
+            if n.first_token().as_ref() != Some(t) {
+                let start = self.lines.get(&n.text_range().start()).copied();
+                let start = start.unwrap_or(block);
+                // `else` lines up with its `if`.
+                return if t.kind() == K::ElseKw {
+                    start
+                } else {
+                    start + 1
+                };
+            }
+            node = n.parent();
+        }
+        block
+    }
+}
+
+/// Nodes whose children each start a new statement or list item.
+fn is_block(kind: SyntaxKind) -> bool {
+    matches!(
+        kind,
+        K::SourceFile
+            | K::DoExpr
+            | K::ClassDef
+            | K::ParamList
+            | K::ArgList
+            | K::ParenExpr
+            | K::TupleExpr
+            | K::ArrayExpr
+            | K::DictPattern
+    )
+}
+
+fn parent_kind(t: &SyntaxToken) -> Option<SyntaxKind> {
+    t.parent().map(|n| n.kind())
+}
+
+fn is_opener(t: &SyntaxToken) -> bool {
+    matches!(t.kind(), K::LParen | K::LBrack | K::LBrace)
+        || (t.kind() == K::DoKw && parent_kind(t) == Some(K::DoExpr))
+}
+
+fn is_closer(t: &SyntaxToken) -> bool {
+    matches!(t.kind(), K::RParen | K::RBrack | K::RBrace)
+        || (t.kind() == K::EndKw && parent_kind(t) == Some(K::DoExpr))
+}
+
+/// Whether a space goes between `prev` and `t` on the same line.
+fn space_between(prev: &SyntaxToken, t: &SyntaxToken) -> bool {
+    let (p, k) = (prev.kind(), t.kind());
+    if k == K::Comment {
+        return true;
+    }
+    // `{a, b} = d` and `use {a, b} from "m"` hug their braces.
+    let in_dict = |t: &SyntaxToken| matches!(parent_kind(t), Some(K::DictPattern | K::UseExpr));
+
+    match p {
+        K::LParen | K::LBrack | K::Dot => return false,
+        K::LBrace if in_dict(prev) => return false,
+        // `*rest`, `**opts`, `-x`, `!x`
+        K::Mul | K::Pow | K::Plus | K::Minus | K::Not
+            if matches!(parent_kind(prev), Some(K::Param | K::PrefixExpr)) =>
+        {
+            return false;
+        }
+        _ => {}
+    }
+    match k {
+        K::RParen | K::RBrack | K::Comma | K::Semicolon | K::Colon | K::Dot => false,
+        K::RBrace if in_dict(t) => false,
+        K::LParen if matches!(parent_kind(t), Some(K::ArgList | K::ParamList)) => false,
+        K::Assign if parent_kind(t) == Some(K::KeywordArg) => false,
+        _ => !(p == K::Assign && parent_kind(prev) == Some(K::KeywordArg)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+
+    fn fmt(src: &str) -> String {
+        format(src, "<test>").unwrap()
+    }
+
+    #[test]
+    fn spacing() {
+        assert_eq!(
+            fmt("def  f(a:i32,b : Str=\"x\",*rest:Any)->Any a+b*-c"),
+            "def f(a: i32, b: Str = \"x\", *rest: Any) -> Any a + b * -c\n"
+        );
+        assert_eq!(
+            fmt("f( 1 ,b = 2 )  ;x.y( [1,2] )"),
+            "f(1, b=2); x.y([1, 2])\n"
+        );
+        assert_eq!(fmt("let {a,b}=d\nc=:( 1, )"), "let {a, b} = d\nc =: (1,)\n");
+        assert_eq!(
+            fmt("use{JSON,dumps  as d}from \"std:json\"\npub   def f()->Any 1"),
+            "use {JSON, dumps as d} from \"std:json\"\npub def f() -> Any 1\n"
+        );
+        assert_eq!(
+            fmt("class A{def A(self:Self)->A self}"),
+            "class A { def A(self: Self) -> A self }\n"
+        );
+    }
+
+    #[test]
+    fn indentation() {
+        let src = "class File{\n\n\tdef File(\n\t\tself: File,\n\t\t# permissions: Str\n\t)->None\n\t\tsetattr(self, \"path\", path)   \n\n\n\n  def read(self: Self)->Str do\n   if a then do\n  print(1) # one\n end else\n  none\nend\n\n}\n\n\n";
+        let expected = "class File {\n    def File(\n        self: File,\n        # permissions: Str\n    ) -> None\n        setattr(self, \"path\", path)\n\n    def read(self: Self) -> Str do\n        if a then do\n            print(1) # one\n        end else\n            none\n    end\n}\n";
+        assert_eq!(fmt(src), expected);
+        assert_eq!(fmt(expected), expected);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(format("def f(", "<test>").is_err());
+    }
+
+    /// Formatting keeps every token and formatting again changes nothing.
+    #[test]
+    fn idempotent_over_examples() {
+        let significant = |src: &str| {
+            crate::syntax::parse(src)
+                .syntax()
+                .descendants_with_tokens()
+                .filter_map(|e| e.into_token())
+                .filter(|t| {
+                    !matches!(
+                        t.kind(),
+                        crate::syntax::SyntaxKind::Whitespace | crate::syntax::SyntaxKind::Newline
+                    )
+                })
+                .map(|t| t.text().trim_end().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        for (path, src) in crate::example_sources() {
+            let once = format(&src, "<test>");
+            // The one example of an error doesn't parse.
+            if path.ends_with("examples/err.lyr") {
+                assert!(once.is_err(), "{:?}", path);
+                continue;
+            }
+            let once = once.unwrap_or_else(|e| panic!("{:?}: {}", path, e));
+            assert_eq!(significant(&src), significant(&once), "{:?}", path);
+            assert_eq!(format(&once, "<test>").unwrap(), once, "{:?}", path);
+        }
+    }
+}
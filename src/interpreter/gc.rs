@@ -0,0 +1,334 @@
+//! A mark-sweep collector for reference cycles.
+//!
+//! Arrays, dicts, objects and tuples are reference counted, which frees them
+//! as soon as they are unused unless they refer to each other in a cycle,
+//! like an object stored in its own attributes. Every one of them is
+//! registered here when it's made, and [`collect`] frees the cycles nothing
+//! else refers to.
+//!
+//! The roots are the values the interpreter hands in (globals, call frames
+//! and module exports) plus every container referenced from outside the
+//! registered ones, e.g. by a value Rust code is holding. Those are found by
+//! comparing each container's reference count with the references the other
+//! containers account for, so a collection is safe at any point. Containers
+//! not reachable from a root are emptied, which breaks their cycles and lets
+//! reference counting free them.
+//!
+//! The heap is per thread, like the values in it.
+
+use super::value::{Object, Value};
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::{Rc, Weak};
+
+/// Allocations between collections, unless configured otherwise.
+pub const DEFAULT_THRESHOLD: usize = 10_000;
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap::default());
+}
+
+#[derive(Default)]
+struct Heap {
+    tracked: Vec<Tracked>,
+    /// Allocations since the last collection.
+    pending: usize,
+    stats: Stats,
+}
+
+/// Counters of the collector, as shown by `gc_stats()`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub collections: usize,
+    /// Containers allocated so far.
+    pub allocated: usize,
+    /// Containers the collector freed, over all collections.
+    pub freed: usize,
+    /// Containers alive after the last collection.
+    pub live: usize,
+}
+
+enum Tracked {
+    Array(Weak<RefCell<Vec<Value>>>),
+    Dict(Weak<RefCell<BTreeMap<String, Value>>>),
+    Object(Weak<RefCell<Object>>),
+    Tuple(Weak<Vec<Value>>),
+}
+
+/// A live container during a collection.
+enum Node {
+    Array(Rc<RefCell<Vec<Value>>>),
+    Dict(Rc<RefCell<BTreeMap<String, Value>>>),
+    Object(Rc<RefCell<Object>>),
+    Tuple(Rc<Vec<Value>>),
+}
+
+impl Tracked {
+    fn upgrade(&self) -> Option<Node> {
+        match self {
+            Tracked::Array(w) => w.upgrade().map(Node::Array),
+            Tracked::Dict(w) => w.upgrade().map(Node::Dict),
+            Tracked::Object(w) => w.upgrade().map(Node::Object),
+            Tracked::Tuple(w) => w.upgrade().map(Node::Tuple),
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        match self {
+            Tracked::Array(w) => w.strong_count() > 0,
+            Tracked::Dict(w) => w.strong_count() > 0,
+            Tracked::Object(w) => w.strong_count() > 0,
+            Tracked::Tuple(w) => w.strong_count() > 0,
+        }
+    }
+}
+
+impl Node {
+    fn address(&self) -> usize {
+        match self {
+            Node::Array(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::Dict(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::Object(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Node::Tuple(rc) => Rc::as_ptr(rc) as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Array(rc) => Rc::strong_count(rc),
+            Node::Dict(rc) => Rc::strong_count(rc),
+            Node::Object(rc) => Rc::strong_count(rc),
+            Node::Tuple(rc) => Rc::strong_count(rc),
+        }
+    }
+
+    /// The values directly inside, or None if the container is borrowed for
+    /// writing and can't be looked into.
+    fn children(&self) -> Option<Vec<Value>> {
+        Some(match self {
+            Node::Array(rc) => rc.try_borrow().ok()?.clone(),
+            Node::Dict(rc) => rc.try_borrow().ok()?.values().cloned().collect(),
+            Node::Object(rc) => rc.try_borrow().ok()?.attrs.values().cloned().collect(),
+            Node::Tuple(rc) => rc.to_vec(),
+        })
+    }
+
+    /// Take the values out, to be dropped once no container is borrowed.
+    /// Tuples can't be emptied, but every cycle has a mutable container in
+    /// it, since a tuple can only refer to values made before it.
+    fn empty(&self, garbage: &mut Vec<Value>) {
+        match self {
+            Node::Array(rc) => {
+                if let Ok(mut values) = rc.try_borrow_mut() {
+                    garbage.append(&mut values);
+                }
+            }
+            Node::Dict(rc) => {
+                if let Ok(mut entries) = rc.try_borrow_mut() {
+                    garbage.extend(std::mem::take(&mut *entries).into_values());
+                }
+            }
+            Node::Object(rc) => {
+                if let Ok(mut object) = rc.try_borrow_mut() {
+                    garbage.extend(std::mem::take(&mut object.attrs).into_values());
+                }
+            }
+            Node::Tuple(_) => {}
+        }
+    }
+}
+
+/// Address of the container `value` is, if it is one.
+fn address(value: &Value) -> Option<usize> {
+    match value {
+        Value::Array(rc) => Some(Rc::as_ptr(rc) as *const () as usize),
+        Value::Dict(rc) => Some(Rc::as_ptr(rc) as *const () as usize),
+        Value::Object(rc) => Some(Rc::as_ptr(rc) as *const () as usize),
+        Value::Tuple(rc) => Some(Rc::as_ptr(rc) as *const () as usize),
+        _ => None,
+    }
+}
+
+/// Register a container that was just made.
+pub fn track(value: &Value) {
+    let tracked = match value {
+        Value::Array(rc) => Tracked::Array(Rc::downgrade(rc)),
+        Value::Dict(rc) => Tracked::Dict(Rc::downgrade(rc)),
+        Value::Object(rc) => Tracked::Object(Rc::downgrade(rc)),
+        Value::Tuple(rc) => Tracked::Tuple(Rc::downgrade(rc)),
+        _ => return,
+    };
+    HEAP.with_borrow_mut(|heap| {
+        heap.tracked.push(tracked);
+        heap.pending += 1;
+        heap.stats.allocated += 1;
+    });
+}
+
+/// Containers allocated since the last collection.
+pub fn pending() -> usize {
+    HEAP.with_borrow(|heap| heap.pending)
+}
+
+pub fn stats() -> Stats {
+    HEAP.with_borrow(|heap| heap.stats)
+}
+
+/// Free the cycles not reachable from `roots`, returning how many containers
+/// were freed.
+pub fn collect<'a>(roots: impl IntoIterator<Item = &'a Value>) -> usize {
+    let tracked = HEAP.with_borrow_mut(|heap| {
+        heap.tracked.retain(Tracked::is_alive);
+        std::mem::take(&mut heap.tracked)
+    });
+    let nodes = tracked
+        .iter()
+        .filter_map(Tracked::upgrade)
+        .collect::<Vec<_>>();
+    let index = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.address(), i))
+        .collect::<HashMap<_, _>>();
+    let children = nodes.iter().map(Node::children).collect::<Vec<_>>();
+
+    // References each container gets from the others. A container whose
+    // count is higher is also referenced from outside them: a root.
+    let mut internal = vec![0; nodes.len()];
+    for values in children.iter().flatten() {
+        for value in values {
+            if let Some(&i) = address(value).and_then(|a| index.get(&a)) {
+                internal[i] += 1;
+            }
+        }
+    }
+
+    let mut marked = vec![false; nodes.len()];
+    let mut stack = (0..nodes.len())
+        // One reference is held by `nodes`, and the children lists hold a
+        // copy of every internal one.
+        .filter(|&i| nodes[i].strong_count() > 1 + 2 * internal[i])
+        .collect::<Vec<_>>();
+    // Containers that can't be looked into are kept with all they hold.
+    stack.extend((0..nodes.len()).filter(|&i| children[i].is_none()));
+    let mut pending_roots = roots.into_iter().cloned().collect::<Vec<_>>();
+    while let Some(value) = pending_roots.pop() {
+        match value {
+            Value::Module(namespace) => pending_roots.extend(namespace.exports.values().cloned()),
+            value => {
+                if let Some(&i) = address(&value).and_then(|a| index.get(&a)) {
+                    stack.push(i);
+                }
+            }
+        }
+    }
+    while let Some(i) = stack.pop() {
+        if std::mem::replace(&mut marked[i], true) {
+            continue;
+        }
+        for value in children[i].iter().flatten() {
+            if let Some(&j) = address(value).and_then(|a| index.get(&a)) {
+                stack.push(j);
+            }
+        }
+    }
+    drop(children);
+
+    let mut garbage = vec![];
+    let mut freed = 0;
+    for (node, _) in nodes.iter().zip(&marked).filter(|(_, m)| !**m) {
+        node.empty(&mut garbage);
+        freed += 1;
+    }
+    drop(nodes);
+    drop(garbage);
+
+    HEAP.with_borrow_mut(|heap| {
+        // Containers made while the garbage was dropped were registered anew.
+        let mut tracked = tracked;
+        tracked.append(&mut heap.tracked);
+        tracked.retain(Tracked::is_alive);
+        heap.stats.collections += 1;
+        heap.stats.freed += freed;
+        heap.stats.live = tracked.len();
+        heap.tracked = tracked;
+        heap.pending = 0;
+    });
+    freed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{Interpreter, Value};
+    use std::rc::Rc;
+
+    fn object(interpreter: &Interpreter, name: &str) -> Value {
+        interpreter.global(name).cloned().unwrap()
+    }
+
+    #[test]
+    fn cycles() {
+        let mut interpreter = Interpreter::new();
+        let src = "
+            class Node {}
+            x = Node()
+            setattr(x, \"me\", x)
+            y = Node()
+            d = json_parse(\"{}\")
+            setattr(y, \"d\", d)
+            setattr(d, \"y\", (y, 1))
+            z = Node()
+            setattr(z, \"me\", z)
+        ";
+        interpreter.run_text(src, "<test>").unwrap();
+        let weak = |name| match object(&interpreter, name) {
+            Value::Object(o) => Rc::downgrade(&o),
+            _ => panic!("expected an object"),
+        };
+        let (x, y) = (weak("x"), weak("y"));
+        let Value::Object(z) = object(&interpreter, "z") else {
+            panic!("expected an object");
+        };
+
+        // Reachable cycles stay, as do those Rust code holds.
+        assert_eq!(interpreter.collect_garbage(), 0);
+        interpreter
+            .run_text("x = none\ny = none\nd = none\nz = none", "<test>")
+            .unwrap();
+        assert!(x.upgrade().is_some());
+        // x and the y -> d -> (y, 1) -> y cycle.
+        assert_eq!(interpreter.collect_garbage(), 4);
+        assert!(x.upgrade().is_none() && y.upgrade().is_none());
+        assert_eq!(z.borrow().attrs.len(), 1);
+    }
+
+    #[test]
+    fn threshold() {
+        let mut interpreter = Interpreter::new().with_gc_threshold(10);
+        let src = "
+            class Node {}
+            def cycle() -> Any do
+                o = Node()
+                setattr(o, \"me\", o)
+            end
+            i = 0
+            while i < 100 do
+                cycle()
+                i += 1
+            end
+            gc_stats()
+        ";
+        let Value::Dict(stats) = interpreter.run_text(src, "<test>").unwrap() else {
+            panic!("expected a dict");
+        };
+        let stats = stats.borrow();
+        assert!(
+            stats["collections"].as_double().unwrap() >= 9.0,
+            "{:?}",
+            stats
+        );
+        assert_eq!(stats["threshold"], Value::Integer(10));
+        assert!(stats["live"].as_double().unwrap() <= 20.0, "{:?}", stats);
+    }
+}
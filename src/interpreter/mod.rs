@@ -0,0 +1,1286 @@
+//! A tree-walking interpreter over the AST of [`crate::parser`].
+//!
+//! Top-level functions and classes are defined before anything runs, so they
+//! can be used above their definition. Variables assigned in a function are
+//! local to it; other lookups fall back to globals, then to the builtins.
+//! Each module has globals of its own: `use` runs a module once, then binds
+//! what it exports in the importing module.
+
+pub mod gc;
+pub(crate) mod ops;
+pub mod trace;
+mod value;
+
+pub use trace::StackTrace;
+pub use value::{Class, ModuleId, Namespace, Object, Value};
+
+use crate::Result;
+use crate::builtins::{Builtin, Registry};
+use crate::lexer::tokens::TokenType;
+use crate::modules::ModuleLoader;
+use crate::native;
+use crate::parser::{AstNode, ExprValue, Function, Import, NodePosition, Pattern, parse_source};
+use crate::sandbox::{Capability, Limits, Meter};
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Positional and keyword arguments of a call.
+type Arguments = (Vec<Value>, Vec<(String, Value)>);
+
+/// A call to make once the function making it has returned.
+type TailCall = (Rc<Function>, ModuleId, Vec<Value>, Vec<(String, Value)>);
+
+/// How deep calls may nest before the program is stopped, unless
+/// [`Limits::call_depth`] says otherwise.
+const MAX_DEPTH: usize = 10_000;
+/// Calls are run on a new stack segment of `STACK_SEGMENT` bytes once less
+/// than `RED_ZONE` is left, so deep recursion doesn't depend on the stack of
+/// the thread running the program.
+const RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+pub struct Interpreter {
+    /// Globals of each module, those of the program being run first.
+    globals: Vec<HashMap<String, Value>>,
+    /// Module of the code being run.
+    module: ModuleId,
+    /// Local variables of the functions being run, innermost last.
+    frames: Vec<HashMap<String, Value>>,
+    /// Set by `return` until the function returning is left.
+    returning: Option<Value>,
+    /// Set by a call in tail position, which the function making it returns
+    /// the result of.
+    tail_call: Option<TailCall>,
+    /// Where `print` writes.
+    pub(crate) output: Box<dyn Write>,
+    builtins: Registry,
+    loader: ModuleLoader,
+    /// Exports of the modules that have been run, by path.
+    modules: HashMap<PathBuf, Rc<Namespace>>,
+    /// File of the statement being run, which `use` paths are relative to.
+    file: String,
+    /// Cause and position of the innermost statement that failed in the last
+    /// program run.
+    last_error: Option<(String, NodePosition)>,
+    /// The calls being made, outermost first, for stack traces.
+    calls: Vec<trace::Frame>,
+    /// The calls that were being made when the last error happened.
+    last_trace: Option<StackTrace>,
+    /// Containers allocated before the garbage collector runs.
+    gc_threshold: usize,
+    limits: Limits,
+    /// What the current run has used of `limits`.
+    meter: Meter,
+    /// The capabilities granted, or None if not sandboxed.
+    capabilities: Option<BTreeSet<Capability>>,
+    /// Text of the programs run from text rather than files, by file name.
+    sources: HashMap<String, Rc<str>>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            globals: vec![HashMap::new()],
+            module: 0,
+            frames: vec![],
+            returning: None,
+            tail_call: None,
+            output: Box::new(io::stdout()),
+            builtins: Registry::standard(),
+            loader: ModuleLoader::new(),
+            modules: HashMap::new(),
+            file: String::new(),
+            last_error: None,
+            calls: vec![],
+            last_trace: None,
+            gc_threshold: gc::DEFAULT_THRESHOLD,
+            limits: Limits::default(),
+            meter: Meter::new(),
+            capabilities: None,
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Send the output of `print` to `output` instead of stdout.
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Load modules with `loader`.
+    pub fn with_loader(mut self, loader: ModuleLoader) -> Self {
+        self.loader = loader;
+        let read_files = self.require(Capability::ReadFiles, "").is_ok();
+        self.loader.set_file_access(read_files);
+        self
+    }
+
+    /// Collect garbage every `threshold` allocations of arrays, dicts, objects
+    /// and tuples.
+    pub fn with_gc_threshold(mut self, threshold: usize) -> Self {
+        self.gc_threshold = threshold.max(1);
+        self
+    }
+
+    /// Stop runs that use up more than `limits`.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sandbox the interpreter: builtins needing a capability not in
+    /// `capabilities` can't be called, and only the standard library can be
+    /// used without [`Capability::ReadFiles`].
+    pub fn with_capabilities(mut self, capabilities: impl IntoIterator<Item = Capability>) -> Self {
+        self.capabilities = Some(capabilities.into_iter().collect());
+        let read_files = self.require(Capability::ReadFiles, "").is_ok();
+        self.loader.set_file_access(read_files);
+        self
+    }
+
+    /// Fail unless `capability` is granted, which `what` needs.
+    pub(crate) fn require(&self, capability: Capability, what: &str) -> Result<()> {
+        match &self.capabilities {
+            Some(granted) if !granted.contains(&capability) => Err(format!(
+                "{} needs the `{}` capability, which wasn't granted",
+                what, capability
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn gc_threshold(&self) -> usize {
+        self.gc_threshold
+    }
+
+    /// Free the unreachable reference cycles now, returning how many
+    /// containers were freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let globals = self.globals.iter().flat_map(|g| g.values());
+        let locals = self.frames.iter().flat_map(|f| f.values());
+        let exports = self.modules.values().flat_map(|m| m.exports.values());
+        gc::collect(globals.chain(locals).chain(exports).chain(&self.returning))
+    }
+
+    /// Count a step of the run against the limits.
+    fn step(&mut self) -> Result<()> {
+        self.meter.step(&self.limits)?;
+        if let Some(max) = self.limits.heap
+            && gc::stats().live + gc::pending() > max
+        {
+            // Only what can't be collected counts.
+            self.collect_garbage();
+            if gc::stats().live > max {
+                return Err(format!("heap limit of {} containers exceeded", max));
+            }
+        }
+        Ok(())
+    }
+
+    /// Count `bytes` of strings, big integers and array elements made against
+    /// the limits.
+    fn charge(&mut self, bytes: usize) -> Result<()> {
+        if self.limits.any() {
+            self.meter.charge(bytes, &self.limits)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the binary operator `op`, checking the limits allow for its
+    /// result before making it.
+    fn binary(&mut self, op: &TokenType, l: Value, r: Value) -> Result<Value> {
+        if !self.limits.any() {
+            return ops::binary(op, l, r);
+        }
+        let size = ops::result_size(op, &l, &r);
+        self.meter.afford(size, &self.limits)?;
+        let value = ops::binary(op, l, r)?;
+        self.charge(value.size())?;
+        Ok(value)
+    }
+
+    /// Collect garbage if enough has been allocated since the last time.
+    fn maybe_collect(&mut self) {
+        if gc::pending() >= self.gc_threshold {
+            self.collect_garbage();
+        }
+    }
+
+    /// A global of the program being run.
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.globals[0].get(name)
+    }
+
+    /// Set a global of the program being run.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals[0].insert(name.to_string(), value);
+    }
+
+    /// Make `builtin` callable by the programs run from now on.
+    pub fn register(&mut self, builtin: Builtin) {
+        self.builtins.register(builtin);
+    }
+
+    pub fn builtins(&self) -> &Registry {
+        &self.builtins
+    }
+
+    /// Show lines of `text` in the traces of errors in `file`, instead of
+    /// reading it from disk. The latest text given for a file is used.
+    pub fn add_source(&mut self, file: &str, text: &str) {
+        self.sources.insert(file.to_string(), text.into());
+    }
+
+    pub fn loader_mut(&mut self) -> &mut ModuleLoader {
+        &mut self.loader
+    }
+
+    /// Cause and position of the statement the last program run failed at,
+    /// for tools that show errors their own way. When it failed inside a
+    /// module, this is the statement of that module.
+    pub fn last_error(&self) -> Option<&(String, NodePosition)> {
+        self.last_error.as_ref()
+    }
+
+    /// The calls that were being made when the last error happened, with the
+    /// innermost at the expression that failed.
+    pub fn last_trace(&self) -> Option<&StackTrace> {
+        self.last_trace.as_ref()
+    }
+
+    /// Lex, parse and run `text`, returning the value of its last expression.
+    pub fn run_text(&mut self, text: &str, file: &str) -> Result<Value> {
+        let ast = parse_source(text, file)?;
+        self.add_source(file, text);
+        self.run_program(&ast)
+    }
+
+    /// Run a program, returning the value of its last expression. Definitions
+    /// and globals are kept, so later programs can use them.
+    pub fn run_program(&mut self, ast: &[(AstNode, NodePosition)]) -> Result<Value> {
+        // Modules are run from inside the program that uses them.
+        if self.module == 0 {
+            self.last_error = None;
+            self.last_trace = None;
+            self.meter = Meter::new();
+        }
+        for (node, _) in ast {
+            match node {
+                AstNode::FunctionDef(f) => {
+                    let value = Value::Function(Rc::new(f.clone()), self.module);
+                    self.globals[self.module].insert(f.name.clone(), value);
+                }
+                AstNode::Class(c) => {
+                    let methods = c
+                        .fns
+                        .iter()
+                        .map(|(f, _)| (f.name.clone(), Rc::new(f.clone())))
+                        .collect();
+                    let class = Class {
+                        name: c.name.clone(),
+                        methods,
+                        module: self.module,
+                    };
+                    self.globals[self.module].insert(c.name.clone(), Value::Class(Rc::new(class)));
+                }
+                AstNode::Extern(_) | AstNode::Expression(_) => {}
+            }
+        }
+
+        self.calls.push(trace::Frame {
+            function: None,
+            class: None,
+            file: String::new(),
+            line: 0,
+            column: 0,
+            source: String::new(),
+        });
+        let result = self.run_statements(ast);
+        self.calls.pop();
+        result
+    }
+
+    fn run_statements(&mut self, ast: &[(AstNode, NodePosition)]) -> Result<Value> {
+        let mut last = Value::None;
+        for (node, pos) in ast {
+            self.file.clone_from(&pos.file);
+            if let Some(call) = self.calls.last_mut() {
+                call.file.clone_from(&pos.file);
+            }
+            self.mark(pos);
+            self.maybe_collect();
+            let result = match node {
+                AstNode::Expression(e) => self.eval(e),
+                // C functions are bound in order, so their errors have a place.
+                AstNode::Extern(f) => self
+                    .require(Capability::Native, "`extern`")
+                    .and_then(|()| native::bind(f, Path::new(&pos.file)))
+                    .map(|builtin| {
+                        self.builtins.register(builtin);
+                        Value::None
+                    }),
+                // Definitions were handled above.
+                _ => Ok(Value::None),
+            };
+            last = result.map_err(|e| {
+                self.returning = None;
+                if self.last_error.is_none() {
+                    self.last_error = Some((e.clone(), pos.clone()));
+                }
+                self.capture_trace(&e);
+                format!(
+                    "{}\n    at {}:{} in file `{}`.",
+                    e, pos.line_no, pos.pos, pos.file
+                )
+            })?;
+            // `return` outside of a function ends the program.
+            if let Some(value) = self.returning.take() {
+                return Ok(value);
+            }
+        }
+        Ok(last)
+    }
+
+    pub fn eval(&mut self, expr: &ExprValue) -> Result<Value> {
+        if self.limits.any() {
+            self.step()?;
+        }
+        match expr {
+            ExprValue::FnCall(name, args, kwargs, at) => {
+                self.mark(at);
+                let callee = self.lookup(name)?;
+                let (args, kwargs) = self.eval_args(args, kwargs)?;
+                self.mark(at);
+                self.call(callee, args, kwargs)
+            }
+            ExprValue::UnOp(op, e) => {
+                let value = self.eval(e)?;
+                ops::unary(op, value)
+            }
+            ExprValue::BinOp(l, op, r, at) => match **op {
+                TokenType::Dot => self.eval_member(l, r, at),
+                TokenType::And => {
+                    let l = self.eval(l)?;
+                    if l.is_truthy() { self.eval(r) } else { Ok(l) }
+                }
+                TokenType::Or => {
+                    let l = self.eval(l)?;
+                    if l.is_truthy() { Ok(l) } else { self.eval(r) }
+                }
+                _ => {
+                    let l = self.eval(l)?;
+                    let r = self.eval(r)?;
+                    self.mark(at);
+                    self.binary(op, l, r)
+                }
+            },
+            ExprValue::Boolean(b) => Ok(Value::Boolean(*b)),
+            ExprValue::Integer(i) => Ok(Value::Integer(*i)),
+            ExprValue::BigInteger(i) => Ok(Value::BigInteger(i.clone())),
+            ExprValue::Double(d) => Ok(Value::Double(*d)),
+            ExprValue::Str(s) => Ok(Value::Str(s.clone())),
+            ExprValue::Interpolation(parts) => {
+                let mut s = String::new();
+                for part in parts {
+                    let part = self.eval(part)?.to_string();
+                    self.charge(part.len())?;
+                    s += &part;
+                }
+                Ok(Value::Str(s))
+            }
+            ExprValue::Identifier(name) => self.lookup(name),
+            ExprValue::VarDecl { name, .. } => {
+                self.assign(name, Value::None);
+                Ok(Value::None)
+            }
+            ExprValue::IfElse { cond, if_, else_ } => {
+                if self.eval(cond)?.is_truthy() {
+                    self.eval(if_)
+                } else {
+                    self.eval(else_)
+                }
+            }
+            ExprValue::Assign { name, value } => {
+                let value = self.eval(value)?;
+                self.assign(name, value);
+                Ok(Value::None)
+            }
+            ExprValue::AugAssign { name, op, value } => {
+                let op = match **op {
+                    TokenType::PlusEq => TokenType::Plus,
+                    TokenType::MinusEq => TokenType::Minus,
+                    TokenType::MulEq => TokenType::Mul,
+                    _ => TokenType::Div,
+                };
+                let current = self.lookup(name)?;
+                let value = self.eval(value)?;
+                let value = self.binary(&op, current, value)?;
+                self.assign(name, value);
+                Ok(Value::None)
+            }
+            ExprValue::Return(e) => {
+                // What a function returns is in tail position.
+                let value = if self.frames.is_empty() {
+                    self.eval(e)?
+                } else {
+                    self.eval_tail(e)?
+                };
+                self.returning = Some(value);
+                Ok(Value::None)
+            }
+            ExprValue::Use(spec, import) => {
+                self.import(spec, import)?;
+                Ok(Value::None)
+            }
+            ExprValue::Extern(spec) => {
+                self.load_native(spec)?;
+                Ok(Value::None)
+            }
+            ExprValue::None => Ok(Value::None),
+            ExprValue::Walrus { name, value } => {
+                let value = self.eval(value)?;
+                self.assign(name, value.clone());
+                Ok(value)
+            }
+            ExprValue::While(cond, body) => {
+                while self.returning.is_none() && self.eval(cond)?.is_truthy() {
+                    self.eval(body)?;
+                }
+                Ok(Value::None)
+            }
+            ExprValue::Do(exprs) => {
+                let mut last = Value::None;
+                for e in exprs {
+                    last = self.eval(e)?;
+                    if self.returning.is_some() {
+                        break;
+                    }
+                }
+                Ok(last)
+            }
+            ExprValue::Array(exprs) => {
+                let values = exprs.iter().map(|e| self.eval(e)).collect::<Result<_>>()?;
+                Ok(Value::array(values))
+            }
+            ExprValue::Tuple(exprs) => {
+                let values = exprs.iter().map(|e| self.eval(e)).collect::<Result<_>>()?;
+                Ok(Value::tuple(values))
+            }
+            ExprValue::Destructure { pattern, value, .. } => {
+                let value = self.eval(value)?;
+                self.bind(pattern, value)?;
+                Ok(Value::None)
+            }
+        }
+    }
+
+    /// Bind what `use spec` imports, running the module unless it has run already.
+    fn import(&mut self, spec: &str, import: &Import) -> Result<()> {
+        let module = self.loader.load(spec, Path::new(&self.file))?;
+        let namespace = match self.modules.get(&module.path) {
+            Some(namespace) => namespace.clone(),
+            None => {
+                let id = self.globals.len();
+                self.globals.push(HashMap::new());
+                let module_before = std::mem::replace(&mut self.module, id);
+                let file = std::mem::take(&mut self.file);
+                let frames = std::mem::take(&mut self.frames);
+                let result = self.run_program(&module.ast);
+                (self.module, self.file, self.frames) = (module_before, file, frames);
+                result?;
+
+                // Modules marking nothing `pub` export all their globals.
+                let public = module
+                    .ast
+                    .iter()
+                    .filter_map(|(node, _)| match node {
+                        AstNode::FunctionDef(f) if f.public => Some(&f.name),
+                        AstNode::Class(c) if c.public => Some(&c.name),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                let exports = self.globals[id]
+                    .iter()
+                    .filter(|(name, _)| public.is_empty() || public.contains(name))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+                let namespace = Rc::new(Namespace {
+                    name: spec.to_string(),
+                    exports,
+                });
+                self.modules.insert(module.path.clone(), namespace.clone());
+                namespace
+            }
+        };
+
+        match import {
+            Import::All => {
+                for (name, value) in &namespace.exports {
+                    self.assign(name, value.clone());
+                }
+            }
+            Import::Module(alias) => self.assign(alias, Value::Module(namespace)),
+            Import::Names(names) => {
+                for (name, alias) in names {
+                    let value =
+                        namespace.exports.get(name).cloned().ok_or_else(|| {
+                            format!("module `{}` does not export `{}`", spec, name)
+                        })?;
+                    self.assign(alias, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval_args(
+        &mut self,
+        args: &[ExprValue],
+        kwargs: &[(String, ExprValue)],
+    ) -> Result<Arguments> {
+        let args = args.iter().map(|a| self.eval(a)).collect::<Result<_>>()?;
+        let kwargs = kwargs
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), self.eval(v)?)))
+            .collect::<Result<_>>()?;
+        Ok((args, kwargs))
+    }
+
+    /// `receiver.name` or `receiver.name(args)`.
+    fn eval_member(
+        &mut self,
+        receiver: &ExprValue,
+        member: &ExprValue,
+        at: &NodePosition,
+    ) -> Result<Value> {
+        let receiver = self.eval(receiver)?;
+        match member {
+            ExprValue::Identifier(name) => {
+                self.mark(at);
+                self.get_attr(&receiver, name)
+            }
+            ExprValue::FnCall(name, args, kwargs, at) => {
+                let (args, kwargs) = self.eval_args(args, kwargs)?;
+                self.mark(at);
+                self.call_method(receiver, name, args, kwargs)
+            }
+            _ => Err("expected a name after '.'".to_string()),
+        }
+    }
+
+    pub(crate) fn get_attr(&self, receiver: &Value, name: &str) -> Result<Value> {
+        let found = match receiver {
+            Value::Object(o) => {
+                let o = o.borrow();
+                o.attrs.get(name).cloned().or_else(|| {
+                    let method = o.class.methods.get(name).cloned();
+                    method.map(|m| Value::Function(m, o.class.module))
+                })
+            }
+            Value::Class(c) => {
+                let method = c.methods.get(name).cloned();
+                method.map(|m| Value::Function(m, c.module))
+            }
+            Value::Module(m) => m.exports.get(name).cloned(),
+            Value::Dict(d) => d.borrow().get(name).cloned(),
+            _ => None,
+        };
+        found.ok_or_else(|| format!("{} has no attribute `{}`", receiver.type_name(), name))
+    }
+
+    /// Methods get the receiver as their first parameter, unless they have none.
+    fn call_method(
+        &mut self,
+        receiver: Value,
+        name: &str,
+        mut args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value> {
+        if let Value::Object(o) = &receiver {
+            let class = o.borrow().class.clone();
+            if let Some(method) = class.methods.get(name) {
+                if !method.args.name.is_empty() {
+                    args.insert(0, receiver.clone());
+                }
+                return self.call_function(method.clone(), class.module, args, kwargs);
+            }
+        }
+        let callee = self.get_attr(&receiver, name)?;
+        self.call(callee, args, kwargs)
+    }
+
+    pub fn call(
+        &mut self,
+        callee: Value,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value> {
+        // Calls from the host start a new run.
+        if self.calls.is_empty() {
+            self.last_trace = None;
+            self.meter = Meter::new();
+        }
+        match callee {
+            Value::Function(f, module) => self.call_function(f, module, args, kwargs),
+            Value::Builtin(name) => self.call_builtin(&name, args, kwargs),
+            Value::Class(class) => self.construct(class, args, kwargs),
+            v => Err(format!("{} is not callable", v.type_name())),
+        }
+    }
+
+    /// Make an instance of `class`, calling its constructor: the method named
+    /// after the class.
+    fn construct(
+        &mut self,
+        class: Rc<Class>,
+        mut args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value> {
+        let object = Value::object(class.clone());
+        let Some(constructor) = class.methods.get(&class.name).cloned() else {
+            if args.is_empty() && kwargs.is_empty() {
+                return Ok(object);
+            }
+            return Err(format!("`{}` has no constructor", class.name));
+        };
+        args.insert(0, object.clone());
+        match self.call_function(constructor, class.module, args, kwargs)? {
+            Value::None => Ok(object),
+            value => Ok(value),
+        }
+    }
+
+    /// Register the functions of the native extension `extern spec` names.
+    fn load_native(&mut self, spec: &str) -> Result<()> {
+        self.require(Capability::Native, "`extern`")?;
+        let path = native::resolve(spec, Path::new(&self.file));
+        for builtin in native::load(&path)? {
+            self.builtins.register(builtin);
+        }
+        Ok(())
+    }
+
+    fn call_builtin(
+        &mut self,
+        name: &str,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value> {
+        let builtin = self.builtins.get(name).cloned();
+        let builtin = builtin.ok_or_else(|| format!("unknown builtin `{}`", name))?;
+        let value = builtin.call(self, args, kwargs)?;
+        self.charge(value.size())?;
+        Ok(value)
+    }
+
+    fn call_function(
+        &mut self,
+        f: Rc<Function>,
+        module: ModuleId,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value> {
+        let max_depth = self.limits.call_depth.unwrap_or(MAX_DEPTH);
+        if self.frames.len() >= max_depth {
+            return Err("maximum call depth exceeded".to_string());
+        }
+        self.maybe_collect();
+        let mut call = (f, module, args, kwargs);
+        stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, || {
+            loop {
+                let (f, module, args, kwargs) = call;
+                let result = self.run_function(&f, module, args, kwargs);
+                // A call the function ended with runs in its place, so
+                // recursion in tail position doesn't grow the stack.
+                match self.tail_call.take() {
+                    Some(next) if result.is_ok() => call = next,
+                    _ => return result,
+                }
+            }
+        })
+    }
+
+    fn run_function(
+        &mut self,
+        f: &Function,
+        module: ModuleId,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value> {
+        self.frames.push(HashMap::new());
+        let module_before = std::mem::replace(&mut self.module, module);
+        self.enter_function(f);
+        // Arguments that don't fit are the caller's error, so the trace ends
+        // there.
+        let result = match self.bind_params(f, args, kwargs) {
+            Ok(()) => self
+                .eval_tail(&f.expression.0)
+                .inspect_err(|e| self.capture_trace(e)),
+            Err(e) => Err(e),
+        };
+        self.calls.pop();
+        self.frames.pop();
+        self.module = module_before;
+
+        let returned = self.returning.take();
+        Ok(returned.unwrap_or(result?))
+    }
+
+    /// Assign the parameters of `f` in the current frame.
+    fn bind_params(
+        &mut self,
+        f: &Function,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<()> {
+        let bound = f
+            .args
+            .bind(args, kwargs)
+            .map_err(|e| format!("in call to `{}`: {}", f.name, e))?;
+        for (i, value) in bound.params.into_iter().enumerate() {
+            let value = match (value, &f.args.default[i]) {
+                (Some(value), _) => value,
+                // Defaults are evaluated at each call, after the earlier parameters.
+                (None, Some(default)) => self.eval(default)?,
+                (None, None) => unreachable!("bind checks required parameters"),
+            };
+            self.assign(&f.args.name[i], value);
+        }
+        if let Some((name, _)) = &f.args.vararg {
+            self.assign(name, Value::array(bound.rest));
+        }
+        if let Some((name, _)) = &f.args.kwarg {
+            self.assign(name, Value::dict(bound.opts.into_iter().collect()));
+        }
+        Ok(())
+    }
+
+    /// Evaluate `expr`, which is in tail position of a function: its value is
+    /// what the function returns. A call to a function there isn't made but
+    /// left in `tail_call`, for [`Interpreter::call_function`] to make once
+    /// the current call is done.
+    fn eval_tail(&mut self, expr: &ExprValue) -> Result<Value> {
+        match expr {
+            ExprValue::FnCall(name, args, kwargs, at) => self.defer_call(name, args, kwargs, at),
+            ExprValue::BinOp(receiver, op, member, _)
+                if **op == TokenType::Dot && matches!(**member, ExprValue::FnCall(..)) =>
+            {
+                self.defer_method(receiver, member)
+            }
+            ExprValue::IfElse { cond, if_, else_ } => {
+                if self.eval(cond)?.is_truthy() {
+                    self.eval_tail(if_)
+                } else {
+                    self.eval_tail(else_)
+                }
+            }
+            ExprValue::Do(exprs) => {
+                let Some((last, init)) = exprs.split_last() else {
+                    return Ok(Value::None);
+                };
+                for e in init {
+                    self.eval(e)?;
+                    if self.returning.is_some() {
+                        return Ok(Value::None);
+                    }
+                }
+                self.eval_tail(last)
+            }
+            expr => self.eval(expr),
+        }
+    }
+
+    /// `name(args)` in tail position. Kept out of [`Interpreter::eval_tail`],
+    /// so that recursion through it takes less stack.
+    fn defer_call(
+        &mut self,
+        name: &str,
+        args: &[ExprValue],
+        kwargs: &[(String, ExprValue)],
+        at: &NodePosition,
+    ) -> Result<Value> {
+        self.mark(at);
+        let callee = self.lookup(name)?;
+        let (args, kwargs) = self.eval_args(args, kwargs)?;
+        self.mark(at);
+        match callee {
+            Value::Function(f, module) => {
+                self.tail_call = Some((f, module, args, kwargs));
+                Ok(Value::None)
+            }
+            callee => self.call(callee, args, kwargs),
+        }
+    }
+
+    /// `receiver.name(args)` in tail position, which is deferred like
+    /// [`Interpreter::defer_call`].
+    fn defer_method(&mut self, receiver: &ExprValue, member: &ExprValue) -> Result<Value> {
+        let ExprValue::FnCall(name, args, kwargs, at) = member else {
+            unreachable!("eval_tail only defers method calls");
+        };
+        let receiver = self.eval(receiver)?;
+        let (mut args, kwargs) = self.eval_args(args, kwargs)?;
+        self.mark(at);
+        let method = match &receiver {
+            Value::Object(o) => {
+                let class = o.borrow().class.clone();
+                class.methods.get(name).map(|m| (m.clone(), class.module))
+            }
+            _ => None,
+        };
+        let Some((method, module)) = method else {
+            return match self.get_attr(&receiver, name)? {
+                Value::Function(f, module) => {
+                    self.tail_call = Some((f, module, args, kwargs));
+                    Ok(Value::None)
+                }
+                callee => self.call(callee, args, kwargs),
+            };
+        };
+        if !method.args.name.is_empty() {
+            args.insert(0, receiver);
+        }
+        self.tail_call = Some((method, module, args, kwargs));
+        Ok(Value::None)
+    }
+
+    /// Note the call of `f`, starting at its body.
+    fn enter_function(&mut self, f: &Function) {
+        let (_, at) = &*f.expression;
+        self.calls.push(trace::Frame {
+            function: Some(f.name.clone()),
+            class: f.class.clone(),
+            file: at.file.clone(),
+            line: at.line_no,
+            column: at.pos,
+            source: String::new(),
+        });
+    }
+
+    /// Note that the innermost frame is at `at`.
+    fn mark(&mut self, at: &NodePosition) {
+        if let Some(call) = self.calls.last_mut() {
+            call.line = at.line_no;
+            call.column = at.pos;
+        }
+    }
+
+    /// Keep the calls being made for the trace of `error`, unless it was kept
+    /// by a call deeper in already.
+    fn capture_trace(&mut self, error: &str) {
+        if self.last_trace.is_none() {
+            let mut frames = self.calls.clone();
+            for frame in &mut frames {
+                frame.source = self.source_line(&frame.file, frame.line);
+            }
+            self.last_trace = Some(StackTrace {
+                message: error.to_string(),
+                frames,
+            });
+        }
+    }
+
+    /// Line `line_no` of `file`, found in the text run, the modules loaded or
+    /// on disk, or nothing.
+    fn source_line(&self, file: &str, line_no: i32) -> String {
+        let text = match self.sources.get(file) {
+            Some(text) => Some(Cow::Borrowed(&**text)),
+            None => self.loader.source(file).map(Cow::Borrowed).or_else(|| {
+                let text = std::fs::read_to_string(file).ok();
+                text.map(Cow::Owned)
+            }),
+        };
+        let line = usize::try_from(line_no - 1).ok();
+        text.zip(line)
+            .and_then(|(text, line)| text.lines().nth(line).map(String::from))
+            .unwrap_or_default()
+    }
+
+    fn lookup(&self, name: &str) -> Result<Value> {
+        if let Some(value) = self.frames.last().and_then(|f| f.get(name)) {
+            return Ok(value.clone());
+        }
+        if let Some(value) = self.globals[self.module].get(name) {
+            return Ok(value.clone());
+        }
+        if self.builtins.get(name).is_some() {
+            return Ok(Value::Builtin(name.to_string()));
+        }
+        Err(format!("undefined variable `{}`", name))
+    }
+
+    fn assign(&mut self, name: &str, value: Value) {
+        match self.frames.last_mut() {
+            Some(frame) => frame.insert(name.to_string(), value),
+            None => self.globals[self.module].insert(name.to_string(), value),
+        };
+    }
+
+    fn bind(&mut self, pattern: &Pattern, value: Value) -> Result<()> {
+        match pattern {
+            Pattern::Identifier(name) => self.assign(name, value),
+            Pattern::Tuple(patterns) | Pattern::Array(patterns) => {
+                let values = match &value {
+                    Value::Tuple(t) => t.to_vec(),
+                    Value::Array(a) => a.borrow().clone(),
+                    v => return Err(format!("cannot destructure {}", v.type_name())),
+                };
+                if values.len() != patterns.len() {
+                    return Err(format!(
+                        "expected {} values to destructure but got {}",
+                        patterns.len(),
+                        values.len()
+                    ));
+                }
+                for (p, v) in patterns.iter().zip(values) {
+                    self.bind(p, v)?;
+                }
+            }
+            Pattern::Dict(names) => {
+                for name in names {
+                    let v = self.get_attr(&value, name)?;
+                    self.assign(name, v);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interpreter, Value};
+    use crate::modules::ModuleLoader;
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    fn run(src: &str) -> crate::Result<Value> {
+        Interpreter::new().run_text(src, "<test>")
+    }
+
+    fn show(src: &str) -> String {
+        run(src).unwrap().repr()
+    }
+
+    #[test]
+    fn cycles() {
+        let src = "d = json_parse(\"{}\")\nsetattr(d, \"me\", d)\nsetattr(d, \"n\", 1)\n";
+        assert_eq!(show(&format!("{}d", src)), "{\"me\": {...}, \"n\": 1}");
+        let same = "e = json_parse(\"{}\")\nsetattr(e, \"me\", e)\nsetattr(e, \"n\", 1)\nd == e";
+        assert_eq!(show(&format!("{}{}", src, same)), "true");
+        assert_eq!(
+            show(&format!("{}{}", src, same.replace("\"n\", 1", "\"n\", 2"))),
+            "false"
+        );
+
+        let a = Value::array(vec![Value::Integer(1)]);
+        let Value::Array(items) = &a else {
+            unreachable!()
+        };
+        items.borrow_mut().push(a.clone());
+        assert_eq!(a.to_string(), "[1, [...]]");
+        let b = Value::array(vec![Value::Integer(1)]);
+        let Value::Array(b_items) = &b else {
+            unreachable!()
+        };
+        b_items.borrow_mut().push(b.clone());
+        assert!(a == b);
+        b_items.borrow_mut().push(Value::None);
+        assert!(a != b);
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(show("1 + 2 * 3 - 4 / 2"), "5");
+        assert_eq!(show("2 ** 70"), "1180591620717411303424");
+        assert_eq!(show("9223372036854775807 + 1 - 1"), "9223372036854775807");
+        assert_eq!(show("1.5 * 2"), "3.0");
+        assert_eq!(show("\"a\" + 1 + true"), "\"a1true\"");
+        assert_eq!(show("[1, 2] + [\"x\"]"), "[1, 2, \"x\"]");
+        assert_eq!(show("(1, \"a\") == (1, \"a\")"), "true");
+        assert_eq!(show("none or 0 or \"x\""), "\"x\"");
+        assert_eq!(show("6 & 3 | 8 << 1"), "18");
+        assert_eq!(show("-5 >> 100000000000000000000"), "-1");
+        assert_eq!(show("(-1) ** 4000000001"), "-1");
+        assert_eq!(show("\"\" * 9223372036854775807"), "\"\"");
+        for src in [
+            "\"ab\" * 9223372036854775807",
+            "3 ** 4000000000",
+            "1 << 100000000000",
+        ] {
+            assert!(run(src).unwrap_err().contains("too large"), "{}", src);
+        }
+        assert_eq!(show("x = 2\n\"x=${x * 2}\""), "\"x=4\"");
+        assert!(run("1 / 0").unwrap_err().contains("division by zero"));
+        assert!(run("y").unwrap_err().contains("undefined variable `y`"));
+    }
+
+    #[test]
+    fn functions() {
+        let src = "
+            def fact(n: i32) -> i32 if n <= 1 then 1 else n * fact(n - 1)
+            def f(a: i32, b: i32 = a + 1, *rest: Any, **opts: Any) -> Any (a, b, rest, opts)
+            def early(x: i32) -> i32 do
+                if x > 0 then return 1
+                2
+            end
+        ";
+        assert_eq!(
+            show(&format!("{}fact(25)", src)),
+            "15511210043330985984000000"
+        );
+        assert_eq!(show(&format!("{}f(1)", src)), "(1, 2, [], {})");
+        assert_eq!(
+            show(&format!("{}f(1, 5, 6, c=7)", src)),
+            "(1, 5, [6], {\"c\": 7})"
+        );
+        assert_eq!(show(&format!("{}(early(1), early(0))", src)), "(1, 2)");
+        assert!(
+            run(&format!("{}f()", src))
+                .unwrap_err()
+                .contains("missing argument `a`")
+        );
+        assert!(
+            run("def r(n: i32) -> i32 do r(n); 1 end\nr(1)")
+                .unwrap_err()
+                .contains("call depth")
+        );
+        // Deep recursion doesn't overflow the stack of the thread running it.
+        let fact = "def fact(n: i32) -> Any if n < 2 then 1 else n * fact(n - 1)\n";
+        assert_eq!(show(&format!("{}fact(300) > 0", fact)), "true");
+        assert_eq!(
+            show("def f(n: i32) -> Any if n == 0 then 0 else 1 + f(n - 1)\nf(5000)"),
+            "5000"
+        );
+    }
+
+    #[test]
+    fn tail_calls() {
+        // Calls in tail position don't count towards the call depth.
+        let src = "
+            def count(n: i32, last: i32) -> Any do
+                if n < last then do
+                    n = n + 1;
+                    count(n, last);
+                end else
+                    n
+            end
+            def even(n: i32) -> Bool if n == 0 then true else odd(n - 1)
+            def odd(n: i32) -> Bool do
+                if n == 0 then return false
+                return even(n - 1)
+            end
+        ";
+        assert_eq!(show(&format!("{}count(0, 10000)", src)), "10000");
+        assert_eq!(
+            show(&format!("{}(even(5001), odd(5001))", src)),
+            "(false, true)"
+        );
+        // So do method calls.
+        let class =
+            "class C { def m(self: C, n: Int) -> Any if n == 0 then 7 else self.m(n - 1) }\n";
+        assert_eq!(show(&format!("{}C().m(200000)", class)), "7");
+        // The call's result is still what's returned when it isn't the last thing done.
+        assert_eq!(
+            show("def f(n: i32) -> Any do\nx = if n > 0 then f(n - 1) else 7\nx + 1\nend\nf(3)"),
+            "11"
+        );
+    }
+
+    #[test]
+    fn traces() {
+        let mut interpreter = Interpreter::new();
+        let src = "class Shape {
+    def area(self: Self) -> Any [1] * \"x\"
+}
+def measure(s: Any) -> Any do
+    a = s.area()
+    a + 1
+end
+x = 1
+measure(Shape())";
+        let e = interpreter.run_text(src, "<test>").unwrap_err();
+        assert!(e.contains("unsupported operand types"), "{}", e);
+        let trace = interpreter.last_trace().unwrap();
+        let frames = trace
+            .frames
+            .iter()
+            .map(|f| (f.class.as_deref(), f.function.as_deref(), f.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            frames,
+            [
+                (None, None, 9),
+                (None, Some("measure"), 5),
+                (Some("Shape"), Some("area"), 2)
+            ]
+        );
+        assert_eq!(trace.message, e.lines().next().unwrap());
+        let rendered = trace.render();
+        assert!(rendered.contains("`Shape.area` at 2:"), "{}", rendered);
+        // Lines come from the text run and the modules loaded, even when they
+        // aren't files.
+        assert!(
+            rendered.contains("\n      measure(Shape())\n"),
+            "{}",
+            rendered
+        );
+        assert!(rendered.contains("def area(self: Self) -> Any [1] * \"x\"\n"));
+        let src = "use \"std:json\" as j\nx = j.JSON()\nx.loads(\"{\")";
+        let mut std_user = Interpreter::new();
+        std_user.run_text(src, "<test>").unwrap_err();
+        let frames = &std_user.last_trace().unwrap().frames;
+        assert_eq!(frames[1].file, "<std>/json.lyr");
+        assert_eq!(frames[1].source.trim(), "json_parse(data);");
+        assert_eq!(trace.to_json()["frames"][1]["function"], "measure");
+
+        // Wrong arguments are the caller's error.
+        let e = run("def f(a: i32) -> Any a\ndef g() -> Any f(1, 2)\ng()");
+        assert!(e.unwrap_err().contains("in call to `f`"));
+        let mut interpreter = Interpreter::new();
+        let src = "def f(a: i32) -> Any a\ndef g() -> Any do\nf(1, 2)\n1\nend\ng()";
+        interpreter.run_text(src, "<test>").unwrap_err();
+        let innermost = interpreter.last_trace().unwrap().frames.last().unwrap();
+        assert_eq!(
+            (innermost.function.as_deref(), innermost.line),
+            (Some("g"), 3)
+        );
+        interpreter.run_text("x = 1", "<test>").unwrap();
+        assert!(interpreter.last_trace().is_none());
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(show("x = 1\nx += 2\nx *= 3\nx"), "9");
+        assert_eq!(show("let (a, [b, c]) = (1, [2, 3])\na + b + c"), "6");
+        assert_eq!(show("i = 0\nwhile i < 5 i += 1\ni"), "5");
+        assert_eq!(show("(n =: 3) + n"), "6");
+        // Assignments in functions are local.
+        assert_eq!(
+            show("x = 1\ndef f() -> Any do x = 2; x end\n(f(), x)"),
+            "(2, 1)"
+        );
+    }
+
+    #[test]
+    fn classes() {
+        let src = "
+            class Point {
+                def Point(self: Self, x: i32, y: i32) -> Point do
+                    setattr(self, \"x\", x)
+                    setattr(self, \"y\", y)
+                end
+                def sum(self: Self) -> i32 self.x + self.y
+                def origin() -> Str \"(0, 0)\"
+            }
+            p = Point(1, 2)
+        ";
+        assert_eq!(show(&format!("{}p.sum()", src)), "3");
+        assert_eq!(show(&format!("{}p.origin()", src)), "\"(0, 0)\"");
+        assert_eq!(
+            show(&format!("{}{{x, y}} = p\n[x, y, type(p)]", src)),
+            "[1, 2, \"Point\"]"
+        );
+        assert!(
+            run(&format!("{}p.z", src))
+                .unwrap_err()
+                .contains("no attribute `z`")
+        );
+    }
+
+    #[test]
+    fn modules() {
+        assert_eq!(
+            show("use \"std:string\"\nString().digits()"),
+            "\"0123456789\""
+        );
+        let mut interpreter = Interpreter::new();
+        let external = concat!(env!("CARGO_MANIFEST_DIR"), "/iorekfiles/external");
+        interpreter.loader = ModuleLoader::new().with_external_dir(external);
+        let src = "use \"@:mathutils/consts\"\nuse \"@:mathutils/consts\"\nPI";
+        assert_eq!(interpreter.run_text(src, "<test>").unwrap().repr(), "3.14");
+        let e = run("x = 1\nuse \"std:nope\"").unwrap_err();
+        assert!(e.starts_with("cannot find module `std:nope`"), "{}", e);
+        assert!(e.contains("at 2:"), "{}", e);
+    }
+
+    #[test]
+    fn imports() {
+        let dir = std::env::temp_dir().join(format!("lyron-imports-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("geo.lyr"),
+            "pub def area(r: Any) -> Any PI * square(r)\ndef square(x: Any) -> Any x * x\nPI = 3\npub class Circle { def Circle(self: Self, r: Any) -> Any setattr(self, \"r\", r) }\n",
+        )
+        .unwrap();
+        let main = dir.join("main.lyr");
+        let run = |src: &str| {
+            Interpreter::new()
+                .run_text(src, main.to_str().unwrap())
+                .map(|v| v.repr())
+        };
+
+        // Helpers that aren't `pub` stay in the module, and can't clash.
+        assert_eq!(run("use \"geo\"\narea(2)").unwrap(), "12");
+        let e = run("use \"geo\"\nsquare(2)").unwrap_err();
+        assert!(e.contains("undefined variable `square`"), "{}", e);
+        assert_eq!(
+            run("def square(x: Any) -> Any 0\nuse \"geo\"\n[area(1), square(5)]").unwrap(),
+            "[3, 0]"
+        );
+
+        assert_eq!(run("use \"geo\" as g\ng.area(1)").unwrap(), "3");
+        assert_eq!(run("use \"geo\" as g\ntype(g)").unwrap(), "\"Module\"");
+        assert!(run("use \"geo\" as g\ng.square(1)").is_err());
+        assert!(run("use \"geo\" as g\narea(1)").is_err());
+
+        assert_eq!(
+            run("use { area as a, Circle } from \"geo\"\n[a(1), Circle(2).r]").unwrap(),
+            "[3, 2]"
+        );
+        let e = run("use { square } from \"geo\"").unwrap_err();
+        assert!(e.contains("module `geo` does not export `square`"), "{}", e);
+        assert!(run("use { area } from \"geo\"\nCircle").is_err());
+
+        // Without `pub`, everything is exported.
+        assert_eq!(
+            run("use { String } from \"std:string\"\nString().digits()").unwrap(),
+            "\"0123456789\""
+        );
+    }
+
+    #[test]
+    fn print() {
+        #[derive(Clone, Default)]
+        struct Buffer(Rc<RefCell<Vec<u8>>>);
+        impl Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Buffer::default();
+        let mut interpreter = Interpreter::new().with_output(buffer.clone());
+        interpreter
+            .run_text("print(\"a\", 1, [\"b\"], none)", "<test>")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.0.take()).unwrap(),
+            "a 1 [\"b\"] none\n"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Unary and binary operators on [`Value`]s.
+
+use crate::Result;
+use crate::interpreter::Value;
+use crate::lexer::tokens::TokenType;
+
+use num_bigint::BigInt;
+use std::cmp::Ordering;
+
+/// Largest string an operator makes, in bytes.
+pub(crate) const MAX_STR_LEN: usize = 1 << 28;
+/// Largest integer an operator makes, in bits, so that `**` and `<<` can't
+/// exhaust memory or run for hours.
+pub(crate) const MAX_INT_BITS: u64 = 1 << 23;
+
+fn too_large(what: &str) -> String {
+    format!("{} is too large", what)
+}
+
+fn type_error(op: &TokenType, l: &Value, r: &Value) -> String {
+    format!(
+        "unsupported operand types for {:?}: {} and {}",
+        op,
+        l.type_name(),
+        r.type_name()
+    )
+}
+
+pub fn unary(op: &TokenType, v: Value) -> Result<Value> {
+    match (op, v) {
+        (TokenType::Not, v) => Ok(Value::Boolean(!v.is_truthy())),
+        (TokenType::Plus, v @ (Value::Integer(_) | Value::BigInteger(_) | Value::Double(_))) => {
+            Ok(v)
+        }
+        (TokenType::Minus, Value::Integer(i)) => Ok(i
+            .checked_neg()
+            .map_or_else(|| Value::BigInteger(-BigInt::from(i)), Value::Integer)),
+        (TokenType::Minus, Value::BigInteger(i)) => Ok(Value::from_bigint(-i)),
+        (TokenType::Minus, Value::Double(d)) => Ok(Value::Double(-d)),
+        (op, v) => Err(format!(
+            "unsupported operand type for {:?}: {}",
+            op,
+            v.type_name()
+        )),
+    }
+}
+
+/// Apply a binary operator other than `.`, `and` and `or`, which need the
+/// unevaluated operands.
+pub fn binary(op: &TokenType, l: Value, r: Value) -> Result<Value> {
+    use TokenType as T;
+    match op {
+        T::Plus => match (&l, &r) {
+            (Value::Str(a), _) => Ok(Value::Str(format!("{}{}", a, r))),
+            (_, Value::Str(b)) => Ok(Value::Str(format!("{}{}", l, b))),
+            (Value::Array(a), Value::Array(b)) => {
+                let mut values = a.borrow().clone();
+                values.extend(b.borrow().iter().cloned());
+                Ok(Value::array(values))
+            }
+            _ => arithmetic(op, l, r),
+        },
+        T::Mul => match (&l, &r) {
+            (Value::Str(s), Value::Integer(n)) | (Value::Integer(n), Value::Str(s)) => {
+                let n = usize::try_from((*n).max(0)).unwrap_or(usize::MAX);
+                match s.len().checked_mul(n) {
+                    Some(len) if len <= MAX_STR_LEN => Ok(Value::Str(s.repeat(n))),
+                    _ => Err(too_large("repeated string")),
+                }
+            }
+            _ => arithmetic(op, l, r),
+        },
+        T::Minus | T::Div | T::Mod | T::Pow => arithmetic(op, l, r),
+        T::Equal => Ok(Value::Boolean(l == r)),
+        T::NotEq => Ok(Value::Boolean(l != r)),
+        T::Less | T::Greater | T::LessEq | T::GreaterEq => {
+            let ordering = compare(&l, &r).ok_or_else(|| type_error(op, &l, &r))?;
+            Ok(Value::Boolean(match op {
+                T::Less => ordering == Ordering::Less,
+                T::Greater => ordering == Ordering::Greater,
+                T::LessEq => ordering != Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            }))
+        }
+        T::BitAnd | T::BitOr | T::BitXor | T::Shl | T::Shr => {
+            let (Some(a), Some(b)) = (l.as_int(), r.as_int()) else {
+                return Err(type_error(op, &l, &r));
+            };
+            if b < BigInt::from(0) && matches!(op, T::Shl | T::Shr) {
+                return Err("invalid shift amount".to_string());
+            }
+            Ok(Value::from_bigint(match op {
+                T::BitAnd => a & b,
+                T::BitOr => a | b,
+                T::BitXor => a ^ b,
+                T::Shl => {
+                    let shift = u64::try_from(&b)
+                        .ok()
+                        .filter(|shift| a.bits().saturating_add(*shift) <= MAX_INT_BITS)
+                        .ok_or_else(|| too_large("shift amount"))?;
+                    a << shift
+                }
+                // Shifting past every bit leaves the sign.
+                _ => match usize::try_from(&b) {
+                    Ok(shift) => a >> shift,
+                    Err(_) => BigInt::from(if a < BigInt::from(0) { -1 } else { 0 }),
+                },
+            }))
+        }
+        _ => Err(format!("{:?} is not a binary operator", op)),
+    }
+}
+
+/// About how many bytes of string or big integer `binary(op, l, r)` makes,
+/// found without making it. Smaller operands make fewer.
+pub fn result_size(op: &TokenType, l: &Value, r: &Value) -> usize {
+    use TokenType as T;
+    // Integers fitting in 64 bits aren't big.
+    let bytes = |bits: u64| match bits {
+        0..=64 => 0,
+        _ => usize::try_from(bits.div_ceil(8)).unwrap_or(usize::MAX),
+    };
+    match (op, l, r) {
+        (T::Plus, Value::Str(a), Value::Str(b)) => a.len() + b.len(),
+        (T::Plus, Value::Str(s), _) | (T::Plus, _, Value::Str(s)) => s.len(),
+        (T::Plus, Value::Array(a), Value::Array(b)) => {
+            (a.borrow().len() + b.borrow().len()).saturating_mul(size_of::<Value>())
+        }
+        (T::Mul, Value::Str(s), Value::Integer(n)) | (T::Mul, Value::Integer(n), Value::Str(s)) => {
+            s.len().saturating_mul(usize::try_from(*n).unwrap_or(0))
+        }
+        (T::Plus | T::Minus, _, _) => bytes(bits(l).max(bits(r)) + 1),
+        (T::Mul, _, _) => bytes(bits(l) + bits(r)),
+        (T::Shl, _, _) => bytes(
+            bits(l).saturating_add(
+                r.as_int()
+                    .map_or(0, |b| u64::try_from(&b).unwrap_or(u64::MAX)),
+            ),
+        ),
+        // Powers of 0, 1 and -1 stay small.
+        (T::Pow, _, _) if bits(l) > 1 => bytes(bits(l).saturating_mul(match r {
+            Value::Integer(n) => u64::try_from(*n).unwrap_or(0),
+            Value::BigInteger(n) => u64::try_from(n).unwrap_or(u64::MAX),
+            _ => 0,
+        })),
+        _ => 0,
+    }
+}
+
+/// Bits of the integer `v`, or 0 if it isn't one.
+fn bits(v: &Value) -> u64 {
+    match v {
+        Value::Integer(i) => u64::from(64 - i.unsigned_abs().leading_zeros()),
+        Value::BigInteger(i) => i.bits(),
+        _ => 0,
+    }
+}
+
+fn compare(l: &Value, r: &Value) -> Option<Ordering> {
+    match (l, r) {
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        (Value::Double(_), _) | (_, Value::Double(_)) => {
+            l.as_double()?.partial_cmp(&r.as_double()?)
+        }
+        _ => Some(l.as_int()?.cmp(&r.as_int()?)),
+    }
+}
+
+fn arithmetic(op: &TokenType, l: Value, r: Value) -> Result<Value> {
+    use TokenType as T;
+    let is_double = matches!(l, Value::Double(_)) || matches!(r, Value::Double(_));
+    if is_double {
+        let (Some(a), Some(b)) = (l.as_double(), r.as_double()) else {
+            return Err(type_error(op, &l, &r));
+        };
+        return Ok(Value::Double(match op {
+            T::Plus => a + b,
+            T::Minus => a - b,
+            T::Mul => a * b,
+            T::Div => a / b,
+            T::Mod => a % b,
+            _ => a.powf(b),
+        }));
+    }
+
+    // Most arithmetic fits in 64 bits; the rest overflows into big integers.
+    if let (Value::Integer(a), Value::Integer(b)) = (&l, &r) {
+        let result = match op {
+            T::Plus => a.checked_add(*b),
+            T::Minus => a.checked_sub(*b),
+            T::Mul => a.checked_mul(*b),
+            T::Div | T::Mod if *b == 0 => return Err("division by zero".to_string()),
+            T::Div => a.checked_div(*b),
+            T::Mod => a.checked_rem(*b),
+            _ => None,
+        };
+        if let Some(i) = result {
+            return Ok(Value::Integer(i));
+        }
+    }
+
+    let (Some(a), Some(b)) = (l.as_int(), r.as_int()) else {
+        return Err(type_error(op, &l, &r));
+    };
+    let zero = BigInt::from(0);
+    Ok(match op {
+        T::Plus => Value::from_bigint(a + b),
+        T::Minus => Value::from_bigint(a - b),
+        T::Mul => Value::from_bigint(a * b),
+        T::Div | T::Mod if b == zero => return Err("division by zero".to_string()),
+        T::Div => Value::from_bigint(a / b),
+        T::Mod => Value::from_bigint(a % b),
+        // A negative power isn't an integer.
+        _ if b < zero => Value::Double(
+            l.as_double()
+                .unwrap_or(0.0)
+                .powf(r.as_double().unwrap_or(0.0)),
+        ),
+        _ => {
+            let exp = if a.bits() <= 1 && b > zero {
+                // Powers of 0, 1 and -1 only depend on whether it's odd.
+                if b.bit(0) { 1 } else { 2 }
+            } else {
+                u32::try_from(&b)
+                    .ok()
+                    .filter(|exp| a.bits().saturating_mul(u64::from(*exp)) <= MAX_INT_BITS)
+                    .ok_or_else(|| too_large("exponent"))?
+            };
+            Value::from_bigint(a.pow(exp))
+        }
+    })
+}
@@ -0,0 +1,105 @@
+//! Stack traces of runtime errors.
+//!
+//! The interpreter keeps a [`Frame`] for every call being made, and for the
+//! code outside of functions, noting where in it the frame is. When an error
+//! happens, the frames are copied into a [`StackTrace`] before the calls are
+//! left.
+
+use crate::format_error;
+
+use owo_colors::OwoColorize;
+use serde_json::json;
+
+/// A call that was being made when an error happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// None for the code outside of functions.
+    pub function: Option<String>,
+    /// The class of a method.
+    pub class: Option<String>,
+    pub file: String,
+    /// Where the frame is: the call it's making, or what failed in the
+    /// innermost one.
+    pub line: i32,
+    pub column: i32,
+    /// The text of line `line`, filled in when the trace is taken.
+    pub source: String,
+}
+
+impl Frame {
+    /// `` `f` ``, `` `Class.method` `` or `top level`.
+    fn describe(&self) -> String {
+        match (&self.class, &self.function) {
+            (Some(class), Some(function)) => format!("`{}.{}`", class, function),
+            (None, Some(function)) => format!("`{}`", function),
+            (_, None) => "top level".to_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "function": self.function,
+            "class": self.class,
+            "file": self.file,
+            "line": self.line,
+            "column": self.column,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackTrace {
+    pub message: String,
+    /// Outermost first.
+    pub frames: Vec<Frame>,
+}
+
+impl StackTrace {
+    /// The trace as the command line prints it: every frame with its line of
+    /// source, then the error under the line it happened at. Runs of the same
+    /// frame, as left by runaway recursion, are shown once.
+    pub fn render(&self) -> String {
+        let mut out = "Stack trace (most recent call last):".to_string();
+        let Some((innermost, outer)) = self.frames.split_last() else {
+            return self.message.clone();
+        };
+        let mut i = 0;
+        while i < outer.len() {
+            let frame = &outer[i];
+            let repeats = outer[i..].iter().take_while(|f| *f == frame).count();
+            out += &format!(
+                "\n  {} at {}:{} in file `{}`\n      {}\n      {}",
+                frame.describe(),
+                frame.line,
+                frame.column,
+                frame.file,
+                frame.source,
+                ("~".repeat(frame.column.max(0) as usize) + "^").red(),
+            );
+            if repeats > 1 {
+                out += &format!("\n  ... {} more times", repeats - 1);
+            }
+            i += repeats;
+        }
+        out += &format!(
+            "\n  {} at {}:{} in file `{}`\n{}",
+            innermost.describe(),
+            innermost.line,
+            innermost.column,
+            innermost.file,
+            format_error(
+                &innermost.source,
+                &self.message,
+                innermost.line,
+                innermost.column,
+                &innermost.file,
+            ),
+        );
+        out
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let frames = self.frames.iter().map(Frame::to_json).collect::<Vec<_>>();
+        json!({ "message": self.message, "frames": frames })
+    }
+}
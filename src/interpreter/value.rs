@@ -0,0 +1,310 @@
+use super::gc;
+use crate::Result;
+use crate::parser::Function;
+
+use num_bigint::BigInt;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+/// A runtime value. Arrays, dicts and objects are shared by reference.
+#[derive(Debug, Clone)]
+pub enum Value {
+    None,
+    Boolean(bool),
+    Integer(i64),
+    BigInteger(BigInt),
+    Double(f64),
+    Str(String),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Tuple(Rc<Vec<Value>>),
+    Dict(Rc<RefCell<BTreeMap<String, Value>>>),
+    /// A function, with the module whose globals it sees.
+    Function(Rc<Function>, ModuleId),
+    /// A function provided by the interpreter, by name.
+    Builtin(String),
+    Class(Rc<Class>),
+    Object(Rc<RefCell<Object>>),
+    /// A module imported with `use "path" as name`.
+    Module(Rc<Namespace>),
+}
+
+/// Index of the globals of a module in the interpreter.
+pub type ModuleId = usize;
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub methods: HashMap<String, Rc<Function>>,
+    /// Module whose globals the methods see.
+    pub module: ModuleId,
+}
+
+#[derive(Debug)]
+pub struct Object {
+    pub class: Rc<Class>,
+    pub attrs: BTreeMap<String, Value>,
+}
+
+/// What a module exports, by name.
+#[derive(Debug)]
+pub struct Namespace {
+    /// The path the module was imported with.
+    pub name: String,
+    pub exports: BTreeMap<String, Value>,
+}
+
+fn tracked(value: Value) -> Value {
+    gc::track(&value);
+    value
+}
+
+impl Value {
+    // Containers are made here only, so the collector knows all of them.
+
+    pub fn array(values: Vec<Value>) -> Self {
+        tracked(Value::Array(Rc::new(RefCell::new(values))))
+    }
+
+    pub fn dict(entries: BTreeMap<String, Value>) -> Self {
+        tracked(Value::Dict(Rc::new(RefCell::new(entries))))
+    }
+
+    pub fn tuple(values: Vec<Value>) -> Self {
+        tracked(Value::Tuple(Rc::new(values)))
+    }
+
+    /// A new instance of `class`, without attributes.
+    pub fn object(class: Rc<Class>) -> Self {
+        tracked(Value::Object(Rc::new(RefCell::new(Object {
+            class,
+            attrs: BTreeMap::new(),
+        }))))
+    }
+
+    /// Bytes of the string or big integer this is, or of the elements of the
+    /// array, or 0 for other values.
+    pub fn size(&self) -> usize {
+        match self {
+            Value::Str(s) => s.len(),
+            Value::BigInteger(i) => i.bits().div_ceil(8) as usize,
+            Value::Array(a) => a.borrow().len() * size_of::<Value>(),
+            _ => 0,
+        }
+    }
+
+    /// Name of the type of this value, as shown by `type()`.
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::None => "None".to_string(),
+            Value::Boolean(_) => "Bool".to_string(),
+            Value::Integer(_) | Value::BigInteger(_) => "Int".to_string(),
+            Value::Double(_) => "Double".to_string(),
+            Value::Str(_) => "Str".to_string(),
+            Value::Array(_) => "Array".to_string(),
+            Value::Tuple(_) => "Tuple".to_string(),
+            Value::Dict(_) => "Dict".to_string(),
+            Value::Function(..) | Value::Builtin(_) => "Function".to_string(),
+            Value::Class(_) => "Class".to_string(),
+            Value::Object(o) => o.borrow().class.name.clone(),
+            Value::Module(_) => "Module".to_string(),
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::None => false,
+            Value::Boolean(b) => *b,
+            Value::Integer(i) => *i != 0,
+            Value::BigInteger(i) => *i != BigInt::from(0),
+            Value::Double(d) => *d != 0.0,
+            Value::Str(s) => !s.is_empty(),
+            Value::Array(a) => !a.borrow().is_empty(),
+            Value::Tuple(t) => !t.is_empty(),
+            Value::Dict(d) => !d.borrow().is_empty(),
+            _ => true,
+        }
+    }
+
+    /// How the value is written in source, with strings quoted.
+    pub fn repr(&self) -> String {
+        match self {
+            Value::Str(s) => format!("{:?}", s),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Integers that fit in 64 bits are always stored as [`Value::Integer`].
+    pub fn from_bigint(i: BigInt) -> Self {
+        match i64::try_from(&i) {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::BigInteger(i),
+        }
+    }
+
+    pub fn as_int(&self) -> Option<BigInt> {
+        match self {
+            Value::Integer(i) => Some(BigInt::from(*i)),
+            Value::BigInteger(i) => Some(i.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn as_double(&self) -> Option<f64> {
+        match self {
+            Value::Integer(i) => Some(*i as f64),
+            Value::BigInteger(i) => i.to_string().parse().ok(),
+            Value::Double(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str> {
+        match self {
+            Value::Str(s) => Ok(s),
+            v => Err(format!("expected a Str, got {}", v.type_name())),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_seen(other, &mut vec![])
+    }
+}
+
+impl Value {
+    /// Address of the array or dict, which may contain itself.
+    fn container(&self) -> Option<*const ()> {
+        match self {
+            Value::Array(a) => Some(Rc::as_ptr(a).cast()),
+            Value::Dict(d) => Some(Rc::as_ptr(d).cast()),
+            _ => None,
+        }
+    }
+
+    /// Equality that holds for arrays and dicts containing themselves: pairs
+    /// in `seen` are being compared further up, and count as equal.
+    fn eq_seen(&self, other: &Self, seen: &mut Vec<(*const (), *const ())>) -> bool {
+        if let (Some(a), Some(b)) = (self.container(), other.container()) {
+            if seen.contains(&(a, b)) {
+                return true;
+            }
+            seen.push((a, b));
+        }
+        let eq = match (self, other) {
+            (Value::None, Value::None) => true,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Double(_), _) | (_, Value::Double(_)) => {
+                matches!((self.as_double(), other.as_double()), (Some(a), Some(b)) if a == b)
+            }
+            (Value::Integer(_) | Value::BigInteger(_), _) => {
+                other.as_int().is_some_and(|b| self.as_int() == Some(b))
+            }
+            (Value::Array(a), Value::Array(b)) => all_eq(&a.borrow(), &b.borrow(), seen),
+            (Value::Tuple(a), Value::Tuple(b)) => all_eq(a, b, seen),
+            (Value::Dict(a), Value::Dict(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|((ka, va), (kb, vb))| ka == kb && va.eq_seen(vb, seen))
+            }
+            (Value::Function(a, _), Value::Function(b, _)) => Rc::ptr_eq(a, b),
+            (Value::Builtin(a), Value::Builtin(b)) => a == b,
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Object(a), Value::Object(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        };
+        if self.container().is_some() && other.container().is_some() {
+            seen.pop();
+        }
+        eq
+    }
+
+    /// Write the value, quoting strings if `quoted`. Arrays and dicts in
+    /// `seen` are being written further up, and are shown as `[...]` and
+    /// `{...}`.
+    fn write(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        quoted: bool,
+        seen: &mut Vec<*const ()>,
+    ) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter<'_>, values: &[Value], seen: &mut Vec<_>| {
+            for (i, v) in values.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                v.write(f, true, seen)?;
+            }
+            Ok(())
+        };
+        if let Some(p) = self.container() {
+            if seen.contains(&p) {
+                return match self {
+                    Value::Array(_) => write!(f, "[...]"),
+                    _ => write!(f, "{{...}}"),
+                };
+            }
+            seen.push(p);
+        }
+        match self {
+            Value::None => write!(f, "none"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::BigInteger(i) => write!(f, "{}", i),
+            Value::Double(d) => write!(f, "{:?}", d),
+            Value::Str(s) if quoted => write!(f, "{:?}", s),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Array(a) => {
+                write!(f, "[")?;
+                list(f, &a.borrow(), seen)?;
+                write!(f, "]")
+            }
+            Value::Tuple(t) if t.len() == 1 => {
+                write!(f, "(")?;
+                t[0].write(f, true, seen)?;
+                write!(f, ",)")
+            }
+            Value::Tuple(t) => {
+                write!(f, "(")?;
+                list(f, t, seen)?;
+                write!(f, ")")
+            }
+            Value::Dict(d) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in d.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: ", k)?;
+                    v.write(f, true, seen)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Function(func, _) => write!(f, "<function {}>", func.name),
+            Value::Builtin(name) => write!(f, "<builtin {}>", name),
+            Value::Class(c) => write!(f, "<class {}>", c.name),
+            Value::Object(o) => write!(f, "<{} object>", o.borrow().class.name),
+            Value::Module(m) => write!(f, "<module {}>", m.name),
+        }?;
+        if self.container().is_some() {
+            seen.pop();
+        }
+        Ok(())
+    }
+}
+
+fn all_eq(a: &[Value], b: &[Value], seen: &mut Vec<(*const (), *const ())>) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_seen(b, seen))
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, false, &mut vec![])
+    }
+}
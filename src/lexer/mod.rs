@@ -1,8 +1,9 @@
 pub mod tokens;
 
-use crate::Result;
-use crate::lexer::tokens::{Token, TokenType};
+use crate::lexer::tokens::{StrPart, Token, TokenType, Trivia, TriviaKind};
+use crate::{Result, format_error};
 
+use num_bigint::BigInt;
 use std::iter::Peekable;
 use std::vec::IntoIter;
 use std::{fs, io};
@@ -13,8 +14,20 @@ use std::{fs, io};
 pub struct Lexer {
     /// The raw program characters.
     raw_data: Peekable<IntoIter<char>>,
+    /// All program characters, to recover token text in lossless mode.
+    source: Vec<char>,
+    /// Whether tokens keep their text and surrounding trivia.
+    lossless: bool,
+    /// The program split into lines, for error messages.
+    lines: Vec<String>,
+    /// The line number of the first of `lines`.
+    first_line: i32,
     pos: i32,
     line_no: i32,
+    /// Line and position of the first character of the current token.
+    start: (i32, i32),
+    /// Whether the [`TokenType::Eof`] token was produced already.
+    done: bool,
     file: String,
 }
 
@@ -24,31 +37,114 @@ impl Lexer {
     /// # Arguments
     /// * `file_path` - The path to the program file.
     pub fn from_file(file_path: &str) -> io::Result<Self> {
-        let mut contents = fs::read_to_string(file_path)?;
-        if contents.as_str() == "" {
-            return Ok(Self::from_text("", file_path));
-        }
-        if !contents.ends_with(';') {
-            contents += ";";
-        }
+        let contents = fs::read_to_string(file_path)?;
         Ok(Self::from_text(&contents, file_path))
     }
-    // adding a ';' as a temporary fix to the "EOF" errors.
-    // will be fixed soon
 
     /// Create a lexer with the program data in plain text.
     ///
     /// # Arguments
     /// * `text` - The raw program.
     pub fn from_text(text: &str, file_path: &str) -> Self {
+        let source = text.chars().collect::<Vec<_>>();
         Lexer {
-            raw_data: text.chars().collect::<Vec<_>>().into_iter().peekable(),
+            raw_data: source.clone().into_iter().peekable(),
+            source,
+            lossless: false,
+            lines: text.lines().map(String::from).collect(),
+            first_line: 1,
             pos: -1,
             line_no: 1,
+            start: (1, 0),
+            done: false,
             file: file_path.to_string(),
         }
     }
 
+    /// Create a lexer for text that starts after `line_no`:`pos` of
+    /// `file_path`, such as the expression inside `${...}`. `lines` are the
+    /// lines of the file the text is on, from `line_no`, so that error
+    /// snippets show the file rather than the text alone.
+    pub fn from_text_at(
+        text: &str,
+        file_path: &str,
+        (line_no, pos): (i32, i32),
+        lines: Vec<String>,
+    ) -> Self {
+        Lexer {
+            lines,
+            first_line: line_no,
+            pos,
+            line_no,
+            start: (line_no, pos),
+            ..Self::from_text(text, file_path)
+        }
+    }
+
+    /// Keep whitespace and comments as trivia on each token, so that
+    /// [`to_source`] gives back the original program.
+    pub fn lossless(mut self) -> Self {
+        self.lossless = true;
+        self
+    }
+
+    /// Number of characters eaten so far.
+    fn offset(&self) -> usize {
+        self.source.len() - self.raw_data.len()
+    }
+
+    /// Eat whitespace and comments. Stops before a newline if `same_line` is set.
+    /// The trivia is only recorded in lossless mode.
+    fn lex_trivia(&mut self, same_line: bool) -> Vec<Trivia> {
+        let mut trivia = vec![];
+        loop {
+            let kind = match self.raw_data.peek() {
+                Some(' ' | '\t' | '\r') => TriviaKind::Whitespace,
+                Some('\n') if !same_line => TriviaKind::Newline,
+                Some('#') => TriviaKind::Comment,
+                _ => return trivia,
+            };
+            let mut text = String::new();
+            match kind {
+                TriviaKind::Whitespace => {
+                    self.get_next_char_while(&mut text, |c| matches!(c, ' ' | '\t' | '\r'))
+                }
+                TriviaKind::Newline => text.extend(self.bump()),
+                TriviaKind::Comment => self.get_next_char_while(&mut text, |c| c != '\n'),
+            }
+            if self.lossless {
+                trivia.push(Trivia { kind, text });
+            }
+        }
+    }
+
+    /// Lex the whole program, returning every error if there were any.
+    pub fn tokenize(self) -> std::result::Result<Vec<Token>, Vec<String>> {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+        for t in self {
+            match t {
+                Ok(t) => tokens.push(t),
+                Err(e) => errors.push(e),
+            }
+        }
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Format an error at `line_no`:`pos` with the offending line.
+    fn error(&self, cause: &str, (line_no, pos): (i32, i32)) -> String {
+        let line = self
+            .lines
+            .get((line_no - self.first_line) as usize)
+            .map(String::as_str)
+            .unwrap_or_default();
+        format_error(line, cause, line_no, pos, &self.file)
+    }
+
     /// Create a token by eating characters while a condition is met.
     ///
     /// # Arguments
@@ -62,7 +158,7 @@ impl Lexer {
                         self.pos += 1;
                     } else {
                         self.line_no += 1;
-                        self.pos = 0;
+                        self.pos = -1;
                     };
                     raw_token.push(*c);
                     self.raw_data.next();
@@ -74,6 +170,264 @@ impl Lexer {
         }
     }
 
+    /// Eat the next character, keeping track of the position.
+    fn bump(&mut self) -> Option<char> {
+        let c = self.raw_data.next()?;
+        if c == '\n' {
+            self.line_no += 1;
+            self.pos = -1;
+        } else {
+            self.pos += 1;
+        }
+        Some(c)
+    }
+
+    /// Lex a string literal after its opening `quote`, handling escapes and,
+    /// if `interpolate` is set, `${expression}` parts.
+    ///
+    /// The whole literal is always consumed, so lexing can go on after an error.
+    fn lex_string(&mut self, quote: char, interpolate: bool) -> Result<TokenType> {
+        let mut parts = vec![];
+        let mut value = String::new();
+        let mut error = None;
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string literal", self.start)),
+                Some(c) if c == quote => break,
+                Some('\\') => {
+                    let at = (self.line_no, self.pos);
+                    match self.lex_escape() {
+                        Ok(c) => value.push(c),
+                        Err(e) => {
+                            error.get_or_insert(self.error(&e, at));
+                        }
+                    }
+                }
+                Some('$') if interpolate && self.raw_data.peek() == Some(&'{') => {
+                    let at = (self.line_no, self.pos);
+                    self.bump(); // Eat '{'
+                    let (line_no, pos) = (self.line_no, self.pos);
+                    let mut code = String::new();
+                    if self.scan_code(&mut code).is_none() {
+                        return Err(self.error("unterminated '${' in string", at));
+                    }
+                    if !value.is_empty() {
+                        parts.push(StrPart::Lit(std::mem::take(&mut value)));
+                    }
+                    let span = (line_no - self.first_line) as usize
+                        ..(self.line_no - self.first_line + 1) as usize;
+                    let lines = self.lines.get(span).unwrap_or_default().to_vec();
+                    parts.push(StrPart::Expr {
+                        code,
+                        line_no,
+                        pos,
+                        lines,
+                    });
+                }
+                Some(c) => value.push(c),
+            }
+        }
+        if let Some(e) = error {
+            return Err(e);
+        }
+        if parts.is_empty() {
+            return Ok(TokenType::Str(value));
+        }
+        if !value.is_empty() {
+            parts.push(StrPart::Lit(value));
+        }
+        Ok(TokenType::Interpolated(parts))
+    }
+
+    /// Copy the code of a `${...}` into `code`, up to the `}` closing it,
+    /// which is eaten. Braces in string literals don't count. None if the
+    /// text ends first.
+    fn scan_code(&mut self, code: &mut String) -> Option<()> {
+        let mut depth = 0;
+        loop {
+            match self.bump()? {
+                '}' if depth == 0 => return Some(()),
+                c @ ('"' | '\'' | '`') => {
+                    code.push(c);
+                    self.scan_quoted(c, code)?;
+                }
+                c => {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    code.push(c);
+                }
+            }
+        }
+    }
+
+    /// Copy a string literal inside a `${...}` into `code`, after its opening
+    /// `quote`, up to and including its closing one.
+    fn scan_quoted(&mut self, quote: char, code: &mut String) -> Option<()> {
+        loop {
+            let c = self.bump()?;
+            code.push(c);
+            match c {
+                c if c == quote => return Some(()),
+                '\\' => code.push(self.bump()?),
+                '$' if quote == '"' && self.raw_data.peek() == Some(&'{') => {
+                    code.push(self.bump()?);
+                    self.scan_code(code)?;
+                    code.push('}');
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Lex the character following a `\` in a string literal.
+    fn lex_escape(&mut self) -> Result<char> {
+        match self.bump() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('0') => Ok('\0'),
+            Some(c @ ('\\' | '"' | '\'' | '`' | '$')) => Ok(c),
+            Some('u') => {
+                if self.raw_data.peek() != Some(&'{') {
+                    return Err("expected '{' after '\\u'".to_string());
+                }
+                self.bump(); // Eat '{'
+                let mut hex = String::new();
+                self.get_next_char_while(&mut hex, |c| c.is_ascii_hexdigit());
+                if self.raw_data.peek() != Some(&'}') {
+                    return Err("expected '}' to close '\\u{'".to_string());
+                }
+                self.bump(); // Eat '}'
+
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(format!("invalid unicode escape '\\u{{{}}}'", hex))
+            }
+            Some(c) => Err(format!("unknown escape sequence '\\{}'", c)),
+            None => Err("unterminated string literal".to_string()),
+        }
+    }
+
+    /// Lex a triple-quoted string after its opening quotes. The contents are
+    /// kept verbatim, including newlines and backslashes.
+    fn lex_raw_string(&mut self, quote: char) -> Result<TokenType> {
+        let mut value = String::new();
+        let end = quote.to_string().repeat(3);
+        while let Some(c) = self.bump() {
+            value.push(c);
+            if value.ends_with(&end) {
+                value.truncate(value.len() - 3);
+                return Ok(TokenType::Str(value));
+            }
+        }
+        Err(self.error("unterminated string literal", self.start))
+    }
+
+    /// Lex an integer or float literal starting with the digit `first`.
+    ///
+    /// Supports `0x`, `0o` and `0b` prefixes, `_` separators between digits and
+    /// exponents. Integers that don't fit in 64 bits become [`TokenType::BigInteger`].
+    fn lex_number(&mut self, first: char) -> Result<TokenType> {
+        let radix = match (first, self.raw_data.peek()) {
+            ('0', Some('x' | 'X')) => 16,
+            ('0', Some('o' | 'O')) => 8,
+            ('0', Some('b' | 'B')) => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            let prefix = self.bump().unwrap_or_default();
+            let kind = match radix {
+                16 => "hexadecimal",
+                8 => "octal",
+                _ => "binary",
+            };
+            let mut value = String::new();
+            self.get_next_char_while(&mut value, Self::is_in_identifier);
+            if value.is_empty() {
+                return Err(format!("missing digits after '0{}'", prefix));
+            }
+            let digits = Self::strip_separators(&value, radix)?;
+            if let Some(c) = digits.chars().find(|c| !c.is_digit(radix)) {
+                return Err(format!(
+                    "invalid digit '{}' in {} literal '0{}{}'",
+                    c, kind, prefix, value
+                ));
+            }
+            return Ok(Self::integer_token(&digits, radix));
+        }
+
+        let mut value = first.to_string();
+        let is_digit = |c: char| c.is_ascii_digit() || c == '_';
+        self.get_next_char_while(&mut value, is_digit);
+        let mut is_float = false;
+        if self.raw_data.peek() == Some(&'.') {
+            is_float = true;
+            value.push(self.bump().unwrap_or_default()); // eat '.'
+            self.get_next_char_while(&mut value, is_digit);
+        }
+        if let Some('e' | 'E') = self.raw_data.peek() {
+            is_float = true;
+            value.push(self.bump().unwrap_or_default()); // eat 'e'
+            if let Some('+' | '-') = self.raw_data.peek() {
+                value.push(self.bump().unwrap_or_default());
+            }
+            let mut exponent = String::new();
+            self.get_next_char_while(&mut exponent, is_digit);
+            if exponent.is_empty() {
+                return Err(format!("missing digits in exponent of '{}'", value));
+            }
+            value += &exponent;
+        }
+        // Letters directly after a number, like `12abc`
+        let mut suffix = String::new();
+        self.get_next_char_while(&mut suffix, Self::is_in_identifier);
+        if !suffix.is_empty() {
+            return Err(format!(
+                "invalid suffix '{}' on number literal '{}'",
+                suffix, value
+            ));
+        }
+
+        let digits = Self::strip_separators(&value, 10)?;
+        if is_float {
+            match digits.parse() {
+                Ok(f) => Ok(TokenType::Double(f)),
+                Err(_) => Err(format!("float literal {} is invalid", value)),
+            }
+        } else {
+            Ok(Self::integer_token(&digits, 10))
+        }
+    }
+
+    /// Remove `_` separators, which are only allowed between two digits.
+    fn strip_separators(value: &str, radix: u32) -> Result<String> {
+        let chars = value.chars().collect::<Vec<_>>();
+        for (i, c) in chars.iter().enumerate() {
+            let is_digit = |j: usize| chars.get(j).is_some_and(|c| c.is_digit(radix.max(10)));
+            if *c == '_' && (i == 0 || !is_digit(i - 1) || !is_digit(i + 1)) {
+                return Err(format!(
+                    "'_' must separate two digits in number literal '{}'",
+                    value
+                ));
+            }
+        }
+        Ok(value.replace('_', ""))
+    }
+
+    fn integer_token(digits: &str, radix: u32) -> TokenType {
+        match i64::from_str_radix(digits, radix) {
+            Ok(i) => TokenType::Integer(i),
+            // Digits were validated already, so this can't fail.
+            Err(_) => TokenType::BigInteger(
+                BigInt::parse_bytes(digits.as_bytes(), radix).unwrap_or_default(),
+            ),
+        }
+    }
+
     /// Check if a character is a part of an identifier.
     ///
     /// Identifiers must start with an alphabetic character or underscore, and then can have
@@ -84,40 +438,38 @@ impl Lexer {
     }
 }
 
+/// Rebuild the program text from tokens of a lossless [`Lexer`].
+pub fn to_source(tokens: &[Token]) -> String {
+    let mut source = String::new();
+    for t in tokens {
+        t.leading_trivia.iter().for_each(|t| source += &t.text);
+        source += &t.text;
+        t.trailing_trivia.iter().for_each(|t| source += &t.text);
+    }
+    source
+}
+
 impl Iterator for Lexer {
     type Item = Result<Token>;
 
     /// Identifies the next token
     fn next(&mut self) -> Option<Self::Item> {
         let token: Result<TokenType>;
-        let current_char: char;
         // Find first non-whitespace character
-        loop {
-            match self.raw_data.next() {
-                Some(' ') | Some('\t') => {
-                    self.pos += 1;
-                    continue;
-                }
-                Some('\n') => {
-                    self.line_no += 1;
-                    self.pos = 0;
-                    continue;
-                }
-                // Comment
-                Some('#') => {
-                    let mut dump = String::new();
-                    self.get_next_char_while(&mut dump, |c| c != '\n');
-                    // println!("Lexing comment");
-                    continue;
-                }
-                Some(c) => {
-                    current_char = c;
-                    self.pos += 1;
-                    break;
-                }
-                None => return None,
+        let leading_trivia = self.lex_trivia(false);
+        let start_offset = self.offset();
+        let current_char = match self.bump() {
+            Some(c) => c,
+            None if self.done => return None,
+            None => {
+                self.done = true;
+                let mut eof = Token::new(TokenType::Eof, self.pos + 1, self.line_no, &self.file);
+                eof.offset = self.offset();
+                eof.leading_trivia = leading_trivia;
+                return Some(Ok(eof));
             }
-        }
+        };
+        self.start = (self.line_no, self.pos);
 
         // println!("First char: {}", current_char);
 
@@ -132,6 +484,9 @@ impl Iterator for Lexer {
                 s if *"def" == s => token = Ok(TokenType::Def),
                 s if *"extern" == s => token = Ok(TokenType::Extern),
                 s if *"use" == s => token = Ok(TokenType::Use),
+                s if *"pub" == s => token = Ok(TokenType::Pub),
+                s if *"as" == s => token = Ok(TokenType::As),
+                s if *"from" == s => token = Ok(TokenType::From),
                 s if *"return" == s => token = Ok(TokenType::Return),
                 s if *"true" == s => token = Ok(TokenType::True),
                 s if *"false" == s => token = Ok(TokenType::False),
@@ -144,53 +499,32 @@ impl Iterator for Lexer {
                 s if *"await" == s => token = Ok(TokenType::Await),
                 s if *"none" == s => token = Ok(TokenType::None),
                 s if *"then" == s => token = Ok(TokenType::Then),
+                s if *"and" == s => token = Ok(TokenType::And),
+                s if *"or" == s => token = Ok(TokenType::Or),
                 s => token = Ok(TokenType::Identifier(s)),
             };
         }
-        // Integer Literal
-        else if current_char.is_numeric() {
-            let mut value = current_char.to_string();
-            self.get_next_char_while(&mut value, |c| c.is_numeric());
-
-            // println!("{:?}", self.raw_data.peek());
-
-            if self.raw_data.peek() == Some(&'.') {
-                value += ".";
-                self.raw_data.next(); // eat '.'
-                self.get_next_char_while(&mut value, |c| c.is_numeric());
-                token = match value.parse() {
-                    Ok(i) => Ok(TokenType::Double(i)),
-                    Err(_) => Err(format!("float literal {} is invalid", value)),
+        // Number Literal
+        else if current_char.is_ascii_digit() {
+            token = self
+                .lex_number(current_char)
+                .map_err(|e| self.error(&e, self.start));
+        }
+        // String Literal
+        else if current_char == '"' || current_char == '\'' {
+            if self.raw_data.peek() == Some(&current_char) {
+                self.bump(); // Eat second quote
+                if self.raw_data.peek() == Some(&current_char) {
+                    self.bump(); // Eat third quote
+                    token = self.lex_raw_string(current_char);
+                } else {
+                    token = Ok(TokenType::Str(String::new()));
                 }
             } else {
-                token = match value.parse() {
-                    Ok(i) => Ok(TokenType::Integer(i)),
-                    Err(_) => Err(format!("Integer literal {} is invalid", value)),
-                }
+                token = self.lex_string(current_char, current_char == '"');
             }
-        }
-        // String Literal
-        else if current_char == '"' {
-            let mut value = String::new();
-
-            self.get_next_char_while(&mut value, |c| c != '"');
-            self.raw_data.next(); // Eat trailing "
-
-            token = Ok(TokenType::Str(value));
-        } else if current_char == '\'' {
-            let mut value = String::new();
-
-            self.get_next_char_while(&mut value, |c| c != '\'');
-            self.raw_data.next(); // Eat trailing "
-
-            token = Ok(TokenType::Str(value));
         } else if current_char == '`' {
-            let mut value = String::new();
-
-            self.get_next_char_while(&mut value, |c| c != '`');
-            self.raw_data.next(); // Eat trailing "
-
-            token = Ok(TokenType::Str(value));
+            token = self.lex_string('`', false);
         }
         // Semicolon
         else if current_char == ';' {
@@ -253,11 +587,14 @@ impl Iterator for Lexer {
                 token = Ok(TokenType::Minus);
             }
         }
-        // Mul and MulEq
+        // Mul, MulEq and Pow
         else if current_char == '*' {
             if self.raw_data.peek() == Some(&'=') {
                 self.raw_data.next(); // Eat =
                 token = Ok(TokenType::MulEq);
+            } else if self.raw_data.peek() == Some(&'*') {
+                self.raw_data.next(); // Eat *
+                token = Ok(TokenType::Pow);
             } else {
                 token = Ok(TokenType::Mul);
             }
@@ -271,24 +608,46 @@ impl Iterator for Lexer {
                 token = Ok(TokenType::Div);
             }
         }
-        // Less and LessEq
+        // Less, LessEq and Shl
         else if current_char == '<' {
             if self.raw_data.peek() == Some(&'=') {
                 self.raw_data.next(); // Eat =
                 token = Ok(TokenType::LessEq);
+            } else if self.raw_data.peek() == Some(&'<') {
+                self.raw_data.next(); // Eat <
+                token = Ok(TokenType::Shl);
             } else {
                 token = Ok(TokenType::Less);
             }
         }
-        // Greater and GreaterEq
+        // Greater, GreaterEq and Shr
         else if current_char == '>' {
             if self.raw_data.peek() == Some(&'=') {
                 self.raw_data.next(); // Eat =
                 token = Ok(TokenType::GreaterEq);
+            } else if self.raw_data.peek() == Some(&'>') {
+                self.raw_data.next(); // Eat >
+                token = Ok(TokenType::Shr);
             } else {
-                token = Ok(TokenType::GreaterEq);
+                token = Ok(TokenType::Greater);
             }
         }
+        // Mod
+        else if current_char == '%' {
+            token = Ok(TokenType::Mod);
+        }
+        // BitAnd
+        else if current_char == '&' {
+            token = Ok(TokenType::BitAnd);
+        }
+        // BitOr
+        else if current_char == '|' {
+            token = Ok(TokenType::BitOr);
+        }
+        // BitXor
+        else if current_char == '^' {
+            token = Ok(TokenType::BitXor);
+        }
         // Assign and Equal
         else if current_char == '=' {
             if self.raw_data.peek() == Some(&'=') {
@@ -310,14 +669,21 @@ impl Iterator for Lexer {
                 token = Ok(TokenType::Not);
             }
         } else {
-            token = Ok(TokenType::Unknown)
+            token = Err(self.error(
+                &format!("unexpected character '{}'", current_char),
+                self.start,
+            ));
         }
 
-        Some(Ok(Token {
-            type_: token.unwrap(),
-            pos: self.pos,
-            line_no: self.line_no,
-            file: self.file.clone(),
+        Some(token.map(|type_| {
+            let mut token = Token::new(type_, self.pos, self.line_no, &self.file);
+            token.offset = start_offset;
+            if self.lossless {
+                token.leading_trivia = leading_trivia;
+                token.text = self.source[start_offset..self.offset()].iter().collect();
+                token.trailing_trivia = self.lex_trivia(true);
+            }
+            token
         }))
     }
 }
@@ -325,7 +691,15 @@ impl Iterator for Lexer {
 #[cfg(test)]
 mod tests {
 
-    use super::Lexer;
+    use super::{Lexer, to_source};
+    use crate::lexer::tokens::{StrPart, TokenType, Trivia, TriviaKind};
+
+    fn lex(src: &str) -> Vec<TokenType> {
+        Lexer::from_text(src, "<test>")
+            .map(|t| t.unwrap().type_)
+            .filter(|t| *t != TokenType::Eof)
+            .collect()
+    }
 
     #[test]
     fn is_in_identifier() {
@@ -337,4 +711,187 @@ mod tests {
             assert!(!Lexer::is_in_identifier(s));
         }
     }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            lex("a and b or c % d ** e & f | g ^ h << i >> j > k"),
+            vec![
+                TokenType::Identifier("a".into()),
+                TokenType::And,
+                TokenType::Identifier("b".into()),
+                TokenType::Or,
+                TokenType::Identifier("c".into()),
+                TokenType::Mod,
+                TokenType::Identifier("d".into()),
+                TokenType::Pow,
+                TokenType::Identifier("e".into()),
+                TokenType::BitAnd,
+                TokenType::Identifier("f".into()),
+                TokenType::BitOr,
+                TokenType::Identifier("g".into()),
+                TokenType::BitXor,
+                TokenType::Identifier("h".into()),
+                TokenType::Shl,
+                TokenType::Identifier("i".into()),
+                TokenType::Shr,
+                TokenType::Identifier("j".into()),
+                TokenType::Greater,
+                TokenType::Identifier("k".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            lex(r#""a\n\t\"\\\u{48}" 'it\'s' """raw \n
+"quoted" text""""#),
+            vec![
+                TokenType::Str("a\n\t\"\\H".into()),
+                TokenType::Str("it's".into()),
+                TokenType::Str("raw \\n\n\"quoted\" text".into()),
+            ]
+        );
+        assert!(
+            Lexer::from_text(r#""\q""#, "<test>")
+                .next()
+                .unwrap()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn interpolation() {
+        assert_eq!(
+            lex(r#""age: ${age + 1}!" "\${x}""#),
+            vec![
+                TokenType::Interpolated(vec![
+                    StrPart::Lit("age: ".into()),
+                    StrPart::Expr {
+                        code: "age + 1".into(),
+                        line_no: 1,
+                        pos: 7,
+                        lines: vec![r#""age: ${age + 1}!" "\${x}""#.into()],
+                    },
+                    StrPart::Lit("!".into()),
+                ]),
+                TokenType::Str("${x}".into()),
+            ]
+        );
+
+        // Strings in the code can have braces and quotes in them.
+        let code = |src: &str| match &lex(src)[..] {
+            [TokenType::Interpolated(parts)] => match &parts[1] {
+                StrPart::Expr { code, .. } => code.clone(),
+                part => panic!("{:?}", part),
+            },
+            tokens => panic!("{:?}", tokens),
+        };
+        assert_eq!(code(r#""a${"}"}b""#), r#""}""#);
+        assert_eq!(code(r#""a${"\"}" + '{'}b""#), r#""\"}" + '{'"#);
+        assert_eq!(code(r#""a${"x${"}"}"}b""#), r#""x${"}"}""#);
+        assert_eq!(lex("\"a${\"}\"}b\"\nx").len(), 2);
+
+        // Errors inside `${...}` point into the line of the file.
+        let e = crate::parser::parse_source("x = 1\ny = \"a ${x @ 2} b\"", "<test>").unwrap_err();
+        assert!(e.starts_with("\ny = \"a ${x @ 2} b\"\n"), "{:?}", e);
+        assert!(e.contains("unexpected character '@'"), "{}", e);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            lex("0xFF 0o17 0b1010 1_000_000 1e9 2.5E-3 1.5 9223372036854775807"),
+            vec![
+                TokenType::Integer(255),
+                TokenType::Integer(15),
+                TokenType::Integer(10),
+                TokenType::Integer(1_000_000),
+                TokenType::Double(1e9),
+                TokenType::Double(2.5e-3),
+                TokenType::Double(1.5),
+                TokenType::Integer(i64::MAX),
+            ]
+        );
+        assert_eq!(
+            lex("9223372036854775808"),
+            vec![TokenType::BigInteger(
+                "9223372036854775808".parse().unwrap()
+            )]
+        );
+
+        let err = |src| Lexer::from_text(src, "<test>").next().unwrap().unwrap_err();
+        assert!(err("0x").contains("missing digits"));
+        assert!(err("0b102").contains("invalid digit '2' in binary literal"));
+        assert!(err("0xFG").contains("invalid digit 'G'"));
+        assert!(err("1__0").contains("'_' must separate two digits"));
+        assert!(err("1_").contains("'_' must separate two digits"));
+        assert!(err("1e").contains("missing digits in exponent"));
+        assert!(err("12abc").contains("invalid suffix 'abc'"));
+    }
+
+    #[test]
+    fn errors_keep_lexing() {
+        let src = "a @ \"x\\q\" b\n'unterminated";
+        let items = Lexer::from_text(src, "<test>").collect::<Vec<_>>();
+        assert_eq!(items.len(), 6);
+        assert!(
+            items[1]
+                .as_ref()
+                .unwrap_err()
+                .contains("unexpected character '@'")
+        );
+        assert!(
+            items[2]
+                .as_ref()
+                .unwrap_err()
+                .contains("unknown escape sequence")
+        );
+        assert_eq!(
+            items[3].as_ref().unwrap().type_,
+            TokenType::Identifier("b".into())
+        );
+        assert!(
+            items[4]
+                .as_ref()
+                .unwrap_err()
+                .contains("unterminated string")
+        );
+
+        let errors = Lexer::from_text(src, "<test>").tokenize().unwrap_err();
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn lossless_round_trip() {
+        let src = "# header\ndef f(a: i32) -> Any do  # trailing\n\ta + 1\r\nend\n\n";
+        let tokens = Lexer::from_text(src, "<test>")
+            .lossless()
+            .tokenize()
+            .unwrap();
+        assert_eq!(to_source(&tokens), src);
+
+        assert_eq!(
+            tokens[0].leading_trivia,
+            vec![
+                Trivia {
+                    kind: TriviaKind::Comment,
+                    text: "# header".into()
+                },
+                Trivia {
+                    kind: TriviaKind::Newline,
+                    text: "\n".into()
+                },
+            ]
+        );
+        let do_ = tokens.iter().find(|t| t.type_ == TokenType::Do).unwrap();
+        assert_eq!(do_.trailing_trivia.len(), 2);
+        assert_eq!(do_.trailing_trivia[1].text, "# trailing");
+
+        for (path, src) in crate::example_sources() {
+            let tokens = Lexer::from_text(&src, "<test>").lossless().tokenize();
+            assert_eq!(to_source(&tokens.unwrap()), src, "{:?}", path);
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use num_bigint::BigInt;
+
 /// A token that is parsed by the [`Lexer`].
 ///
 /// [`Lexer`]: ../struct.Lexer.html
@@ -13,6 +15,9 @@ pub enum TokenType {
     Class,  // class
     Extern, // extern
     Use,    // use
+    Pub,    // pub
+    As,     // as
+    From,   // from
     Return, // return
     True,   // true
     False,  // false
@@ -22,10 +27,17 @@ pub enum TokenType {
     End,    // end
     None,   // none
     Then,   // then
+    /// Short-circuiting logical operators
+    And, // and
+    Or,     // or
 
     /// Literals
-    Integer(i32),
+    Integer(i64),
+    /// An integer literal too large for 64 bits.
+    BigInteger(BigInt),
     Str(String),
+    /// A string literal containing `${expression}` parts.
+    Interpolated(Vec<StrPart>),
     Double(f64),
 
     /// Punctuators
@@ -54,6 +66,13 @@ pub enum TokenType {
     Equal,     // ==
     Not,       // !
     NotEq,     // !=
+    Mod,       // %
+    Pow,       // **
+    BitAnd,    // &
+    BitOr,     // |
+    BitXor,    // ^
+    Shl,       // <<
+    Shr,       // >>
 
     /// AugAssign operators
     PlusEq, // +=
@@ -65,7 +84,48 @@ pub enum TokenType {
     Async, // async
     Await, // await
 
-    Unknown,
+    /// End of input, produced once after the last token.
+    Eof,
+}
+
+impl TokenType {
+    /// Binding power of a binary operator, or -1 if this is not one.
+    pub fn precedence(&self) -> i32 {
+        match self {
+            TokenType::Or => 0,
+            TokenType::And => 1,
+            TokenType::Equal
+            | TokenType::NotEq
+            | TokenType::Greater
+            | TokenType::GreaterEq
+            | TokenType::Less
+            | TokenType::LessEq => 2,
+            TokenType::BitOr => 3,
+            TokenType::BitXor => 4,
+            TokenType::BitAnd => 5,
+            TokenType::Shl | TokenType::Shr => 6,
+            TokenType::Minus | TokenType::Plus => 7,
+            TokenType::Div | TokenType::Mul | TokenType::Mod => 8,
+            TokenType::Pow => 9,
+            TokenType::Dot => 10,
+            _ => -1,
+        }
+    }
+}
+
+/// A piece of an interpolated string literal.
+#[derive(Debug, PartialEq, Clone)]
+pub enum StrPart {
+    /// Literal text, with escapes already processed.
+    Lit(String),
+    /// The source of an embedded `${...}` expression and where it starts.
+    Expr {
+        code: String,
+        line_no: i32,
+        pos: i32,
+        /// The lines of the file the expression is on, for error snippets.
+        lines: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -74,4 +134,45 @@ pub struct Token {
     pub pos: i32,
     pub line_no: i32,
     pub file: String,
+    /// Index of the first character of the token in the source.
+    pub offset: usize,
+    /// Whitespace, newlines and comments before the token. Only kept by a
+    /// lossless [`Lexer`](../struct.Lexer.html).
+    pub leading_trivia: Vec<Trivia>,
+    /// The source text of the token. Only kept by a lossless lexer.
+    pub text: String,
+    /// Whitespace and a comment after the token, up to the end of its line.
+    /// Only kept by a lossless lexer.
+    pub trailing_trivia: Vec<Trivia>,
+}
+
+impl Token {
+    pub fn new(type_: TokenType, pos: i32, line_no: i32, file: &str) -> Self {
+        Token {
+            type_,
+            pos,
+            line_no,
+            file: file.to_string(),
+            offset: 0,
+            leading_trivia: vec![],
+            text: String::new(),
+            trailing_trivia: vec![],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriviaKind {
+    /// Spaces, tabs and carriage returns.
+    Whitespace,
+    Newline,
+    /// A `#` comment, without the newline ending it.
+    Comment,
+}
+
+/// Source text between tokens, which doesn't affect the program.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
 }
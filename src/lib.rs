@@ -1,20 +1,62 @@
+pub mod builtins;
+pub mod engine;
+pub mod formatter;
+pub mod interpreter;
 pub mod lexer;
+pub mod lsp;
+pub mod mir;
+pub mod modules;
+pub mod native;
+pub mod optimizer;
 pub mod parser;
+pub mod repl;
+pub mod resolver;
+pub mod sandbox;
+pub mod syntax;
 
-use clap::{arg, command};
+pub use engine::{Engine, Error, ErrorKind, FromValue, IntoValue};
+pub use interpreter::Value;
+
+use clap::{Command, arg, command};
+use owo_colors::OwoColorize;
 use std::path;
 
-#[macro_export]
-macro_rules! unwrap_some {
-    ($val:expr) => {
-        match $val {
-            Some(s) => s,
-            None => return Err("EOF".to_string()),
-        }
-    };
+pub type Result<T> = std::result::Result<T, String>;
+
+/// Path and text of every example program, for tests to run over.
+#[cfg(test)]
+pub(crate) fn example_sources() -> impl Iterator<Item = (path::PathBuf, String)> {
+    ["examples", "iorekfiles"]
+        .into_iter()
+        .flat_map(|dir| {
+            let dir = path::Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+            std::fs::read_dir(dir).unwrap()
+        })
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "lyr"))
+        .map(|path| {
+            let src = std::fs::read_to_string(&path).unwrap();
+            (path, src)
+        })
 }
 
-pub type Result<T> = std::result::Result<T, String>;
+/// Render `cause` below the offending source `line`, with a caret under `pos`.
+pub fn format_error(line: &str, cause: &str, line_no: i32, pos: i32, file: &str) -> String {
+    format!(
+        "
+{text}
+{pointy}
+{cause}
+
+    at {line}:{pos} in file `{file}`.",
+        text = line,
+        pointy = ("~".repeat(pos.max(0) as usize) + "^").red(),
+        cause = cause.yellow(),
+        line = line_no.green(),
+        pos = pos.green(),
+        file = file.green()
+    )
+}
 
 /// CLI input configuration and parameters.
 pub struct CLIInput {
@@ -42,23 +84,71 @@ pub fn init_cli() -> CLIInput {
         //         .index(1),
         // )
         .infer_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
         .arg(arg!([input] "Path to the lyron file to run").required(true))
+        .arg(arg!(--external <DIR> "Directory that `use \"@:...\"` modules are loaded from"))
+        .arg(arg!(--stdlib <DIR> "Directory that `use \"std:...\"` modules are loaded from, instead of the built-in standard library"))
+        .arg(
+            arg!(--"gc-threshold" <N> "Allocations between garbage collections")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(arg!(--sandbox "Only allow builtins reaching outside the program if granted with --allow"))
+        .arg(
+            arg!(--allow <CAPABILITY> "Grant a capability to a sandboxed program")
+                .value_parser(clap::value_parser!(sandbox::Capability))
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            arg!(--"max-instructions" <N> "Stop the program after evaluating N expressions")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"max-depth" <N> "Stop the program when calls nest deeper than N (default 10000)")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"max-heap" <N> "Stop the program when more than N arrays, dicts, objects and tuples are alive")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"max-memory" <BYTES> "Stop the program when it would make more than BYTES of strings, big integers and array elements")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"time-limit" <MS> "Stop the program after MS milliseconds")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(arg!(--"no-optimize" "Run the program as written, without folding constants or removing dead code"))
+        .arg(
+            arg!(--emit <WHAT> "Print the program lowered to WHAT instead of running it")
+                .value_parser(["ir"]),
+        )
+        .arg(
+            arg!(--"error-format" <FORMAT> "How errors of the program are printed")
+                .value_parser(["human", "json"])
+                .default_value("human"),
+        )
+        .subcommand(
+            Command::new("fmt")
+                .about("Format lyron files in place")
+                .arg(arg!(--check "Only report the files that aren't formatted"))
+                .arg(arg!(<files> ... "Paths to the lyron files to format")),
+        )
+        .subcommand(Command::new("lsp").about("Start a language server on stdio"))
+        .subcommand(Command::new("repl").about("Start an interactive session"))
         .get_matches();
 
-    let input_path = match matches.get_one::<String>("input") {
-        Some(v) => v,
-        _ => {
-            println!("[input] needed",);
-            unreachable!()
-        }
-    };
+    // Subcommands take their own paths.
+    let input_path = matches
+        .get_one::<String>("input")
+        .map_or("", String::as_str);
 
     // let input_path = matches.value_of("input").unwrap();
     let input_name = path::Path::new(input_path)
         .file_stem()
-        .unwrap()
-        .to_str()
-        .unwrap();
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
 
     CLIInput {
         input_path: String::from(input_path),
@@ -0,0 +1,669 @@
+//! Editor features for one document, computed from its syntax tree.
+
+use crate::builtins::Registry;
+use crate::lexer::Lexer;
+use crate::modules::{self, ModuleLoader};
+use crate::parser::{AstNode, ExprValue, Parser};
+use crate::resolver::Resolver;
+use crate::syntax::ast::{ClassDef, FnDef, Param, SourceFile, TypedNode, UseExpr};
+use crate::syntax::{SyntaxError, SyntaxKind, SyntaxNode, SyntaxToken, parse};
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DocumentSymbol, Position, Range, SymbolKind,
+};
+use rowan::{TextRange, TextSize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use SyntaxKind as K;
+
+/// A source file and its syntax tree.
+pub struct Document {
+    pub path: PathBuf,
+    text: String,
+    root: SyntaxNode,
+    errors: Vec<SyntaxError>,
+    /// Byte offset of the start of each line.
+    line_starts: Vec<usize>,
+}
+
+/// Something a name can refer to.
+enum Symbol {
+    Fn(FnDef),
+    Class(ClassDef),
+    Param(Param),
+}
+
+impl Document {
+    pub fn new(path: PathBuf, text: String) -> Self {
+        let parse = parse(&text);
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Document {
+            path,
+            root: parse.syntax(),
+            errors: parse.errors,
+            text,
+            line_starts,
+        }
+    }
+
+    fn file(&self) -> SourceFile {
+        SourceFile::cast(self.root.clone()).expect("the root is a source file")
+    }
+
+    fn position(&self, offset: TextSize) -> Position {
+        let offset = usize::from(offset).min(self.text.len());
+        let line = self.line_starts.partition_point(|&s| s <= offset) - 1;
+        let character = self.text[self.line_starts[line]..offset]
+            .encode_utf16()
+            .count();
+        Position::new(line as u32, character as u32)
+    }
+
+    fn offset(&self, position: Position) -> TextSize {
+        let Some(&start) = self.line_starts.get(position.line as usize) else {
+            return TextSize::of(self.text.as_str());
+        };
+        let (mut units, mut offset) = (0, start);
+        for c in self.text[start..].chars() {
+            if c == '\n' || units >= position.character as usize {
+                break;
+            }
+            units += c.len_utf16();
+            offset += c.len_utf8();
+        }
+        TextSize::from(offset as u32)
+    }
+
+    fn range(&self, range: TextRange) -> Range {
+        Range::new(self.position(range.start()), self.position(range.end()))
+    }
+
+    /// The character at a compiler position: a 1-based line and a column.
+    fn point(&self, line_no: i32, pos: i32) -> Range {
+        let line = (line_no.max(1) - 1) as usize;
+        let start = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.text.len());
+        let offset = self.text[start..]
+            .char_indices()
+            .nth(pos.max(0) as usize)
+            .map_or(self.text.len(), |(i, _)| start + i);
+        let offset = TextSize::from(offset as u32);
+        Range::new(
+            self.position(offset),
+            self.position(offset + TextSize::from(1)),
+        )
+    }
+
+    /// The text of a 1-based line, without indentation.
+    fn line(&self, line_no: i32) -> Range {
+        let line = (line_no.max(1) - 1) as usize;
+        let start = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.text.len());
+        let text = self.text[start..].lines().next().unwrap_or("");
+        let indent = text.len() - text.trim_start().len();
+        let range = TextRange::at(
+            TextSize::from((start + indent) as u32),
+            TextSize::of(text.trim()),
+        );
+        self.range(range)
+    }
+
+    /// Errors of the lexer, parser and resolver.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self
+            .errors
+            .iter()
+            .map(|e| diagnostic(self.range(e.range), &e.message))
+            .collect::<Vec<_>>();
+        if !diagnostics.is_empty() {
+            return diagnostics;
+        }
+
+        // The compiler checks more than the grammar does.
+        let file = self.path.to_string_lossy();
+        let Ok(tokens) = Lexer::from_text(&self.text, &file).tokenize() else {
+            return diagnostics;
+        };
+        let mut parser = Parser::new(tokens.into_iter().peekable(), &file).with_source(&self.text);
+        match parser.parse_program() {
+            Ok(ast) => {
+                let mut loader = ModuleLoader::new();
+                for (node, pos) in &ast {
+                    if let AstNode::Expression(ExprValue::Use(spec, _)) = node
+                        && let Err(e) = loader.load(spec, &self.path)
+                    {
+                        diagnostics.push(diagnostic(self.line(pos.line_no), &e));
+                    }
+                }
+                // Resolver errors are located by statement, so mark the line.
+                for (message, pos) in Resolver::new().check_program(&ast) {
+                    diagnostics.push(diagnostic(self.line(pos.line_no), &message));
+                }
+            }
+            Err(_) => {
+                if let Some((cause, (line_no, pos))) = parser.last_error() {
+                    diagnostics.push(diagnostic(self.point(*line_no, *pos), cause));
+                }
+            }
+        }
+        diagnostics
+    }
+
+    /// Signature of the function, class or parameter under the cursor, as markdown.
+    pub fn hover(&self, position: Position) -> Option<String> {
+        let token = self.ident_at(position)?;
+        let text = match self.lookup(&token) {
+            Some(Symbol::Fn(f)) => fn_signature(&f),
+            Some(Symbol::Class(c)) => class_signature(&c),
+            Some(Symbol::Param(p)) => param_signature(&p),
+            None => Registry::standard().get(token.text())?.to_string(),
+        };
+        Some(format!("```lyron\n{}\n```", text))
+    }
+
+    /// Where the name under the cursor is defined, or the file of a `use`d
+    /// module. Nothing is found in the embedded standard library, which has
+    /// no file to show.
+    pub fn definition(&self, position: Position) -> Option<(PathBuf, Range)> {
+        let offset = self.offset(position);
+        let token = self
+            .root
+            .token_at_offset(offset)
+            .find(|t| matches!(t.kind(), K::Ident | K::Str))?;
+        if token.kind() == K::Str {
+            token.parent().filter(|n| n.kind() == K::UseExpr)?;
+            let path = ModuleLoader::new()
+                .resolve(&unquote(token.text()), &self.path)
+                .ok()?;
+            return path.is_file().then_some((path, Range::default()));
+        }
+
+        let range = |doc: &Document, name: Option<SyntaxToken>| {
+            if !std::ptr::eq(doc, self) && !doc.path.is_file() {
+                return None;
+            }
+            Some((doc.path.clone(), doc.range(name?.text_range())))
+        };
+        self.search(|doc| match doc.find(&token, std::ptr::eq(doc, self))? {
+            Symbol::Fn(f) => range(doc, f.name()),
+            Symbol::Class(c) => range(doc, c.name()),
+            Symbol::Param(p) => range(doc, p.name()),
+        })
+    }
+
+    /// Functions and classes, with class methods nested under their class.
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        let fn_symbol = |f: &FnDef, kind| {
+            let name = f.name()?;
+            Some(self.symbol(&name, f.syntax(), kind, Some(fn_signature(f)), None))
+        };
+        let mut symbols = vec![];
+        for node in self.root.children() {
+            if let Some(f) = FnDef::cast(node.clone()) {
+                symbols.extend(fn_symbol(&f, SymbolKind::FUNCTION));
+            } else if let Some(c) = ClassDef::cast(node) {
+                let Some(name) = c.name() else { continue };
+                let methods = c
+                    .fns()
+                    .filter_map(|f| fn_symbol(&f, SymbolKind::METHOD))
+                    .collect();
+                symbols.push(self.symbol(
+                    &name,
+                    c.syntax(),
+                    SymbolKind::CLASS,
+                    None,
+                    Some(methods),
+                ));
+            }
+        }
+        symbols
+    }
+
+    #[allow(deprecated)]
+    fn symbol(
+        &self,
+        name: &SyntaxToken,
+        node: &SyntaxNode,
+        kind: SymbolKind,
+        detail: Option<String>,
+        children: Option<Vec<DocumentSymbol>>,
+    ) -> DocumentSymbol {
+        DocumentSymbol {
+            name: name.text().to_string(),
+            detail,
+            kind,
+            tags: None,
+            deprecated: None,
+            range: self.range(node.text_range()),
+            selection_range: self.range(name.text_range()),
+            children,
+        }
+    }
+
+    /// Members after a `.`, otherwise every name in scope.
+    pub fn completions(&self, position: Position) -> Vec<CompletionItem> {
+        let offset = usize::from(self.offset(position));
+        let before = &self.text[..offset];
+        let word = before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+        if let Some(receiver) = word.trim_end().strip_suffix('.') {
+            let dot = TextSize::from(receiver.len() as u32);
+            let class = self
+                .root
+                .token_at_offset(dot)
+                .find(|t| t.kind() == K::Dot)
+                .and_then(|t| self.receiver_class(&t));
+            return self.members(class.as_deref());
+        }
+
+        let mut items = vec![];
+        let mut seen = BTreeSet::new();
+        self.search::<()>(|doc| {
+            for f in doc.file().fns() {
+                items.extend(completion(
+                    &mut seen,
+                    f.name(),
+                    CompletionItemKind::FUNCTION,
+                    fn_signature(&f),
+                ));
+            }
+            for c in doc.file().classes() {
+                items.extend(completion(
+                    &mut seen,
+                    c.name(),
+                    CompletionItemKind::CLASS,
+                    format!("class {}", c.name()?.text()),
+                ));
+            }
+            None
+        });
+        for builtin in Registry::standard().iter() {
+            if seen.insert(builtin.name.clone()) {
+                items.push(CompletionItem {
+                    label: builtin.name.clone(),
+                    kind: Some(CompletionItemKind::FUNCTION),
+                    detail: Some(builtin.to_string()),
+                    ..CompletionItem::default()
+                });
+            }
+        }
+        let current = TextRange::new(
+            TextSize::from(word.len() as u32),
+            TextSize::from(offset as u32),
+        );
+        for t in self
+            .root
+            .descendants_with_tokens()
+            .filter_map(|e| e.into_token())
+        {
+            if t.kind() == K::Ident && t.text_range() != current {
+                items.extend(completion(
+                    &mut seen,
+                    Some(t),
+                    CompletionItemKind::VARIABLE,
+                    String::new(),
+                ));
+            }
+        }
+        items
+    }
+
+    /// Methods of `class`, or of every class when it isn't known.
+    fn members(&self, class: Option<&str>) -> Vec<CompletionItem> {
+        let mut items = vec![];
+        let mut seen = BTreeSet::new();
+        self.search::<()>(|doc| {
+            for c in doc.file().classes() {
+                if class.is_none_or(|class| c.name().is_some_and(|n| n.text() == class)) {
+                    for f in c.fns() {
+                        items.extend(completion(
+                            &mut seen,
+                            f.name(),
+                            CompletionItemKind::METHOD,
+                            fn_signature(&f),
+                        ));
+                    }
+                }
+            }
+            None
+        });
+        items
+    }
+
+    /// The class of the value before `dot`, when it's plain to see: `self`,
+    /// `Class()` or a variable assigned `Class()`.
+    fn receiver_class(&self, dot: &SyntaxToken) -> Option<String> {
+        let receiver =
+            std::iter::successors(dot.prev_sibling_or_token(), |e| e.prev_sibling_or_token())
+                .find(|e| !e.kind().is_trivia())?
+                .into_node()?;
+        let is_class = |name: &str| {
+            self.search(|doc| {
+                doc.file()
+                    .classes()
+                    .find(|c| c.name().is_some_and(|n| n.text() == name))
+            })
+            .is_some()
+        };
+        let name = match receiver.kind() {
+            K::CallExpr => receiver.first_token()?.text().to_string(),
+            K::NameRef if receiver.text() == "self" => {
+                let class = dot.parent_ancestors().find_map(ClassDef::cast)?;
+                return Some(class.name()?.text().to_string());
+            }
+            K::NameRef => {
+                let var = receiver.text().to_string();
+                if is_class(&var) {
+                    return Some(var);
+                }
+                // The last `var = Class(...)` before the dot.
+                self.root
+                    .descendants()
+                    .filter(|n| {
+                        n.kind() == K::AssignExpr
+                            && n.text_range().end() <= dot.text_range().start()
+                    })
+                    .filter(|n| n.first_token().is_some_and(|t| t.text() == var))
+                    .filter_map(|n| n.children().nth(1).filter(|v| v.kind() == K::CallExpr))
+                    .last()?
+                    .first_token()?
+                    .text()
+                    .to_string()
+            }
+            _ => return None,
+        };
+        is_class(&name).then_some(name)
+    }
+
+    fn ident_at(&self, position: Position) -> Option<SyntaxToken> {
+        self.root
+            .token_at_offset(self.offset(position))
+            .find(|t| t.kind() == K::Ident)
+    }
+
+    /// What `token` names, in this document or a `use`d module.
+    fn lookup(&self, token: &SyntaxToken) -> Option<Symbol> {
+        self.search(|doc| doc.find(token, std::ptr::eq(doc, self)))
+    }
+
+    /// The definition named by `token` in this document. Parameters are only
+    /// looked up when `token` is from this document.
+    fn find(&self, token: &SyntaxToken, local: bool) -> Option<Symbol> {
+        let name = token.text();
+        let named = |t: Option<SyntaxToken>| t.is_some_and(|t| t.text() == name);
+        let expr = token.parent()?;
+        let expr = match expr.parent() {
+            Some(call) if call.kind() == K::CallExpr => call,
+            _ => expr,
+        };
+
+        // `receiver.name`: a method of some class.
+        if is_member(&expr) {
+            return self
+                .file()
+                .classes()
+                .flat_map(|c| c.fns().collect::<Vec<_>>())
+                .find(|f| named(f.name()))
+                .map(Symbol::Fn);
+        }
+        if local {
+            let param = token
+                .parent_ancestors()
+                .find_map(FnDef::cast)
+                .and_then(|f| f.param_list())
+                .and_then(|l| l.params().find(|p| named(p.name())));
+            if let Some(param) = param {
+                return Some(Symbol::Param(param));
+            }
+            // The name of a method being defined.
+            if let Some(f) = expr
+                .parent()
+                .and_then(FnDef::cast)
+                .filter(|f| named(f.name()))
+            {
+                return Some(Symbol::Fn(f));
+            }
+        }
+        let file = self.file();
+        if let Some(f) = file.fns().find(|f| named(f.name())) {
+            return Some(Symbol::Fn(f));
+        }
+        file.classes().find(|c| named(c.name())).map(Symbol::Class)
+    }
+
+    /// Run `f` on this document, then on each `use`d module, until it finds something.
+    fn search<T>(&self, mut f: impl FnMut(&Document) -> Option<T>) -> Option<T> {
+        if let Some(found) = f(self) {
+            return Some(found);
+        }
+        self.modules().find_map(|doc| f(&doc))
+    }
+
+    /// The modules this document `use`s.
+    fn modules(&self) -> impl Iterator<Item = Document> + '_ {
+        self.root
+            .descendants()
+            .filter_map(UseExpr::cast)
+            .filter_map(|n| {
+                let spec = unquote(n.path()?.text());
+                ModuleLoader::new().resolve(&spec, &self.path).ok()
+            })
+            .filter_map(|path| {
+                let text = modules::read(&path).ok()?;
+                Some(Document::new(path, text))
+            })
+    }
+}
+
+/// Whether `expr` is the right side of a `.`.
+fn is_member(expr: &SyntaxNode) -> bool {
+    std::iter::successors(expr.prev_sibling_or_token(), |e| e.prev_sibling_or_token())
+        .find(|e| !e.kind().is_trivia())
+        .is_some_and(|e| e.kind() == K::Dot)
+}
+
+fn diagnostic(range: Range, message: &str) -> Diagnostic {
+    Diagnostic {
+        source: Some("lyronc".to_string()),
+        ..Diagnostic::new_simple(range, message.to_string())
+    }
+}
+
+/// A completion for `name`, unless one with the same label was made already.
+fn completion(
+    seen: &mut BTreeSet<String>,
+    name: Option<SyntaxToken>,
+    kind: CompletionItemKind,
+    detail: String,
+) -> Option<CompletionItem> {
+    let label = name?.text().to_string();
+    seen.insert(label.clone()).then(|| CompletionItem {
+        label,
+        kind: Some(kind),
+        detail: (!detail.is_empty()).then_some(detail),
+        ..Default::default()
+    })
+}
+
+fn fn_signature(f: &FnDef) -> String {
+    let text = |t: Option<SyntaxToken>| t.map_or(String::new(), |t| t.text().to_string());
+    let params = f
+        .param_list()
+        .map(|l| l.params().map(|p| param_signature(&p)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    format!(
+        "def {}({}) -> {}",
+        text(f.name()),
+        params.join(", "),
+        text(f.return_type())
+    )
+}
+
+fn param_signature(p: &Param) -> String {
+    let text = |t: Option<SyntaxToken>| t.map_or(String::new(), |t| t.text().to_string());
+    let prefix = if p.is_keywords() {
+        "**"
+    } else if p.is_variadic() {
+        "*"
+    } else {
+        ""
+    };
+    let mut signature = format!("{}{}: {}", prefix, text(p.name()), text(p.type_name()));
+    if let Some(default) = p.default() {
+        signature += &format!(" = {}", default.text().to_string().trim());
+    }
+    signature
+}
+
+fn class_signature(c: &ClassDef) -> String {
+    let name = c.name().map_or(String::new(), |t| t.text().to_string());
+    let fns = c
+        .fns()
+        .map(|f| format!("    {}\n", fn_signature(&f)))
+        .collect::<String>();
+    format!("class {} {{\n{}}}", name, fns)
+}
+
+fn unquote(text: &str) -> String {
+    text.trim_matches(['"', '\'', '`']).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Document;
+    use lsp_types::{CompletionItemKind, Position, SymbolKind};
+    use std::path::Path;
+
+    fn doc(text: &str) -> Document {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/<test>.lyr");
+        Document::new(path, text.to_string())
+    }
+
+    const SRC: &str = "use \"std:json\"\nclass A {\n    def A(self: Self) -> A self\n    def go(self: Self, n: i32 = 1) -> Any self.go(n)\n}\ndef f(a: i32, *rest: Any) -> Any a\nx = A()\nx.go()\nf(1)\nJSON().dumps(1)\n";
+
+    #[test]
+    fn diagnostics() {
+        assert!(doc(SRC).diagnostics().is_empty());
+
+        let d = doc("def f(a: i32) -> Any a\nf(1, 2)\n").diagnostics();
+        assert_eq!(d.len(), 1);
+        assert!(
+            d[0].message
+                .contains("expected 1 positional argument but 2 were given")
+        );
+        assert_eq!(d[0].range.start, Position::new(1, 0));
+        assert_eq!(d[0].range.end, Position::new(1, 7));
+
+        let d = doc("x = 1\nuse \"std:nope\"\n").diagnostics();
+        assert!(d[0].message.starts_with("cannot find module `std:nope`"));
+        assert_eq!(d[0].range.start, Position::new(1, 0));
+
+        let d = doc("x = (1 +\ny = 2\n").diagnostics();
+        assert_eq!(d[0].message, "Missing closing ')'");
+    }
+
+    #[test]
+    fn hover() {
+        let d = doc(SRC);
+        assert_eq!(
+            d.hover(Position::new(8, 0)).unwrap(),
+            "```lyron\ndef f(a: i32, *rest: Any) -> Any\n```"
+        );
+        assert!(
+            d.hover(Position::new(7, 3))
+                .unwrap()
+                .contains("def go(self: Self, n: i32 = 1) -> Any")
+        );
+        assert!(
+            d.hover(Position::new(6, 4))
+                .unwrap()
+                .contains("class A {\n    def A(self: Self) -> A\n")
+        );
+        assert!(d.hover(Position::new(5, 33)).unwrap().contains("a: i32"));
+        assert!(d.hover(Position::new(9, 8)).unwrap().contains("def dumps"));
+        assert!(
+            doc("print(1)")
+                .hover(Position::new(0, 1))
+                .unwrap()
+                .contains("def print(*values: Any) -> None")
+        );
+    }
+
+    #[test]
+    fn definition() {
+        let d = doc(SRC);
+        let (path, range) = d.definition(Position::new(8, 0)).unwrap();
+        assert_eq!(path, d.path);
+        assert_eq!((range.start.line, range.start.character), (5, 4));
+
+        let (_, range) = d.definition(Position::new(7, 3)).unwrap();
+        assert_eq!((range.start.line, range.start.character), (3, 8));
+
+        // The standard library is embedded in the binary.
+        assert!(d.definition(Position::new(0, 6)).is_none());
+        assert!(d.definition(Position::new(9, 1)).is_none());
+
+        let d = doc("use \"vm_test\"\nsq(2)\n");
+        let (path, _) = d.definition(Position::new(0, 6)).unwrap();
+        assert!(path.ends_with("examples/vm_test.lyr"));
+        let (path, range) = d.definition(Position::new(1, 0)).unwrap();
+        assert!(path.ends_with("examples/vm_test.lyr"));
+        assert_eq!(range.start.line, 0);
+    }
+
+    #[test]
+    fn symbols() {
+        let symbols = doc(SRC).symbols();
+        let names = symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![("A", SymbolKind::CLASS), ("f", SymbolKind::FUNCTION)]
+        );
+        let methods = symbols[0].children.as_ref().unwrap();
+        assert_eq!(methods.len(), 2);
+        assert_eq!(
+            methods[1].detail.as_deref(),
+            Some("def go(self: Self, n: i32 = 1) -> Any")
+        );
+    }
+
+    #[test]
+    fn completions() {
+        let labels = |text: &str, position| {
+            doc(text)
+                .completions(position)
+                .into_iter()
+                .map(|c| (c.label, c.kind.unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let src = format!("{}x.g", SRC);
+        assert_eq!(
+            labels(&src, Position::new(10, 3)),
+            vec![
+                ("A".to_string(), CompletionItemKind::METHOD),
+                ("go".to_string(), CompletionItemKind::METHOD)
+            ]
+        );
+        let src = format!("{}JSON().", SRC);
+        let members = labels(&src, Position::new(10, 7));
+        assert!(members.iter().any(|(l, _)| l == "loads"));
+        assert!(!members.iter().any(|(l, _)| l == "go"));
+
+        let all = labels(SRC, Position::new(10, 0));
+        assert!(all.contains(&("f".to_string(), CompletionItemKind::FUNCTION)));
+        assert!(all.contains(&("JSON".to_string(), CompletionItemKind::CLASS)));
+        assert!(all.contains(&("x".to_string(), CompletionItemKind::VARIABLE)));
+        assert!(all.contains(&("read_file".to_string(), CompletionItemKind::FUNCTION)));
+    }
+}
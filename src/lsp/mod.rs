@@ -0,0 +1,298 @@
+//! A language server for editors, speaking LSP over stdio (`lyronc lsp`).
+//!
+//! Documents are kept in memory as the editor sends them and re-parsed on
+//! every request; the syntax tree of [`crate::syntax`] is cheap to build and
+//! copes with the broken code found mid-edit.
+
+mod analysis;
+
+pub use analysis::Document;
+
+use crate::Result;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationTrait, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as RequestTrait,
+};
+use lsp_types::{
+    CompletionOptions, CompletionResponse, DocumentSymbolResponse, GotoDefinitionResponse, Hover,
+    HoverContents, HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf,
+    PublishDiagnosticsParams, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind,
+    Uri,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Serve the editor on stdin and stdout until it shuts the server down.
+pub fn run() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_string()]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let capabilities = serde_json::to_value(capabilities).map_err(|e| e.to_string())?;
+    connection
+        .initialize(capabilities)
+        .map_err(|e| e.to_string())?;
+
+    Server {
+        connection,
+        documents: HashMap::new(),
+    }
+    .main_loop()?;
+    io_threads.join().map_err(|e| e.to_string())
+}
+
+struct Server {
+    connection: Connection,
+    /// Text of the open documents.
+    documents: HashMap<Uri, String>,
+}
+
+impl Server {
+    fn main_loop(&mut self) -> Result<()> {
+        let receiver = self.connection.receiver.clone();
+        for message in &receiver {
+            match message {
+                Message::Request(request) => {
+                    if self
+                        .connection
+                        .handle_shutdown(&request)
+                        .map_err(|e| e.to_string())?
+                    {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(request);
+                    self.send(response.into())?;
+                }
+                // Notifications get no response to report an error in, so
+                // one the server can't handle is logged and skipped.
+                Message::Notification(notification) => {
+                    if let Err(e) = self.handle_notification(notification) {
+                        eprintln!("lyronc lsp: {}", e);
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&self, message: Message) -> Result<()> {
+        self.connection
+            .sender
+            .send(message)
+            .map_err(|e| e.to_string())
+    }
+
+    fn document(&self, uri: &Uri) -> Option<Document> {
+        let text = self.documents.get(uri)?;
+        Some(Document::new(uri_to_path(uri), text.clone()))
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            HoverRequest::METHOD => parse_params::<HoverRequest>(request.params).map(|params| {
+                let position = params.text_document_position_params;
+                let hover = self
+                    .document(&position.text_document.uri)
+                    .and_then(|doc| doc.hover(position.position))
+                    .map(|value| Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value,
+                        }),
+                        range: None,
+                    });
+                serde_json::to_value(hover)
+            }),
+            GotoDefinition::METHOD => {
+                parse_params::<GotoDefinition>(request.params).map(|params| {
+                    let position = params.text_document_position_params;
+                    let location = self
+                        .document(&position.text_document.uri)
+                        .and_then(|doc| doc.definition(position.position))
+                        .and_then(|(path, range)| {
+                            let uri = path_to_uri(&path)?;
+                            Some(GotoDefinitionResponse::Scalar(Location { uri, range }))
+                        });
+                    serde_json::to_value(location)
+                })
+            }
+            DocumentSymbolRequest::METHOD => parse_params::<DocumentSymbolRequest>(request.params)
+                .map(|params| {
+                    let symbols = self
+                        .document(&params.text_document.uri)
+                        .map(|doc| DocumentSymbolResponse::Nested(doc.symbols()));
+                    serde_json::to_value(symbols)
+                }),
+            Completion::METHOD => parse_params::<Completion>(request.params).map(|params| {
+                let position = params.text_document_position;
+                let items = self
+                    .document(&position.text_document.uri)
+                    .map(|doc| CompletionResponse::Array(doc.completions(position.position)));
+                serde_json::to_value(items)
+            }),
+            method => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request `{}`", method),
+                );
+            }
+        };
+        match result {
+            Ok(Ok(value)) => Response {
+                id,
+                result: Some(value),
+                error: None,
+            },
+            Ok(Err(e)) => Response::new_err(id, ErrorCode::InternalError as i32, e.to_string()),
+            Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e),
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<()> {
+        let uri = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = parse_notification::<DidOpenTextDocument>(notification)?;
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), document.text);
+                document.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = parse_notification::<DidChangeTextDocument>(notification)?;
+                // With full sync the last change holds the whole text.
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents
+                        .insert(params.text_document.uri.clone(), change.text);
+                }
+                params.text_document.uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = parse_notification::<DidCloseTextDocument>(notification)?;
+                self.documents.remove(&params.text_document.uri);
+                params.text_document.uri
+            }
+            _ => return Ok(()),
+        };
+
+        let diagnostics = self
+            .document(&uri)
+            .map(|doc| doc.diagnostics())
+            .unwrap_or_default();
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        self.send(Notification::new(PublishDiagnostics::METHOD.to_string(), params).into())
+    }
+}
+
+fn parse_params<R: RequestTrait>(params: serde_json::Value) -> Result<R::Params> {
+    serde_json::from_value(params).map_err(|e| format!("{}: {}", R::METHOD, e))
+}
+
+fn parse_notification<N: NotificationTrait>(notification: Notification) -> Result<N::Params> {
+    serde_json::from_value(notification.params).map_err(|e| format!("{}: {}", N::METHOD, e))
+}
+
+fn uri_to_path(uri: &Uri) -> PathBuf {
+    let path = uri.path().as_str();
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let escaped = (b == b'%')
+            .then(|| std::str::from_utf8(tail.get(..2)?).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn path_to_uri(path: &Path) -> Option<Uri> {
+    let path = path.canonicalize().ok()?;
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    Uri::from_str(&uri).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Server;
+    use lsp_server::{Connection, Message, Notification, Request, RequestId};
+    use std::collections::HashMap;
+    use std::thread;
+
+    #[test]
+    fn bad_notifications() {
+        let (server, client) = Connection::memory();
+        let server = thread::spawn(move || {
+            Server {
+                connection: server,
+                documents: HashMap::new(),
+            }
+            .main_loop()
+        });
+        let malformed = Notification::new(
+            "textDocument/didOpen".to_string(),
+            serde_json::json!({ "textDocument": 1 }),
+        );
+        client.sender.send(malformed.into()).unwrap();
+        // The server is still answering.
+        let request = Request::new(
+            RequestId::from(1),
+            "textDocument/hover".to_string(),
+            serde_json::json!({
+                "textDocument": { "uri": "file:///x.lyr" },
+                "position": { "line": 0, "character": 0 },
+            }),
+        );
+        client.sender.send(request.into()).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(response) => {
+                assert_eq!(response.id, RequestId::from(1));
+                assert!(response.error.is_none(), "{:?}", response);
+            }
+            message => panic!("expected a response, got {:?}", message),
+        }
+        let shutdown = Request::new(RequestId::from(2), "shutdown".to_string(), ());
+        client.sender.send(shutdown.into()).unwrap();
+        client.receiver.recv().unwrap();
+        client
+            .sender
+            .send(Notification::new("exit".to_string(), ()).into())
+            .unwrap();
+        assert_eq!(server.join().unwrap(), Ok(()));
+    }
+}
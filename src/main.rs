@@ -1,7 +1,17 @@
+use lyronc::Engine;
+use lyronc::formatter;
 use lyronc::init_cli;
 use lyronc::lexer::Lexer;
+use lyronc::lsp;
+use lyronc::mir;
+use lyronc::modules::ModuleLoader;
+use lyronc::optimizer;
 use lyronc::parser::Parser;
+use lyronc::repl;
+use lyronc::sandbox::{Capability, Limits};
+use std::fs;
 use std::process::{self};
+use std::time::Duration;
 
 /// Unwrap and return result, or log and exit if Err.
 macro_rules! unwrap_or_exit {
@@ -21,23 +31,114 @@ pub fn main() {
 
     match cli_input.matches.subcommand_name() {
         None => {
-            let lexer = unwrap_or_exit!(Lexer::from_file(&cli_input.input_path), "IO");
-            let tokens = lexer
-                .map(|t| unwrap_or_exit!(t, "Lexing"))
-                .collect::<Vec<_>>();
+            let source = unwrap_or_exit!(
+                fs::read_to_string(&cli_input.input_path)
+                    .map_err(|e| format!("{}: {}", cli_input.input_path, e)),
+                "IO"
+            );
+            let tokens = match Lexer::from_text(&source, &cli_input.input_path).tokenize() {
+                Ok(tokens) => tokens,
+                Err(errors) => {
+                    errors.iter().for_each(|e| println!("Lexing: {}", e));
+                    process::exit(1);
+                }
+            };
 
             if cli_input.print_tokens {
                 println!("***TOKENS***");
                 tokens.iter().for_each(|t| println!("{:?}", t));
             }
 
-            let mut parser = Parser::new(tokens.into_iter().peekable(), &cli_input.input_path);
+            let mut parser = Parser::new(tokens.into_iter().peekable(), &cli_input.input_path)
+                .with_source(&source);
             let program = unwrap_or_exit!(parser.parse_program(), "Parsing");
             if cli_input.print_ast {
                 println!("***AST***\n{:#?}", program);
             }
+
+            if cli_input.matches.get_one::<String>("emit").is_some() {
+                let mut program = program;
+                if !cli_input.matches.get_flag("no-optimize") {
+                    optimizer::optimize(&mut program);
+                }
+                let ir = unwrap_or_exit!(mir::lower(&program), "Lowering");
+                unwrap_or_exit!(mir::verify(&ir), "Verifying");
+                println!("{}", ir);
+                return;
+            }
+
+            let mut loader = ModuleLoader::new();
+            if let Some(dir) = cli_input.matches.get_one::<String>("external") {
+                loader = loader.with_external_dir(dir);
+            }
+            if let Some(dir) = cli_input.matches.get_one::<String>("stdlib") {
+                loader = loader.with_std_dir(dir);
+            }
+            let optimize = !cli_input.matches.get_flag("no-optimize");
+            let mut engine = Engine::new()
+                .with_loader(loader)
+                .with_optimizations(optimize);
+            if let Some(threshold) = cli_input.matches.get_one::<usize>("gc-threshold") {
+                engine = engine.with_gc_threshold(*threshold);
+            }
+            let matches = &cli_input.matches;
+            engine = engine.with_limits(Limits {
+                instructions: matches.get_one::<u64>("max-instructions").copied(),
+                call_depth: matches.get_one::<usize>("max-depth").copied(),
+                heap: matches.get_one::<usize>("max-heap").copied(),
+                memory: matches.get_one::<usize>("max-memory").copied(),
+                time: matches
+                    .get_one::<u64>("time-limit")
+                    .map(|ms| Duration::from_millis(*ms)),
+            });
+            if matches.get_flag("sandbox") {
+                let granted = matches
+                    .get_many::<Capability>("allow")
+                    .into_iter()
+                    .flatten();
+                engine = engine.with_capabilities(granted.copied());
+            }
+            if let Err(e) = engine.run(&program, &cli_input.input_path) {
+                match cli_input.matches.get_one::<String>("error-format") {
+                    Some(format) if format == "json" => println!("{}", e.to_json()),
+                    _ => println!("{}: {}", e.kind, e),
+                }
+                process::exit(1);
+            }
+        }
+
+        Some("fmt") => {
+            let matches = cli_input.matches.subcommand_matches("fmt").unwrap();
+            let check = matches.get_flag("check");
+            let mut unformatted = false;
+            for path in matches.get_many::<String>("files").unwrap() {
+                let source = unwrap_or_exit!(
+                    fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e)),
+                    "IO"
+                );
+                let formatted = unwrap_or_exit!(formatter::format(&source, path), "Formatting");
+                if formatted == source {
+                    continue;
+                }
+                if check {
+                    println!("{} is not formatted", path);
+                    unformatted = true;
+                } else {
+                    unwrap_or_exit!(
+                        fs::write(path, formatted).map_err(|e| format!("{}: {}", path, e)),
+                        "IO"
+                    );
+                }
+            }
+            if unformatted {
+                process::exit(1);
+            }
         }
 
-        Some(_) => todo!(),
+        Some("lsp") => unwrap_or_exit!(lsp::run(), "LSP"),
+
+        Some("repl") => unwrap_or_exit!(repl::run(), "REPL"),
+
+        Some(_) => unreachable!(),
     };
 }
@@ -0,0 +1,338 @@
+//! Lowering the AST into the IR.
+
+use super::{Block, BlockId, Body, Operand, Program, Rvalue, Statement, Temp, Terminator};
+use crate::Result;
+use crate::interpreter::Value;
+use crate::lexer::tokens::TokenType;
+use crate::parser::{AstNode, ExprValue, Function, NodePosition};
+
+/// Lower every statement, function and method of `ast`.
+pub fn lower(ast: &[(AstNode, NodePosition)]) -> Result<Program> {
+    let mut top = Builder::new();
+    let mut last = Operand::Const(Value::None);
+    let mut bodies = vec![];
+    for (node, _) in ast {
+        match node {
+            AstNode::Expression(e) => last = top.expr(e)?,
+            AstNode::Extern(f) => {
+                top.push(Statement::Native(f.clone()));
+                last = Operand::Const(Value::None);
+            }
+            AstNode::FunctionDef(f) => bodies.push(function(f)?),
+            AstNode::Class(c) => {
+                for (f, _) in &c.fns {
+                    bodies.push(function(f)?);
+                }
+            }
+        }
+    }
+    // The program is worth its last statement.
+    bodies.insert(0, top.finish(last, "<top level>"));
+    Ok(Program { bodies })
+}
+
+fn function(f: &Function) -> Result<Body> {
+    let mut builder = Builder::new();
+    let value = builder.expr(&f.expression.0)?;
+    let mut body = builder.finish(value, &f.name);
+    body.class.clone_from(&f.class);
+    body.params.clone_from(&f.args.name);
+    body.rest = f.args.vararg.as_ref().map(|(name, _)| name.clone());
+    body.opts = f.args.kwarg.as_ref().map(|(name, _)| name.clone());
+    Ok(body)
+}
+
+/// Positional and keyword arguments of a call.
+type Arguments = (Vec<Operand>, Vec<(String, Operand)>);
+
+/// A body being lowered, appending to the current block.
+struct Builder {
+    /// Statements of every block, with how it's left once it is.
+    blocks: Vec<(Vec<Statement>, Option<Terminator>)>,
+    current: BlockId,
+    temps: usize,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Builder {
+            blocks: vec![(vec![], None)],
+            current: 0,
+            temps: 0,
+        }
+    }
+
+    fn push(&mut self, statement: Statement) {
+        self.blocks[self.current].0.push(statement);
+    }
+
+    /// Assign `rvalue` to a new temporary.
+    fn assign(&mut self, rvalue: Rvalue) -> Operand {
+        let temp = self.temp();
+        self.push(Statement::Assign(temp, rvalue));
+        Operand::Temp(temp)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((vec![], None));
+        self.blocks.len() - 1
+    }
+
+    /// End the current block with `terminator` and carry on in `next`.
+    fn terminate(&mut self, terminator: Terminator, next: BlockId) {
+        self.blocks[self.current].1 = Some(terminator);
+        self.current = next;
+    }
+
+    /// End the current block returning `value`, and drop blocks that can't
+    /// be reached, like the code after a `return`.
+    fn finish(mut self, value: Operand, name: &str) -> Body {
+        self.blocks[self.current].1 = Some(Terminator::Return(value));
+        let blocks = self
+            .blocks
+            .into_iter()
+            .map(|(statements, terminator)| Block {
+                statements,
+                // Every block made is carried on into, and left by the
+                // time the next one is.
+                terminator: terminator.expect("every block is terminated"),
+            })
+            .collect();
+        let mut body = Body {
+            name: name.to_string(),
+            class: None,
+            params: vec![],
+            rest: None,
+            opts: None,
+            temps: self.temps,
+            blocks,
+        };
+        let reachable = body.reachable();
+        let mut renumbered = vec![0; body.blocks.len()];
+        let mut next = 0;
+        for (b, r) in reachable.iter().enumerate() {
+            renumbered[b] = next;
+            next += usize::from(*r);
+        }
+        let mut b = 0;
+        body.blocks.retain(|_| {
+            b += 1;
+            reachable[b - 1]
+        });
+        for block in &mut body.blocks {
+            match &mut block.terminator {
+                Terminator::Goto(b) => *b = renumbered[*b],
+                Terminator::Branch { then, else_, .. } => {
+                    *then = renumbered[*then];
+                    *else_ = renumbered[*else_];
+                }
+                Terminator::Return(_) => {}
+            }
+        }
+        body
+    }
+
+    fn args(&mut self, args: &[ExprValue], kwargs: &[(String, ExprValue)]) -> Result<Arguments> {
+        let args = args.iter().map(|e| self.expr(e)).collect::<Result<_>>()?;
+        let kwargs = kwargs
+            .iter()
+            .map(|(name, e)| Ok((name.clone(), self.expr(e)?)))
+            .collect::<Result<_>>()?;
+        Ok((args, kwargs))
+    }
+
+    /// Lower `expr` into the current block, returning its value.
+    fn expr(&mut self, expr: &ExprValue) -> Result<Operand> {
+        let none = Operand::Const(Value::None);
+        Ok(match expr {
+            ExprValue::Boolean(b) => Operand::Const(Value::Boolean(*b)),
+            ExprValue::Integer(i) => Operand::Const(Value::Integer(*i)),
+            ExprValue::BigInteger(i) => Operand::Const(Value::BigInteger(i.clone())),
+            ExprValue::Double(d) => Operand::Const(Value::Double(*d)),
+            ExprValue::Str(s) => Operand::Const(Value::Str(s.clone())),
+            ExprValue::None => none,
+            ExprValue::Identifier(name) => self.assign(Rvalue::Var(name.clone())),
+            ExprValue::FnCall(function, args, kwargs, _) => {
+                let (args, kwargs) = self.args(args, kwargs)?;
+                self.assign(Rvalue::Call {
+                    function: function.clone(),
+                    args,
+                    kwargs,
+                })
+            }
+            ExprValue::UnOp(op, e) => {
+                let value = self.expr(e)?;
+                self.assign(Rvalue::Unary((**op).clone(), value))
+            }
+            ExprValue::BinOp(l, op, r, _) => match **op {
+                TokenType::Dot => {
+                    let receiver = self.expr(l)?;
+                    match &**r {
+                        ExprValue::Identifier(name) => {
+                            self.assign(Rvalue::Attribute(receiver, name.clone()))
+                        }
+                        ExprValue::FnCall(name, args, kwargs, _) => {
+                            let (args, kwargs) = self.args(args, kwargs)?;
+                            self.assign(Rvalue::Method {
+                                receiver,
+                                name: name.clone(),
+                                args,
+                                kwargs,
+                            })
+                        }
+                        _ => return Err("expected a name after '.'".to_string()),
+                    }
+                }
+                TokenType::And | TokenType::Or => {
+                    let left = self.expr(l)?;
+                    self.short_circuit(left, **op == TokenType::Or, r)?
+                }
+                _ => {
+                    let left = self.expr(l)?;
+                    let right = self.expr(r)?;
+                    self.assign(Rvalue::Binary((**op).clone(), left, right))
+                }
+            },
+            ExprValue::Interpolation(parts) => {
+                let parts = parts.iter().map(|e| self.expr(e)).collect::<Result<_>>()?;
+                self.assign(Rvalue::Concat(parts))
+            }
+            ExprValue::VarDecl { name, .. } => {
+                self.push(Statement::Declare(name.clone()));
+                none
+            }
+            ExprValue::IfElse { cond, if_, else_ } => {
+                let cond = self.expr(cond)?;
+                self.if_else(cond, if_, else_)?
+            }
+            ExprValue::Assign { name, value } => {
+                let value = self.expr(value)?;
+                self.push(Statement::Store(name.clone(), value));
+                none
+            }
+            ExprValue::AugAssign { name, op, value } => {
+                let op = match **op {
+                    TokenType::PlusEq => TokenType::Plus,
+                    TokenType::MinusEq => TokenType::Minus,
+                    TokenType::MulEq => TokenType::Mul,
+                    _ => TokenType::Div,
+                };
+                let current = self.assign(Rvalue::Var(name.clone()));
+                let value = self.expr(value)?;
+                let value = self.assign(Rvalue::Binary(op, current, value));
+                self.push(Statement::Store(name.clone(), value));
+                none
+            }
+            ExprValue::Return(e) => {
+                let value = self.expr(e)?;
+                // What follows can't run, and is dropped when the body is
+                // finished.
+                let dead = self.new_block();
+                self.terminate(Terminator::Return(value), dead);
+                none
+            }
+            ExprValue::Use(spec, import) => {
+                self.push(Statement::Use(spec.clone(), import.clone()));
+                none
+            }
+            ExprValue::Extern(spec) => {
+                self.push(Statement::Extern(spec.clone()));
+                none
+            }
+            ExprValue::Walrus { name, value } => {
+                let value = self.expr(value)?;
+                self.push(Statement::Store(name.clone(), value.clone()));
+                value
+            }
+            ExprValue::While(cond, body) => {
+                let header = self.new_block();
+                self.terminate(Terminator::Goto(header), header);
+                let cond = self.expr(cond)?;
+                let (looped, exit) = (self.new_block(), self.new_block());
+                self.terminate(
+                    Terminator::Branch {
+                        cond,
+                        then: looped,
+                        else_: exit,
+                    },
+                    looped,
+                );
+                self.expr(body)?;
+                self.terminate(Terminator::Goto(header), exit);
+                none
+            }
+            ExprValue::Do(exprs) => {
+                let mut last = none;
+                for e in exprs {
+                    last = self.expr(e)?;
+                }
+                last
+            }
+            ExprValue::Array(exprs) => {
+                let items = exprs.iter().map(|e| self.expr(e)).collect::<Result<_>>()?;
+                self.assign(Rvalue::Array(items))
+            }
+            ExprValue::Tuple(exprs) => {
+                let items = exprs.iter().map(|e| self.expr(e)).collect::<Result<_>>()?;
+                self.assign(Rvalue::Tuple(items))
+            }
+            ExprValue::Destructure {
+                pattern,
+                value,
+                declare,
+            } => {
+                let value = self.expr(value)?;
+                self.push(Statement::Destructure {
+                    pattern: pattern.clone(),
+                    value,
+                    declare: *declare,
+                });
+                none
+            }
+        })
+    }
+
+    /// Branch on `cond` to `if_` or `else_`, joining into a temporary.
+    fn if_else(&mut self, cond: Operand, if_: &ExprValue, else_: &ExprValue) -> Result<Operand> {
+        let result = self.temp();
+        let (then, otherwise, join) = (self.new_block(), self.new_block(), self.new_block());
+        let branch = Terminator::Branch {
+            cond,
+            then,
+            else_: otherwise,
+        };
+        self.terminate(branch, then);
+        let value = self.expr(if_)?;
+        self.push(Statement::Assign(result, Rvalue::Use(value)));
+        self.terminate(Terminator::Goto(join), otherwise);
+        let value = self.expr(else_)?;
+        self.push(Statement::Assign(result, Rvalue::Use(value)));
+        self.terminate(Terminator::Goto(join), join);
+        Ok(Operand::Temp(result))
+    }
+
+    /// `left and right` or `left or right`: `left` unless it decides the
+    /// result by itself, otherwise `right`.
+    fn short_circuit(&mut self, left: Operand, or: bool, right: &ExprValue) -> Result<Operand> {
+        let result = self.temp();
+        let (rest, short, join) = (self.new_block(), self.new_block(), self.new_block());
+        let (then, else_) = if or { (short, rest) } else { (rest, short) };
+        let branch = Terminator::Branch {
+            cond: left.clone(),
+            then,
+            else_,
+        };
+        self.terminate(branch, short);
+        self.push(Statement::Assign(result, Rvalue::Use(left)));
+        self.terminate(Terminator::Goto(join), rest);
+        let value = self.expr(right)?;
+        self.push(Statement::Assign(result, Rvalue::Use(value)));
+        self.terminate(Terminator::Goto(join), join);
+        Ok(Operand::Temp(result))
+    }
+
+    fn temp(&mut self) -> Temp {
+        self.temps += 1;
+        Temp(self.temps - 1)
+    }
+}
@@ -0,0 +1,413 @@
+//! A mid-level IR between the AST and what runs it.
+//!
+//! [`lower`] desugars a program into [`Body`]s of basic blocks: one for the
+//! code outside of functions and one for every function and method. Blocks
+//! are straight-line [`Statement`]s over numbered temporaries, ending in a
+//! [`Terminator`] that jumps to other blocks or returns. `do`, `if`, `while`,
+//! `and`, `or` and `+=` are gone, and every operand is evaluated into a
+//! temporary before it's used, so evaluation order is explicit. The blocks
+//! and their jumps are the body's control-flow graph, walked with
+//! [`Body::successors`] and [`Body::predecessors`]. [`verify`] checks that a
+//! body is well formed, and the [`Display`](fmt::Display) of a [`Program`]
+//! is what `--emit ir` prints.
+
+mod lower;
+mod verify;
+
+pub use lower::lower;
+pub use verify::verify;
+
+use crate::interpreter::Value;
+use crate::lexer::tokens::TokenType;
+use crate::parser::{External, Import, Pattern};
+
+use std::fmt;
+
+/// Index of a block in its [`Body`].
+pub type BlockId = usize;
+
+/// A temporary, local to its [`Body`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Temp(pub usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Temp(Temp),
+    Const(Value),
+}
+
+/// What a temporary is assigned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rvalue {
+    Use(Operand),
+    /// The value of a variable.
+    Var(String),
+    Unary(TokenType, Operand),
+    /// Any binary operator but `.`, `and` and `or`.
+    Binary(TokenType, Operand, Operand),
+    Call {
+        function: String,
+        args: Vec<Operand>,
+        kwargs: Vec<(String, Operand)>,
+    },
+    Method {
+        receiver: Operand,
+        name: String,
+        args: Vec<Operand>,
+        kwargs: Vec<(String, Operand)>,
+    },
+    Attribute(Operand, String),
+    Array(Vec<Operand>),
+    Tuple(Vec<Operand>),
+    /// The string forms of the operands, concatenated.
+    Concat(Vec<Operand>),
+}
+
+#[derive(Debug, Clone)]
+pub enum Statement {
+    Assign(Temp, Rvalue),
+    /// Assign a variable.
+    Store(String, Operand),
+    /// `let name: type`, setting the variable to `none`.
+    Declare(String),
+    Destructure {
+        pattern: Pattern,
+        value: Operand,
+        declare: bool,
+    },
+    Use(String, Import),
+    /// Load a native module.
+    Extern(String),
+    /// Bind a C function.
+    Native(External),
+}
+
+/// How a block is left.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Goto(BlockId),
+    Branch {
+        cond: Operand,
+        then: BlockId,
+        else_: BlockId,
+    },
+    Return(Operand),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Goto(b) => vec![*b],
+            Terminator::Branch { then, else_, .. } => vec![*then, *else_],
+            Terminator::Return(_) => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub statements: Vec<Statement>,
+    pub terminator: Terminator,
+}
+
+/// The code outside of functions, a function or a method.
+#[derive(Debug, Clone)]
+pub struct Body {
+    /// `<top level>`, or the name of the function.
+    pub name: String,
+    /// The class of a method.
+    pub class: Option<String>,
+    /// Named parameters, in order. Default values aren't lowered.
+    pub params: Vec<String>,
+    /// `*rest`, collecting extra positional arguments.
+    pub rest: Option<String>,
+    /// `**opts`, collecting extra keyword arguments.
+    pub opts: Option<String>,
+    /// How many temporaries the body uses.
+    pub temps: usize,
+    /// Running starts at the first block.
+    pub blocks: Vec<Block>,
+}
+
+impl Body {
+    /// The blocks `block` may jump to.
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        self.blocks[block].terminator.successors()
+    }
+
+    /// The blocks that may jump to each block.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![vec![]; self.blocks.len()];
+        for (b, block) in self.blocks.iter().enumerate() {
+            for s in block.terminator.successors() {
+                if let Some(p) = preds.get_mut(s) {
+                    p.push(b);
+                }
+            }
+        }
+        preds
+    }
+
+    /// Whether each block can be reached from the first.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut stack = vec![0];
+        while let Some(b) = stack.pop() {
+            if b >= seen.len() || seen[b] {
+                continue;
+            }
+            seen[b] = true;
+            stack.extend(self.successors(b));
+        }
+        seen
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    /// The code outside of functions first, then functions and methods in
+    /// the order they're defined.
+    pub bodies: Vec<Body>,
+}
+
+impl fmt::Display for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Temp(t) => write!(f, "{}", t),
+            Operand::Const(v) => write!(f, "{}", v.repr()),
+        }
+    }
+}
+
+/// `a, b, name=c`.
+fn list(args: &[Operand], kwargs: &[(String, Operand)]) -> String {
+    let args = args.iter().map(Operand::to_string);
+    let kwargs = kwargs.iter().map(|(k, v)| format!("{}={}", k, v));
+    args.chain(kwargs).collect::<Vec<_>>().join(", ")
+}
+
+fn symbol(op: &TokenType) -> &'static str {
+    use TokenType as T;
+    match op {
+        T::Plus => "+",
+        T::Minus => "-",
+        T::Mul => "*",
+        T::Div => "/",
+        T::Mod => "%",
+        T::Pow => "**",
+        T::Equal => "==",
+        T::NotEq => "!=",
+        T::Less => "<",
+        T::Greater => ">",
+        T::LessEq => "<=",
+        T::GreaterEq => ">=",
+        T::BitAnd => "&",
+        T::BitOr => "|",
+        T::BitXor => "^",
+        T::Shl => "<<",
+        T::Shr => ">>",
+        T::Not => "!",
+        _ => "?",
+    }
+}
+
+impl fmt::Display for Rvalue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rvalue::Use(o) => write!(f, "{}", o),
+            Rvalue::Var(name) => write!(f, "{}", name),
+            Rvalue::Unary(op, o) => write!(f, "{}{}", symbol(op), o),
+            Rvalue::Binary(op, l, r) => write!(f, "{} {} {}", l, symbol(op), r),
+            Rvalue::Call {
+                function,
+                args,
+                kwargs,
+            } => write!(f, "call {}({})", function, list(args, kwargs)),
+            Rvalue::Method {
+                receiver,
+                name,
+                args,
+                kwargs,
+            } => write!(f, "call {}.{}({})", receiver, name, list(args, kwargs)),
+            Rvalue::Attribute(o, name) => write!(f, "{}.{}", o, name),
+            Rvalue::Array(items) => write!(f, "[{}]", list(items, &[])),
+            Rvalue::Tuple(items) if items.len() == 1 => write!(f, "({},)", items[0]),
+            Rvalue::Tuple(items) => write!(f, "({})", list(items, &[])),
+            Rvalue::Concat(parts) => write!(f, "concat({})", list(parts, &[])),
+        }
+    }
+}
+
+fn pattern(p: &Pattern) -> String {
+    let all = |ps: &[Pattern]| ps.iter().map(pattern).collect::<Vec<_>>().join(", ");
+    match p {
+        Pattern::Identifier(name) => name.clone(),
+        Pattern::Tuple(ps) => format!("({})", all(ps)),
+        Pattern::Array(ps) => format!("[{}]", all(ps)),
+        Pattern::Dict(names) => format!("{{{}}}", names.join(", ")),
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Assign(t, rvalue) => write!(f, "{} = {}", t, rvalue),
+            Statement::Store(name, o) => write!(f, "{} = {}", name, o),
+            Statement::Declare(name) => write!(f, "let {}", name),
+            Statement::Destructure {
+                pattern: p,
+                value,
+                declare,
+            } => {
+                let let_ = if *declare { "let " } else { "" };
+                write!(f, "{}{} = {}", let_, pattern(p), value)
+            }
+            Statement::Use(spec, import) => match import {
+                Import::All => write!(f, "use {:?}", spec),
+                Import::Module(name) => write!(f, "use {:?} as {}", spec, name),
+                Import::Names(names) => {
+                    let names = names
+                        .iter()
+                        .map(|(name, alias)| match name == alias {
+                            true => name.clone(),
+                            false => format!("{} as {}", name, alias),
+                        })
+                        .collect::<Vec<_>>();
+                    write!(f, "use {{ {} }} from {:?}", names.join(", "), spec)
+                }
+            },
+            Statement::Extern(spec) => write!(f, "extern {:?}", spec),
+            Statement::Native(e) => write!(f, "extern {:?} def {}", e.library, e.name),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Goto(b) => write!(f, "goto bb{}", b),
+            Terminator::Branch { cond, then, else_ } => {
+                write!(f, "branch {}, bb{}, bb{}", cond, then, else_)
+            }
+            Terminator::Return(o) => write!(f, "return {}", o),
+        }
+    }
+}
+
+impl fmt::Display for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut params = self.params.clone();
+        params.extend(self.rest.iter().map(|r| format!("*{}", r)));
+        params.extend(self.opts.iter().map(|o| format!("**{}", o)));
+        match &self.class {
+            Some(class) => write!(f, "def {}.{}", class, self.name)?,
+            None => write!(f, "def {}", self.name)?,
+        }
+        writeln!(f, "({}) {{", params.join(", "))?;
+        for (b, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "bb{}:", b)?;
+            for s in &block.statements {
+                writeln!(f, "    {}", s)?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bodies = self.bodies.iter().map(Body::to_string).collect::<Vec<_>>();
+        write!(f, "{}", bodies.join("\n\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Program, lower, verify};
+    use crate::parser::parse_source;
+
+    fn lowered(src: &str) -> Program {
+        let ast = parse_source(src, "<test>").unwrap();
+        let program = lower(&ast).unwrap();
+        verify(&program).unwrap();
+        program
+    }
+
+    #[test]
+    fn lowering() {
+        let program = lowered("x = 1 + 2\nx += 3\nx");
+        assert_eq!(
+            program.to_string(),
+            "def <top level>() {
+bb0:
+    %0 = 1 + 2
+    x = %0
+    %1 = x
+    %2 = %1 + 3
+    x = %2
+    %3 = x
+    return %3
+}"
+        );
+
+        let program = lowered("if a and b then f(1, k=\"s\") else none");
+        let body = &program.bodies[0];
+        assert_eq!(body.blocks.len(), 7);
+        assert_eq!(body.successors(0), [1, 2]);
+        assert_eq!(body.predecessors()[3], [1, 2]);
+        assert_eq!(body.predecessors()[6], [4, 5]);
+        assert!(body.to_string().contains("call f(1, k=\"s\")"));
+
+        let program = lowered(
+            "def count(n: i32) -> i32 do\nlet i: i32\nwhile i < n do i += 1 end\nreturn i\nend\n\
+             class P { def get(self: P) -> Any self.x }",
+        );
+        let names = program.bodies.iter().map(|b| b.name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), ["<top level>", "count", "get"]);
+        let count = &program.bodies[1];
+        assert_eq!(count.params, ["n"]);
+        // The loop header is jumped to from before the loop and from its body.
+        assert_eq!(count.predecessors()[1], [0, 2]);
+        // Code after `return` is dropped.
+        assert!(count.to_string().ends_with("    return %5\n}"), "{}", count);
+        assert!(program.bodies[2].to_string().contains("%1 = %0.x"));
+    }
+
+    #[test]
+    fn verifying() {
+        use super::{Operand, Rvalue, Statement, Temp, Terminator};
+
+        let mut program = lowered("x = if c then 1 else 2\nx");
+        verify(&program).unwrap();
+        let body = &mut program.bodies[0];
+
+        // The join temp is only assigned on one path.
+        let mut broken = body.clone();
+        broken.blocks[2].statements.clear();
+        let e = verify(&Program {
+            bodies: vec![broken],
+        })
+        .unwrap_err();
+        assert!(
+            e.contains("%1 is used in bb3 before it's assigned"),
+            "{}",
+            e
+        );
+
+        body.blocks[1].terminator = Terminator::Goto(9);
+        body.blocks[0].statements.push(Statement::Assign(
+            Temp(7),
+            Rvalue::Use(Operand::Temp(Temp(0))),
+        ));
+        let e = verify(&program).unwrap_err();
+        assert!(e.contains("bb1 jumps to bb9, which doesn't exist"), "{}", e);
+        assert!(e.contains("%7 is out of range"), "{}", e);
+    }
+}
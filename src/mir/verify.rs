@@ -0,0 +1,167 @@
+//! Checking lowered programs are well formed.
+
+use super::{Body, Operand, Program, Rvalue, Statement, Temp, Terminator};
+use crate::Result;
+
+use std::collections::BTreeSet;
+
+/// Check that every body of `program` jumps only to its own blocks, uses
+/// only its own temporaries, and assigns every temporary on all paths to
+/// where it's used. Only blocks that can be reached are checked for the
+/// last.
+pub fn verify(program: &Program) -> Result<()> {
+    let errors = program
+        .bodies
+        .iter()
+        .flat_map(|body| {
+            check(body)
+                .into_iter()
+                .map(move |e| format!("in `{}`: {}", body.name, e))
+        })
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+fn check(body: &Body) -> Vec<String> {
+    let mut errors = vec![];
+    if body.blocks.is_empty() {
+        return vec!["there are no blocks".to_string()];
+    }
+    for (b, block) in body.blocks.iter().enumerate() {
+        for s in block.terminator.successors() {
+            if s >= body.blocks.len() {
+                errors.push(format!("bb{} jumps to bb{}, which doesn't exist", b, s));
+            }
+        }
+        let temps = block.statements.iter().flat_map(|s| {
+            let assigned = match s {
+                Statement::Assign(t, _) => Some(*t),
+                _ => None,
+            };
+            uses(s).into_iter().chain(assigned)
+        });
+        for t in temps.chain(terminator_uses(&block.terminator)) {
+            if t.0 >= body.temps {
+                errors.push(format!("{} is out of range in bb{}", t, b));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return errors;
+    }
+
+    // The temporaries assigned on every path into each block, found by
+    // narrowing down from all of them until nothing changes.
+    let reachable = body.reachable();
+    let preds = body.predecessors();
+    let all = (0..body.temps).map(Temp).collect::<BTreeSet<_>>();
+    let mut assigned_in = vec![all; body.blocks.len()];
+    assigned_in[0].clear();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in (0..body.blocks.len()).filter(|b| reachable[*b]) {
+            // Nothing is assigned on entry, even if the first block is
+            // jumped back to.
+            let assigned = if b == 0 {
+                BTreeSet::new()
+            } else {
+                let mut from = preds[b].iter().filter(|p| reachable[**p]).map(|p| {
+                    let mut out = assigned_in[*p].clone();
+                    out.extend(assigns(body, *p));
+                    out
+                });
+                let first = from.next().unwrap_or_default();
+                from.fold(first, |acc, out| &acc & &out)
+            };
+            if assigned != assigned_in[b] {
+                assigned_in[b] = assigned;
+                changed = true;
+            }
+        }
+    }
+
+    for (b, block) in body.blocks.iter().enumerate() {
+        if !reachable[b] {
+            continue;
+        }
+        let mut assigned = assigned_in[b].clone();
+        for s in &block.statements {
+            for t in uses(s) {
+                if !assigned.contains(&t) {
+                    errors.push(format!("{} is used in bb{} before it's assigned", t, b));
+                }
+            }
+            if let Statement::Assign(t, _) = s {
+                assigned.insert(*t);
+            }
+        }
+        for t in terminator_uses(&block.terminator) {
+            if !assigned.contains(&t) {
+                errors.push(format!("{} is used in bb{} before it's assigned", t, b));
+            }
+        }
+    }
+    errors
+}
+
+/// The temporaries block `b` assigns.
+fn assigns(body: &Body, b: usize) -> impl Iterator<Item = Temp> + '_ {
+    body.blocks[b].statements.iter().filter_map(|s| match s {
+        Statement::Assign(t, _) => Some(*t),
+        _ => None,
+    })
+}
+
+fn temps<'a>(operands: impl IntoIterator<Item = &'a Operand>) -> Vec<Temp> {
+    operands
+        .into_iter()
+        .filter_map(|o| match o {
+            Operand::Temp(t) => Some(*t),
+            Operand::Const(_) => None,
+        })
+        .collect()
+}
+
+/// The temporaries `statement` reads.
+fn uses(statement: &Statement) -> Vec<Temp> {
+    match statement {
+        Statement::Assign(_, rvalue) => match rvalue {
+            Rvalue::Use(o) | Rvalue::Unary(_, o) | Rvalue::Attribute(o, _) => temps([o]),
+            Rvalue::Binary(_, l, r) => temps([l, r]),
+            Rvalue::Var(_) => vec![],
+            Rvalue::Call { args, kwargs, .. } => {
+                temps(args.iter().chain(kwargs.iter().map(|(_, o)| o)))
+            }
+            Rvalue::Method {
+                receiver,
+                args,
+                kwargs,
+                ..
+            } => temps(
+                [receiver]
+                    .into_iter()
+                    .chain(args)
+                    .chain(kwargs.iter().map(|(_, o)| o)),
+            ),
+            Rvalue::Array(items) | Rvalue::Tuple(items) | Rvalue::Concat(items) => temps(items),
+        },
+        Statement::Store(_, o) | Statement::Destructure { value: o, .. } => temps([o]),
+        Statement::Declare(_)
+        | Statement::Use(..)
+        | Statement::Extern(_)
+        | Statement::Native(_) => vec![],
+    }
+}
+
+fn terminator_uses(terminator: &Terminator) -> Vec<Temp> {
+    match terminator {
+        Terminator::Goto(_) => vec![],
+        Terminator::Branch { cond, .. } => temps([cond]),
+        Terminator::Return(o) => temps([o]),
+    }
+}
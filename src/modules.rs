@@ -0,0 +1,476 @@
+//! Finding and loading the modules named by `use`.
+//!
+//! `use "std:name"` loads `name.lyr` from the standard library, `use "@:path"`
+//! loads `path.lyr` from the external directory, which is `LYRON_EXTERNAL` or
+//! the `external` directory next to the lyronc executable unless given with
+//! [`ModuleLoader::with_external_dir`], and any other path is relative
+//! to the file containing the `use`. When there is no `external` next to the
+//! executable, as when running from a checkout, `iorekfiles/external` in the
+//! nearest directory above the file containing the `use` is used instead. Each file is parsed once, and a module that
+//! ends up importing itself is an error naming the whole chain.
+//!
+//! The standard library is compiled into lyronc, so it works wherever lyronc is
+//! installed. Pass `--stdlib <DIR>` or set `LYRON_STDLIB` to a directory to load
+//! it from there instead, e.g. `iorekfiles` while working on it.
+//!
+//! A sandboxed program without the `read-files` capability may only use the
+//! standard library: loading any other file would let it read what
+//! `read_file` can't.
+
+use crate::Result;
+use crate::optimizer;
+use crate::parser::{AstNode, ExprValue, NodePosition, parse_source};
+use crate::sandbox::Capability;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+/// Sources of the standard library, by module name.
+const STDLIB: &[(&str, &str)] = &[
+    ("file", include_str!("../iorekfiles/file.lyr")),
+    ("hello_world", include_str!("../iorekfiles/hello_world.lyr")),
+    ("json", include_str!("../iorekfiles/json.lyr")),
+    ("string", include_str!("../iorekfiles/string.lyr")),
+];
+
+/// Directory the paths of the embedded standard library are in. It doesn't
+/// exist on disk.
+const EMBEDDED_DIR: &str = "<std>";
+
+/// A parsed source file.
+#[derive(Debug)]
+pub struct Module {
+    /// Canonical path of the file, or `<std>/name.lyr` for a module of the
+    /// embedded standard library.
+    pub path: PathBuf,
+    pub ast: Vec<(AstNode, NodePosition)>,
+}
+
+pub struct ModuleLoader {
+    /// Where `std:` modules are loaded from, if not from the binary.
+    std_dir: Option<PathBuf>,
+    /// Where `@:` modules are loaded from, if given.
+    external_dir: Option<PathBuf>,
+    cache: HashMap<PathBuf, Rc<Module>>,
+    /// Files whose imports are being loaded, outermost first.
+    loading: Vec<PathBuf>,
+    /// Run [`optimizer::optimize`] on the modules loaded.
+    optimize: bool,
+    /// Whether modules outside the standard library may be loaded.
+    read_files: bool,
+    /// Text of the modules loaded, by the file name their positions have.
+    sources: HashMap<String, Rc<str>>,
+}
+
+impl ModuleLoader {
+    pub fn new() -> Self {
+        ModuleLoader {
+            std_dir: env::var_os("LYRON_STDLIB").map(PathBuf::from),
+            external_dir: env::var_os("LYRON_EXTERNAL").map(PathBuf::from),
+            cache: HashMap::new(),
+            loading: vec![],
+            optimize: true,
+            read_files: true,
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Load `std:` modules from `dir` instead of the embedded standard library.
+    pub fn with_std_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.std_dir = Some(dir.into());
+        self
+    }
+
+    /// Load `@:` modules from `dir`.
+    pub fn with_external_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.external_dir = Some(dir.into());
+        self
+    }
+
+    /// Optimize the modules loaded from now on, or not.
+    pub fn with_optimizations(mut self, optimize: bool) -> Self {
+        self.set_optimizations(optimize);
+        self
+    }
+
+    pub fn set_optimizations(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Load modules from files other than the standard library, which is the
+    /// default, or not.
+    pub fn set_file_access(&mut self, read_files: bool) {
+        self.read_files = read_files;
+    }
+
+    /// The file `use spec` refers to in the file `from`.
+    pub fn resolve(&self, spec: &str, from: &Path) -> Result<PathBuf> {
+        let path = self.locate(spec, from);
+        if embedded(&path).is_some() || path.is_file() {
+            Ok(path)
+        } else {
+            Err(format!(
+                "cannot find module `{}`, looked for `{}`",
+                spec,
+                path.display()
+            ))
+        }
+    }
+
+    /// Where the file `use spec` refers to in the file `from` would be.
+    fn locate(&self, spec: &str, from: &Path) -> PathBuf {
+        let mut path = if let Some(name) = spec.strip_prefix("std:") {
+            self.std_root().join(name)
+        } else if let Some(name) = spec.strip_prefix("@:") {
+            self.external_dir(from).join(name)
+        } else {
+            from.parent().unwrap_or(Path::new("")).join(spec)
+        };
+        if path.extension().is_none() {
+            path.set_extension("lyr");
+        }
+        path
+    }
+
+    /// The directory `@:` modules used in the file `from` are loaded from.
+    fn external_dir(&self, from: &Path) -> PathBuf {
+        if let Some(dir) = &self.external_dir {
+            return dir.clone();
+        }
+        let exe = env::current_exe().unwrap_or_default();
+        let installed = exe.parent().unwrap_or(Path::new("")).join("external");
+        if installed.is_dir() {
+            return installed;
+        }
+        from.ancestors()
+            .skip(1)
+            .map(|dir| dir.join("iorekfiles/external"))
+            .find(|dir| dir.is_dir())
+            .unwrap_or(installed)
+    }
+
+    fn std_root(&self) -> &Path {
+        self.std_dir.as_deref().unwrap_or(Path::new(EMBEDDED_DIR))
+    }
+
+    /// Whether `path` is in the standard library, without looking at the
+    /// files. Modules loaded from `--stdlib` have canonical paths.
+    fn in_stdlib(&self, path: &Path) -> bool {
+        let root = self.std_root();
+        let canonical = fs::canonicalize(root).ok();
+        let rest = path.strip_prefix(root).or_else(|e| {
+            canonical
+                .as_deref()
+                .map_or(Err(e), |c| path.strip_prefix(c))
+        });
+        rest.is_ok_and(|rest| {
+            rest.components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        })
+    }
+
+    /// Load the module `use spec` refers to in the file `from`, with the
+    /// modules it uses in turn.
+    pub fn load(&mut self, spec: &str, from: &Path) -> Result<Rc<Module>> {
+        // Checked before looking for the file, so whether it exists doesn't
+        // show either.
+        if !self.read_files && !self.in_stdlib(&self.locate(spec, from)) {
+            return Err(format!(
+                "`use \"{}\"` needs the `{}` capability, which wasn't granted",
+                spec,
+                Capability::ReadFiles
+            ));
+        }
+        let path = self.resolve(spec, from)?;
+        let path = match embedded(&path) {
+            Some((name, _)) => Path::new(EMBEDDED_DIR).join(format!("{}.lyr", name)),
+            None => fs::canonicalize(&path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        };
+        if let Some(module) = self.cache.get(&path) {
+            return Ok(module.clone());
+        }
+        if let Some(start) = self.loading.iter().position(|p| *p == path) {
+            let chain = self.loading[start..]
+                .iter()
+                .chain([&path])
+                .map(|p| display(p))
+                .collect::<Vec<_>>();
+            return Err(format!("import cycle: {}", chain.join(" -> ")));
+        }
+
+        let (text, file) = (read(&path)?, display(&path));
+        let mut ast = parse_source(&text, &file)?;
+        self.sources.insert(file, text.into());
+        self.load_imports(&ast, &path)?;
+        if self.optimize {
+            optimizer::optimize(&mut ast);
+        }
+        let module = Rc::new(Module { path, ast });
+        self.cache.insert(module.path.clone(), module.clone());
+        Ok(module)
+    }
+
+    /// The text of the loaded module whose positions are in `file`.
+    pub fn source(&self, file: &str) -> Option<&str> {
+        self.sources.get(file).map(|text| &**text)
+    }
+
+    /// Load every module used at the top level of `ast`, which is the program
+    /// in the file `file`.
+    pub fn load_imports(
+        &mut self,
+        ast: &[(AstNode, NodePosition)],
+        file: &Path,
+    ) -> Result<Vec<Rc<Module>>> {
+        self.loading
+            .push(fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf()));
+        let modules = ast
+            .iter()
+            .filter_map(|(node, pos)| match node {
+                AstNode::Expression(ExprValue::Use(spec, _)) => Some((spec, pos)),
+                _ => None,
+            })
+            .map(|(spec, pos)| {
+                self.load(spec, file).map_err(|e| {
+                    format!(
+                        "{}\n    at {}:{} in file `{}`.",
+                        e, pos.line_no, pos.pos, pos.file
+                    )
+                })
+            })
+            .collect();
+        self.loading.pop();
+        modules
+    }
+}
+
+impl Default for ModuleLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The source of the module at `path`, which may be in the embedded standard
+/// library.
+pub fn read(path: &Path) -> Result<String> {
+    match embedded(path) {
+        Some((_, text)) => Ok(text.to_string()),
+        None => fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// Name and source of the embedded module at `path`.
+fn embedded(path: &Path) -> Option<(&'static str, &'static str)> {
+    let name = path.strip_prefix(EMBEDDED_DIR).ok()?.with_extension("");
+    let name = name
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?
+        .join("/");
+    STDLIB.iter().find(|(n, _)| *n == name).copied()
+}
+
+/// `path` relative to the working directory, if it is inside it.
+fn display(path: &Path) -> String {
+    let cwd = env::current_dir().unwrap_or_default();
+    path.strip_prefix(&cwd)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ModuleLoader, STDLIB};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+
+    /// A fresh directory with the given files in it.
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lyron-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, text) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn resolve() {
+        let dir = tree(
+            "resolve",
+            &[
+                ("std/json.lyr", ""),
+                ("vendor/math/consts.lyr", ""),
+                ("app/util.lyr", ""),
+            ],
+        );
+        let loader = ModuleLoader::new()
+            .with_std_dir(dir.join("std"))
+            .with_external_dir(dir.join("vendor"));
+        let main = dir.join("app/main.lyr");
+
+        assert_eq!(
+            loader.resolve("std:json", &main).unwrap(),
+            dir.join("std/json.lyr")
+        );
+        assert_eq!(
+            loader.resolve("@:math/consts", &main).unwrap(),
+            dir.join("vendor/math/consts.lyr")
+        );
+        assert_eq!(
+            loader.resolve("util", &main).unwrap(),
+            dir.join("app/util.lyr")
+        );
+        assert_eq!(
+            loader.resolve("util.lyr", &main).unwrap(),
+            dir.join("app/util.lyr")
+        );
+        assert!(
+            loader
+                .resolve("std:nope", &main)
+                .unwrap_err()
+                .starts_with("cannot find module `std:nope`")
+        );
+
+        // Found at run time, not where lyronc was built: next to the
+        // executable, or in a checkout.
+        let mut loader = ModuleLoader::new();
+        loader.external_dir = None;
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(
+            loader.external_dir(&main),
+            exe.parent().unwrap().join("external")
+        );
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/newlex.lyr");
+        assert_eq!(
+            loader.resolve("@:mathutils/consts", &examples).unwrap(),
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("iorekfiles/external/mathutils/consts.lyr")
+        );
+        assert!(
+            loader
+                .resolve("@:mathutils/consts", Path::new("examples/newlex.lyr"))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn embedded_stdlib() {
+        let mut loader = ModuleLoader::new();
+        loader.std_dir = None;
+        let json = loader
+            .load("std:json", Path::new("/nowhere/main.lyr"))
+            .unwrap();
+        assert_eq!(json.path, Path::new("<std>/json.lyr"));
+        assert!(!json.ast.is_empty());
+        assert!(loader.resolve("std:nope", Path::new("x.lyr")).is_err());
+
+        // Relative paths in the standard library stay in it.
+        let string = loader.load("./string", &json.path).unwrap();
+        assert_eq!(string.path, Path::new("<std>/string.lyr"));
+        assert!(Rc::ptr_eq(
+            &string,
+            &loader.load("std:string", &json.path).unwrap()
+        ));
+
+        // Every module of the standard library is embedded.
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("iorekfiles");
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "lyr") {
+                let name = path.file_stem().unwrap().to_str().unwrap();
+                let text = fs::read_to_string(&path).unwrap();
+                assert!(STDLIB.contains(&(name, text.as_str())), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn stdlib_override() {
+        let dir = tree(
+            "stdlib",
+            &[
+                (
+                    "std/json.lyr",
+                    "use \"./string\"\ndef dumps() -> Any \"edited\"\n",
+                ),
+                ("std/string.lyr", "x = 1\n"),
+            ],
+        );
+        let mut loader = ModuleLoader::new().with_std_dir(dir.join("std"));
+        let json = loader.load("std:json", &dir.join("main.lyr")).unwrap();
+        assert_eq!(
+            json.path,
+            fs::canonicalize(dir.join("std/json.lyr")).unwrap()
+        );
+        assert_eq!(json.ast.len(), 2);
+
+        // The rest of the standard library comes from the same directory.
+        let string = loader.load("std:string", &dir.join("main.lyr")).unwrap();
+        assert_eq!(
+            string.path,
+            fs::canonicalize(dir.join("std/string.lyr")).unwrap()
+        );
+        assert_eq!(loader.cache.len(), 2);
+        assert!(loader.load("std:file", &dir.join("main.lyr")).is_err());
+    }
+
+    #[test]
+    fn load() {
+        let dir = tree(
+            "load",
+            &[
+                ("a.lyr", "use \"b\"\nuse \"./c\"\nx = 1\n"),
+                ("b.lyr", "use \"c\"\n"),
+                ("c.lyr", "def c() -> Any 1\n"),
+            ],
+        );
+        let mut loader = ModuleLoader::new();
+        let main = dir.join("main.lyr");
+        let a = loader.load("a", &main).unwrap();
+        assert_eq!(a.ast.len(), 3);
+
+        // Loaded once, however it's named.
+        let c = loader.load("./c.lyr", &main).unwrap();
+        assert!(Rc::ptr_eq(
+            &c,
+            &loader.load("c", &dir.join("b.lyr")).unwrap()
+        ));
+        assert_eq!(loader.cache.len(), 3);
+    }
+
+    #[test]
+    fn cycles() {
+        let dir = tree(
+            "cycles",
+            &[
+                ("a.lyr", "use \"b\"\n"),
+                ("b.lyr", "x = 1\nuse \"c\"\n"),
+                ("c.lyr", "use \"a\"\n"),
+                ("self.lyr", "use \"self\"\n"),
+            ],
+        );
+        let main = dir.join("main.lyr");
+
+        let e = ModuleLoader::new().load("a", &main).unwrap_err();
+        assert!(e.starts_with("import cycle: "), "{}", e);
+        let chain = e.lines().next().unwrap();
+        let names = chain
+            .trim_start_matches("import cycle: ")
+            .split(" -> ")
+            .map(|p| Path::new(p).file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a.lyr", "b.lyr", "c.lyr", "a.lyr"]);
+        assert!(e.contains("at 2:"), "{}", e);
+
+        let e = ModuleLoader::new().load("self", &main).unwrap_err();
+        assert!(e.contains("self.lyr -> "), "{}", e);
+    }
+}
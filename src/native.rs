@@ -0,0 +1,676 @@
+//! Native code: extensions loaded with `extern "path"` and C functions
+//! declared with `extern "path" def f(...) -> Type`.
+//!
+//! The C side of the extension ABI is `include/lyron.h`. Loading an extension
+//! calls its `lyron_init`, which registers each function the library provides;
+//! those become [`Builtin`]s taking and returning `Any`.
+//!
+//! Declared C functions are called through libffi, converting each argument
+//! by its declared [`CType`]. Either way the library stays loaded as long as
+//! one of its builtins is around.
+
+use crate::Result;
+use crate::builtins::Builtin;
+use crate::interpreter::Value;
+use crate::parser::External;
+
+use libffi::middle::{Arg, Cif, CodePtr, Type};
+use libloading::{Library, Symbol};
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// `LYRON_ABI_VERSION` of the header this module implements.
+const ABI_VERSION: u32 = 1;
+
+const TAG_NONE: c_int = 0;
+const TAG_BOOL: c_int = 1;
+const TAG_INT: c_int = 2;
+const TAG_DOUBLE: c_int = 3;
+const TAG_STR: c_int = 4;
+
+const LYRON_OK: c_int = 0;
+
+/// `LyronValue`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawValue {
+    tag: c_int,
+    as_: Payload,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union Payload {
+    b: bool,
+    i: i64,
+    d: f64,
+    str: RawStr,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawStr {
+    ptr: *const c_char,
+    len: usize,
+}
+
+/// `LyronNativeFn`.
+type RawFn = unsafe extern "C" fn(*const RawValue, usize, *mut RawValue) -> c_int;
+
+/// `LyronRegistrar`.
+#[repr(C)]
+struct Registrar {
+    abi_version: u32,
+    ctx: *mut c_void,
+    register_fn: unsafe extern "C" fn(*mut c_void, *const c_char, i32, RawFn),
+}
+
+/// A function registered by `lyron_init`: name, arity (-1 for any) and code.
+type Registered = (String, i32, RawFn);
+
+/// The library `extern spec` refers to in the file `from`. Paths with a
+/// directory are relative to `from`; bare names like `libm.so.6` are left
+/// to the system's library search.
+pub fn resolve(spec: &str, from: &Path) -> PathBuf {
+    let path = Path::new(spec);
+    if path.is_relative() && path.components().count() > 1 {
+        from.parent().unwrap_or(Path::new("")).join(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+fn open(path: &Path) -> Result<Library> {
+    // Safety: loading runs the library's initializers, which we trust like
+    // any code the program asks for.
+    unsafe { Library::new(path) }.map_err(|e| format!("cannot load `{}`: {}", path.display(), e))
+}
+
+/// Load the extension at `path` and return the functions it registers.
+pub fn load(path: &Path) -> Result<Vec<Builtin>> {
+    let library = open(path)?;
+    let mut registered: Vec<Registered> = vec![];
+    {
+        // Safety: `lyron_init` has this signature by the ABI.
+        let init: Symbol<unsafe extern "C" fn(*const Registrar) -> c_int> =
+            unsafe { library.get(b"lyron_init") }
+                .map_err(|_| format!("`{}` has no `lyron_init`", path.display()))?;
+        let registrar = Registrar {
+            abi_version: ABI_VERSION,
+            ctx: &mut registered as *mut Vec<Registered> as *mut c_void,
+            register_fn: register,
+        };
+        // Safety: the registrar and the vector it points to outlive the call.
+        let code = unsafe { init(&registrar) };
+        if code != 0 {
+            return Err(format!(
+                "`lyron_init` of `{}` failed with code {}",
+                path.display(),
+                code
+            ));
+        }
+    }
+
+    let library = Rc::new(library);
+    Ok(registered
+        .into_iter()
+        .map(|(name, arity, f)| builtin(name, arity, f, library.clone()))
+        .collect())
+}
+
+/// `register_fn` of the registrar handed to `lyron_init`.
+unsafe extern "C" fn register(ctx: *mut c_void, name: *const c_char, arity: i32, f: RawFn) {
+    if ctx.is_null() || name.is_null() {
+        return;
+    }
+    // Safety: `ctx` is the vector `load` passed, `name` a C string by the ABI.
+    let (registered, name) = unsafe { (&mut *(ctx as *mut Vec<Registered>), CStr::from_ptr(name)) };
+    registered.push((name.to_string_lossy().into_owned(), arity, f));
+}
+
+fn builtin(name: String, arity: i32, f: RawFn, library: Rc<Library>) -> Builtin {
+    let params = (1..=arity.max(0))
+        .map(|i| format!("arg{}", i))
+        .collect::<Vec<_>>();
+    let params = params
+        .iter()
+        .map(|p| (p.as_str(), "Any"))
+        .collect::<Vec<_>>();
+    let builtin_name = name.clone();
+    let builtin = Builtin::new(&name, &params, "Any", move |_, args| {
+        // The function's code lives in the library.
+        let _library = &library;
+        call(&builtin_name, f, &args)
+    });
+    if arity < 0 {
+        builtin.variadic()
+    } else {
+        builtin
+    }
+}
+
+/// Call the native function `f` with `args`.
+fn call(name: &str, f: RawFn, args: &[Value]) -> Result<Value> {
+    // Strings passed in, which must outlive the call.
+    let mut strings = vec![];
+    let raw = args
+        .iter()
+        .map(|value| to_raw(value, &mut strings))
+        .collect::<Result<Vec<_>>>()
+        .map_err(|e| format!("in call to `{}`: {}", name, e))?;
+    let mut result = RawValue {
+        tag: TAG_NONE,
+        as_: Payload { i: 0 },
+    };
+    // Safety: `f` was registered with this signature, and the arguments and
+    // their strings live until it returns.
+    let code = unsafe { f(raw.as_ptr(), raw.len(), &mut result) };
+    let value = from_raw(result).map_err(|e| format!("`{}` {}", name, e))?;
+    if code == LYRON_OK {
+        return Ok(value);
+    }
+    match value {
+        Value::Str(message) => Err(message),
+        _ => Err(format!("`{}` failed with code {}", name, code)),
+    }
+}
+
+fn to_raw(value: &Value, strings: &mut Vec<CString>) -> Result<RawValue> {
+    let (tag, as_) = match value {
+        Value::None => (TAG_NONE, Payload { i: 0 }),
+        Value::Boolean(b) => (TAG_BOOL, Payload { b: *b }),
+        Value::Integer(i) => (TAG_INT, Payload { i: *i }),
+        Value::Double(d) => (TAG_DOUBLE, Payload { d: *d }),
+        Value::Str(s) => {
+            let c = CString::new(s.as_str())
+                .map_err(|_| "strings passed to native code can't contain NUL".to_string())?;
+            let str = RawStr {
+                ptr: c.as_ptr(),
+                len: s.len(),
+            };
+            strings.push(c);
+            (TAG_STR, Payload { str })
+        }
+        v => {
+            return Err(format!("cannot pass {} to native code", v.type_name()));
+        }
+    };
+    Ok(RawValue { tag, as_ })
+}
+
+fn from_raw(value: RawValue) -> Result<Value> {
+    // Safety: the tag says which field of the payload is set.
+    unsafe {
+        match value.tag {
+            TAG_NONE => Ok(Value::None),
+            TAG_BOOL => Ok(Value::Boolean(value.as_.b)),
+            TAG_INT => Ok(Value::Integer(value.as_.i)),
+            TAG_DOUBLE => Ok(Value::Double(value.as_.d)),
+            TAG_STR => {
+                let RawStr { ptr, len } = value.as_.str;
+                if ptr.is_null() {
+                    return Ok(Value::Str(String::new()));
+                }
+                let bytes = std::slice::from_raw_parts(ptr as *const u8, len);
+                String::from_utf8(bytes.to_vec())
+                    .map(Value::Str)
+                    .map_err(|_| "returned a string that isn't UTF-8".to_string())
+            }
+            tag => Err(format!("returned a value with unknown tag {}", tag)),
+        }
+    }
+}
+
+/// A C type a declared function takes or returns, by the name it's declared
+/// with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CType {
+    I8,
+    I16,
+    I32,
+    /// `i64` or `Int`
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    /// `f64` or `Double`
+    F64,
+    /// A C `bool`.
+    Bool,
+    /// A NUL-terminated `char *`, or `none` for NULL.
+    Str,
+    /// Any other pointer, as an Int address, or `none` for NULL.
+    Ptr,
+    /// `void`, only as a return type.
+    None,
+}
+
+impl CType {
+    pub fn parse(name: &str) -> Option<CType> {
+        Some(match name {
+            "i8" => CType::I8,
+            "i16" => CType::I16,
+            "i32" => CType::I32,
+            "i64" | "Int" => CType::I64,
+            "u8" => CType::U8,
+            "u16" => CType::U16,
+            "u32" => CType::U32,
+            "u64" => CType::U64,
+            "f32" => CType::F32,
+            "f64" | "Double" => CType::F64,
+            "Bool" => CType::Bool,
+            "Str" => CType::Str,
+            "Ptr" => CType::Ptr,
+            "None" => CType::None,
+            _ => return None,
+        })
+    }
+
+    fn ffi_type(self) -> Type {
+        match self {
+            CType::I8 => Type::i8(),
+            CType::I16 => Type::i16(),
+            CType::I32 => Type::i32(),
+            CType::I64 => Type::i64(),
+            CType::U8 | CType::Bool => Type::u8(),
+            CType::U16 => Type::u16(),
+            CType::U32 => Type::u32(),
+            CType::U64 => Type::u64(),
+            CType::F32 => Type::f32(),
+            CType::F64 => Type::f64(),
+            CType::Str | CType::Ptr => Type::pointer(),
+            CType::None => Type::void(),
+        }
+    }
+
+    /// The Lyron type of the values passed for this C type.
+    fn lyron_type(self) -> &'static str {
+        match self {
+            CType::F32 | CType::F64 => "Double",
+            CType::Bool => "Bool",
+            CType::Str | CType::Ptr | CType::None => "Any",
+            _ => "Int",
+        }
+    }
+}
+
+/// An argument converted for C. Strings are pointers into CStrings kept
+/// alongside.
+enum CValue {
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Ptr(*const c_void),
+}
+
+impl CValue {
+    fn arg(&self) -> Arg {
+        match self {
+            CValue::I8(v) => Arg::new(v),
+            CValue::I16(v) => Arg::new(v),
+            CValue::I32(v) => Arg::new(v),
+            CValue::I64(v) => Arg::new(v),
+            CValue::U8(v) => Arg::new(v),
+            CValue::U16(v) => Arg::new(v),
+            CValue::U32(v) => Arg::new(v),
+            CValue::U64(v) => Arg::new(v),
+            CValue::F32(v) => Arg::new(v),
+            CValue::F64(v) => Arg::new(v),
+            CValue::Ptr(v) => Arg::new(v),
+        }
+    }
+}
+
+/// Bind the C function `f` declares, looking its library up from the file
+/// `from`.
+pub fn bind(f: &External, from: &Path) -> Result<Builtin> {
+    if f.args.vararg.is_some()
+        || f.args.kwarg.is_some()
+        || f.args.default.iter().any(|d| d.is_some())
+    {
+        return Err(format!(
+            "extern function `{}` can only have plain parameters",
+            f.name
+        ));
+    }
+    let ctype = |name: &str| {
+        CType::parse(name)
+            .ok_or_else(|| format!("extern function `{}`: no C type `{}`", f.name, name))
+    };
+    let params = f
+        .args
+        .type_
+        .iter()
+        .map(|t| ctype(t))
+        .collect::<Result<Vec<_>>>()?;
+    if params.contains(&CType::None) {
+        return Err(format!(
+            "extern function `{}`: None is only a return type",
+            f.name
+        ));
+    }
+    let returns = ctype(&f.return_type)?;
+
+    let path = resolve(&f.library, from);
+    let library = open(&path)?;
+    // Safety: only the address is taken; calls go through the declared types.
+    let code = unsafe { library.get::<unsafe extern "C" fn()>(f.name.as_bytes()) }
+        .map(|symbol| CodePtr::from_fun(*symbol))
+        .map_err(|_| format!("`{}` has no function `{}`", path.display(), f.name))?;
+    let cif = Cif::new(params.iter().map(|t| t.ffi_type()), returns.ffi_type());
+
+    let names = f
+        .args
+        .name
+        .iter()
+        .zip(&params)
+        .map(|(n, t)| (n.as_str(), t.lyron_type()))
+        .collect::<Vec<_>>();
+    let name = f.name.clone();
+    Ok(Builtin::new(
+        &f.name,
+        &names,
+        &f.return_type,
+        move |_, args| {
+            // The function's code lives in the library.
+            let _library = &library;
+            let mut strings = vec![];
+            let values = args
+                .iter()
+                .zip(&params)
+                .map(|(value, ctype)| to_c(value, *ctype, &mut strings))
+                .collect::<Result<Vec<_>>>()
+                .map_err(|e| format!("in call to `{}`: {}", name, e))?;
+            let args = values.iter().map(CValue::arg).collect::<Vec<_>>();
+            // Safety: the declaration promises `code` has this signature, and the
+            // arguments and their strings live until it returns.
+            unsafe { call_c(&cif, code, &args, returns) }
+        },
+    ))
+}
+
+fn to_c(value: &Value, ctype: CType, strings: &mut Vec<CString>) -> Result<CValue> {
+    fn int<T: TryFrom<i64>>(value: &Value, name: &str) -> Result<T> {
+        match value {
+            Value::Integer(i) => {
+                T::try_from(*i).map_err(|_| format!("{} doesn't fit in {}", i, name))
+            }
+            v => Err(format!(
+                "expected an Int for {} but got {}",
+                name,
+                v.type_name()
+            )),
+        }
+    }
+    let pointer = |value: &Value| match value {
+        Value::None => Ok(std::ptr::null()),
+        Value::Integer(i) => Ok(*i as usize as *const c_void),
+        v => Err(format!(
+            "expected an address for Ptr but got {}",
+            v.type_name()
+        )),
+    };
+    let double = |value: &Value| {
+        value
+            .as_double()
+            .ok_or_else(|| format!("expected a Double but got {}", value.type_name()))
+    };
+    Ok(match ctype {
+        CType::I8 => CValue::I8(int(value, "i8")?),
+        CType::I16 => CValue::I16(int(value, "i16")?),
+        CType::I32 => CValue::I32(int(value, "i32")?),
+        CType::I64 => CValue::I64(int(value, "i64")?),
+        CType::U8 => CValue::U8(int(value, "u8")?),
+        CType::U16 => CValue::U16(int(value, "u16")?),
+        CType::U32 => CValue::U32(int(value, "u32")?),
+        CType::U64 => CValue::U64(int(value, "u64")?),
+        CType::F32 => CValue::F32(double(value)? as f32),
+        CType::F64 => CValue::F64(double(value)?),
+        CType::Bool => CValue::U8(value.is_truthy().into()),
+        CType::Str => match value {
+            Value::None => CValue::Ptr(std::ptr::null()),
+            Value::Str(s) => {
+                let c = CString::new(s.as_str())
+                    .map_err(|_| "strings passed to native code can't contain NUL".to_string())?;
+                let ptr = c.as_ptr() as *const c_void;
+                strings.push(c);
+                CValue::Ptr(ptr)
+            }
+            v => return Err(format!("expected a Str but got {}", v.type_name())),
+        },
+        CType::Ptr => CValue::Ptr(pointer(value)?),
+        CType::None => unreachable!("rejected when binding"),
+    })
+}
+
+/// Call `code` and convert what it returns. Integers smaller than a register
+/// come back widened, so they're read as 64 bits and narrowed.
+///
+/// # Safety
+///
+/// `code` must have the signature `cif` describes, and `args` match it.
+unsafe fn call_c(cif: &Cif, code: CodePtr, args: &[Arg], returns: CType) -> Result<Value> {
+    // Safety: passed on to the caller.
+    unsafe {
+        Ok(match returns {
+            CType::F32 => Value::Double(cif.call::<f32>(code, args).into()),
+            CType::F64 => Value::Double(cif.call::<f64>(code, args)),
+            CType::None => {
+                cif.call::<u64>(code, args);
+                Value::None
+            }
+            CType::Str => {
+                let ptr = cif.call::<*const c_char>(code, args);
+                if ptr.is_null() {
+                    Value::None
+                } else {
+                    Value::Str(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+                }
+            }
+            CType::Ptr => Value::Integer(cif.call::<usize>(code, args) as i64),
+            int => {
+                let raw = cif.call::<u64>(code, args);
+                match int {
+                    CType::I8 => Value::Integer((raw as i8).into()),
+                    CType::I16 => Value::Integer((raw as i16).into()),
+                    CType::I32 => Value::Integer((raw as i32).into()),
+                    CType::U8 => Value::Integer((raw as u8).into()),
+                    CType::U16 => Value::Integer((raw as u16).into()),
+                    CType::U32 => Value::Integer((raw as u32).into()),
+                    CType::U64 => Value::from_bigint(raw.into()),
+                    CType::Bool => Value::Boolean(raw as u8 != 0),
+                    _ => Value::Integer(raw as i64),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    const EXTENSION: &str = r#"
+        #include <stdio.h>
+        #include "lyron.h"
+
+        static int add(const LyronValue *args, size_t nargs, LyronValue *result) {
+            if (args[0].tag != LYRON_INT || args[1].tag != LYRON_INT)
+                return lyron_error(result, "add takes two Ints");
+            *result = lyron_int(args[0].as.i + args[1].as.i);
+            return LYRON_OK;
+        }
+
+        static int greet(const LyronValue *args, size_t nargs, LyronValue *result) {
+            static char buffer[64];
+            snprintf(buffer, sizeof buffer, "hello, %s", args[0].as.str.ptr);
+            *result = lyron_str(buffer);
+            return LYRON_OK;
+        }
+
+        static int count(const LyronValue *args, size_t nargs, LyronValue *result) {
+            *result = lyron_int((int64_t)nargs);
+            return LYRON_OK;
+        }
+
+        int lyron_init(const LyronRegistrar *r) {
+            if (r->abi_version != LYRON_ABI_VERSION)
+                return 1;
+            r->register_fn(r->ctx, "add", 2, add);
+            r->register_fn(r->ctx, "greet", 1, greet);
+            r->register_fn(r->ctx, "count", -1, count);
+            return 0;
+        }
+    "#;
+
+    /// Compile `source` into `dir/lib/libname.so`, or None without a C compiler.
+    fn build(dir: &Path, name: &str, source: &str) -> Option<PathBuf> {
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        let c = dir.join(format!("{}.c", name));
+        fs::write(&c, source).unwrap();
+        let library = dir.join("lib").join(format!("lib{}.so", name));
+        let include = Path::new(env!("CARGO_MANIFEST_DIR")).join("include");
+        let status = Command::new("cc")
+            .args(["-shared", "-fPIC", "-I"])
+            .arg(include)
+            .arg(&c)
+            .arg("-o")
+            .arg(&library)
+            .status();
+        match status {
+            Ok(status) if status.success() => Some(library),
+            _ => {
+                eprintln!("skipping: cannot build a C library with `cc`");
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn extensions() {
+        let dir = std::env::temp_dir().join(format!("lyron-native-{}", std::process::id()));
+        let Some(_) = build(&dir, "ext", EXTENSION) else {
+            return;
+        };
+        let main = dir.join("main.lyr");
+        let run = |src: &str| {
+            let mut interpreter = Interpreter::new();
+            interpreter
+                .run_text(src, main.to_str().unwrap())
+                .map(|v| v.repr())
+        };
+
+        let src = "extern \"lib/libext.so\"\n(add(40, 2), greet(\"lyron\"), count(1, \"a\", none))";
+        assert_eq!(run(src).unwrap(), "(42, \"hello, lyron\", 3)");
+
+        let e = run("extern \"lib/libext.so\"\nadd(1, \"a\")").unwrap_err();
+        assert!(e.starts_with("add takes two Ints"), "{}", e);
+        let e = run("extern \"lib/libext.so\"\nadd(1)").unwrap_err();
+        assert!(e.contains("in call to `add`"), "{}", e);
+        let e = run("extern \"lib/libext.so\"\ngreet([1])").unwrap_err();
+        assert!(e.contains("cannot pass Array to native code"), "{}", e);
+        let e = run("extern \"lib/missing.so\"").unwrap_err();
+        assert!(e.starts_with("cannot load `"), "{}", e);
+
+        build(&dir, "noinit", "int unrelated(void) { return 0; }").unwrap();
+        let e = run("extern \"lib/libnoinit.so\"").unwrap_err();
+        assert!(e.contains("has no `lyron_init`"), "{}", e);
+    }
+
+    #[test]
+    fn c_functions() {
+        let dir = std::env::temp_dir().join(format!("lyron-cffi-{}", std::process::id()));
+        let source = r#"
+            #include <stdint.h>
+            #include <stdbool.h>
+            #include <string.h>
+
+            int64_t scale(int32_t x, double f) { return (int64_t)(x * f); }
+            int8_t wrap(int8_t x) { return x + 1; }
+            uint64_t big(void) { return UINT64_MAX; }
+            float half(float x) { return x / 2; }
+            bool empty(const char *s) { return s == NULL || *s == 0; }
+            const char *skip(const char *s, int32_t n) { return s + n; }
+            static int64_t counter;
+            void bump(void) { counter++; }
+            int64_t count(void) { return counter; }
+        "#;
+        let Some(_) = build(&dir, "plain", source) else {
+            return;
+        };
+        let main = dir.join("main.lyr");
+        let declarations = [
+            "scale(x: i32, f: f64) -> i64",
+            "wrap(x: i8) -> i8",
+            "big() -> u64",
+            "half(x: f32) -> f32",
+            "empty(s: Str) -> Bool",
+            "skip(s: Str, n: i32) -> Str",
+            "bump() -> None",
+            "count() -> Int",
+        ]
+        .map(|d| format!("extern \"lib/libplain.so\" def {}\n", d))
+        .concat();
+        let run = |src: &str| {
+            Interpreter::new()
+                .run_text(&(declarations.clone() + src), main.to_str().unwrap())
+                .map(|v| v.repr())
+        };
+
+        assert_eq!(run("scale(3, 2.5)").unwrap(), "7");
+        assert_eq!(run("scale(3, 2)").unwrap(), "6");
+        assert_eq!(run("wrap(127)").unwrap(), "-128");
+        assert_eq!(run("big()").unwrap(), "18446744073709551615");
+        assert_eq!(run("half(3)").unwrap(), "1.5");
+        assert_eq!(
+            run("(empty(\"\"), empty(none), empty(\"x\"))").unwrap(),
+            "(true, true, false)"
+        );
+        assert_eq!(run("skip(\"hello\", 2)").unwrap(), "\"llo\"");
+        assert_eq!(run("bump()\nbump()\ncount()").unwrap(), "2");
+
+        let e = run("wrap(300)").unwrap_err();
+        assert!(e.contains("300 doesn't fit in i8"), "{}", e);
+        let e = run("scale(\"a\", 1)").unwrap_err();
+        assert!(e.contains("expected Int for `x` but got Str"), "{}", e);
+
+        let e = Interpreter::new()
+            .run_text(
+                "x = 1\nextern \"lib/libplain.so\" def nope() -> Int",
+                main.to_str().unwrap(),
+            )
+            .unwrap_err();
+        assert!(
+            e.contains("has no function `nope`") && e.contains("at 2:"),
+            "{}",
+            e
+        );
+        let e = Interpreter::new()
+            .run_text(
+                "extern \"lib/libplain.so\" def wrap(x: char) -> Int",
+                main.to_str().unwrap(),
+            )
+            .unwrap_err();
+        assert!(e.contains("no C type `char`"), "{}", e);
+        let e = Interpreter::new()
+            .run_text(
+                "extern \"lib/libplain.so\" def wrap(*x: i8) -> Int",
+                main.to_str().unwrap(),
+            )
+            .unwrap_err();
+        assert!(e.contains("only have plain parameters"), "{}", e);
+    }
+}
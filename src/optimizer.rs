@@ -0,0 +1,243 @@
+//! Simplifying programs before they run.
+//!
+//! [`optimize`] folds operators whose operands are literals into the literal
+//! they evaluate to, using the interpreter's own operators so results don't
+//! change. `if`s and `while`s with a constant condition lose the branch that
+//! can't run, and literals in the middle of a `do` block, whose values are
+//! thrown away, are dropped. Operations that would fail, like `1 / 0`, or
+//! make a large string or integer, like `"ab" * 1000000`, are left for the
+//! program to run, where the sandbox limits apply to them.
+
+use crate::interpreter::{Value, ops};
+use crate::lexer::tokens::TokenType;
+use crate::parser::{AstNode, ExprValue, Function, NodePosition};
+
+/// Largest string or big integer folded, in bytes.
+const MAX_FOLDED: usize = 1024;
+
+/// Optimize every function, method and statement of `ast`.
+pub fn optimize(ast: &mut Vec<(AstNode, NodePosition)>) {
+    for (node, _) in ast.iter_mut() {
+        match node {
+            AstNode::FunctionDef(f) => optimize_function(f),
+            AstNode::Class(c) => c.fns.iter_mut().for_each(|(f, _)| optimize_function(f)),
+            AstNode::Expression(e) => fold(e),
+            AstNode::Extern(_) => {}
+        }
+    }
+    // The last statement is the value of the program.
+    let last = ast.len().saturating_sub(1);
+    let mut i = 0;
+    ast.retain(|(node, _)| {
+        i += 1;
+        i > last || !matches!(node, AstNode::Expression(e) if is_pure(e))
+    });
+}
+
+fn optimize_function(f: &mut Function) {
+    f.args.default.iter_mut().flatten().for_each(fold);
+    fold(&mut f.expression.0);
+}
+
+/// Simplify `expr` in place.
+pub fn fold(expr: &mut ExprValue) {
+    match expr {
+        ExprValue::FnCall(_, args, kwargs, _) => {
+            args.iter_mut().for_each(fold);
+            kwargs.iter_mut().for_each(|(_, e)| fold(e));
+        }
+        ExprValue::UnOp(op, e) => {
+            fold(e);
+            if let Some(value) = constant(e).and_then(|v| ops::unary(op, v).ok()) {
+                replace(expr, value);
+            }
+        }
+        ExprValue::BinOp(l, op, r, _) => {
+            fold(l);
+            // The member of `a.b` is a name, not a value.
+            if **op != TokenType::Dot {
+                fold(r);
+            }
+            let Some(left) = constant(l) else {
+                return;
+            };
+            match **op {
+                TokenType::Dot => {}
+                // `and` and `or` evaluate to one of their operands.
+                TokenType::And | TokenType::Or => {
+                    let short = left.is_truthy() == (**op == TokenType::Or);
+                    let kept =
+                        std::mem::replace(&mut **(if short { l } else { r }), ExprValue::None);
+                    *expr = kept;
+                }
+                _ => {
+                    let value = constant(r)
+                        .filter(|right| ops::result_size(op, &left, right) <= MAX_FOLDED)
+                        .and_then(|right| ops::binary(op, left, right).ok())
+                        .filter(|value| value.size() <= MAX_FOLDED);
+                    if let Some(value) = value {
+                        replace(expr, value);
+                    }
+                }
+            }
+        }
+        ExprValue::Interpolation(parts) => {
+            parts.iter_mut().for_each(fold);
+            let text = parts
+                .iter()
+                .map(|p| constant(p).map(|v| v.to_string()))
+                .collect::<Option<String>>()
+                .filter(|text| text.len() <= MAX_FOLDED);
+            if let Some(text) = text {
+                *expr = ExprValue::Str(text);
+            }
+        }
+        ExprValue::IfElse { cond, if_, else_ } => {
+            fold(cond);
+            // The branch that can't run isn't folded.
+            if let Some(cond) = constant(cond) {
+                let taken = if cond.is_truthy() { if_ } else { else_ };
+                let kept = std::mem::replace(&mut **taken, ExprValue::None);
+                *expr = kept;
+                fold(expr);
+            } else {
+                fold(if_);
+                fold(else_);
+            }
+        }
+        ExprValue::While(cond, body) => {
+            fold(cond);
+            fold(body);
+            if constant(cond).is_some_and(|c| !c.is_truthy()) {
+                *expr = ExprValue::None;
+            }
+        }
+        ExprValue::Do(exprs) => {
+            exprs.iter_mut().for_each(fold);
+            // Only the value of the last expression is used.
+            let last = exprs.len().saturating_sub(1);
+            let mut i = 0;
+            exprs.retain(|e| {
+                i += 1;
+                i > last || !is_pure(e)
+            });
+        }
+        ExprValue::Assign { value, .. }
+        | ExprValue::AugAssign { value, .. }
+        | ExprValue::Walrus { value, .. }
+        | ExprValue::Destructure { value, .. }
+        | ExprValue::Return(value) => fold(value),
+        ExprValue::Array(exprs) | ExprValue::Tuple(exprs) => exprs.iter_mut().for_each(fold),
+        ExprValue::Boolean(_)
+        | ExprValue::Integer(_)
+        | ExprValue::BigInteger(_)
+        | ExprValue::Double(_)
+        | ExprValue::Str(_)
+        | ExprValue::Identifier(_)
+        | ExprValue::VarDecl { .. }
+        | ExprValue::Use(..)
+        | ExprValue::Extern(_)
+        | ExprValue::None => {}
+    }
+}
+
+/// The value of a literal.
+fn constant(expr: &ExprValue) -> Option<Value> {
+    Some(match expr {
+        ExprValue::Boolean(b) => Value::Boolean(*b),
+        ExprValue::Integer(i) => Value::Integer(*i),
+        ExprValue::BigInteger(i) => Value::BigInteger(i.clone()),
+        ExprValue::Double(d) => Value::Double(*d),
+        ExprValue::Str(s) => Value::Str(s.clone()),
+        ExprValue::None => Value::None,
+        _ => return None,
+    })
+}
+
+/// Replace `expr` with the literal for `value`, if there is one.
+fn replace(expr: &mut ExprValue, value: Value) {
+    *expr = match value {
+        Value::Boolean(b) => ExprValue::Boolean(b),
+        Value::Integer(i) => ExprValue::Integer(i),
+        Value::BigInteger(i) => ExprValue::BigInteger(i),
+        Value::Double(d) => ExprValue::Double(d),
+        Value::Str(s) => ExprValue::Str(s),
+        Value::None => ExprValue::None,
+        _ => return,
+    };
+}
+
+/// Whether evaluating `expr` can't do anything but make its value.
+fn is_pure(expr: &ExprValue) -> bool {
+    match expr {
+        ExprValue::Array(exprs) | ExprValue::Tuple(exprs) => exprs.iter().all(is_pure),
+        _ => constant(expr).is_some(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::optimize;
+    use crate::parser::{AstNode, ExprValue, parse_source};
+
+    /// The statements of `src` after optimizing, as debug strings.
+    fn optimized(src: &str) -> Vec<String> {
+        let mut ast = parse_source(src, "<test>").unwrap();
+        optimize(&mut ast);
+        ast.iter()
+            .map(|(node, _)| match node {
+                AstNode::Expression(e) => format!("{:?}", e),
+                AstNode::FunctionDef(f) => format!("{:?}", f.expression.0),
+                node => format!("{:?}", node),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(optimized("3 + 4 * 2"), ["Integer(11)"]);
+        assert_eq!(optimized("2 ** 70 > 1"), ["Boolean(true)"]);
+        assert_eq!(optimized("\"a\" + 1 + \"b\""), ["Str(\"a1b\")"]);
+        assert_eq!(optimized("-(1.5) * 2"), ["Double(-3.0)"]);
+        assert_eq!(optimized("-(2 - 5)"), ["Integer(3)"]);
+        assert_eq!(optimized("\"x=${1 + 1}\""), ["Str(\"x=2\")"]);
+        assert_eq!(optimized("false or x"), ["Identifier(\"x\")"]);
+        // Errors are left for the program to raise.
+        let kept = optimized("1 / 0");
+        assert!(kept[0].starts_with("BinOp"), "{:?}", kept);
+        let kept = optimized("x + 1 * 2");
+        assert!(kept[0].contains("Integer(2)"), "{:?}", kept);
+        // So are large results, even where they would fail or take forever.
+        for src in [
+            "\"ab\" * 9223372036854775807",
+            "\"ab\" * 1000",
+            "3 ** 4000000000",
+            "1 << 100000000000",
+            "2 ** 10000",
+        ] {
+            let kept = optimized(src);
+            assert!(kept[0].starts_with("BinOp"), "{:?}", kept);
+        }
+        assert_eq!(
+            optimized("if false then \"ab\" * 1000 else 1"),
+            ["Integer(1)"]
+        );
+    }
+
+    #[test]
+    fn dead_code() {
+        assert_eq!(optimized("if 1 < 2 then a else b"), ["Identifier(\"a\")"]);
+        assert_eq!(optimized("while 2 - 2 do f() end"), ["None"]);
+        let body = optimized("def f(x: Any) -> Any do\n1 + 1\n(1, [2])\nprint(x)\n3 - 1\nend");
+        assert!(body[0].starts_with("Do([FnCall(\"print\""), "{:?}", body);
+        assert!(body[0].ends_with("}), Integer(2)])"), "{:?}", body);
+        assert_eq!(optimized("1\nx = 2\n3\n2 - 2").len(), 2);
+
+        let mut ast = parse_source("if true then do 1; 2 end else 3", "<test>").unwrap();
+        optimize(&mut ast);
+        assert!(matches!(
+            &ast[0].0,
+            AstNode::Expression(ExprValue::Do(exprs)) if exprs.len() == 1
+        ));
+    }
+}
@@ -1,6 +1,7 @@
+use crate::Result;
 use crate::lexer::tokens::TokenType;
 use crate::parser::{Class, Function, NodePosition, Parser};
-use crate::{Result, unwrap_some};
+use crate::syntax::SyntaxKind;
 
 impl Parser {
     pub fn parse_class(&mut self) -> Result<(Class, NodePosition)> {
@@ -9,7 +10,7 @@ impl Parser {
         // println!("{:#?}", self.tokens.peek());
 
         self.advance();
-        let nx = unwrap_some!(self.tokens.next()); // Eat class
+        let nx = self.next_token(); // Eat class
         let start = NodePosition {
             pos: nx.pos,
             line_no: nx.line_no,
@@ -17,42 +18,49 @@ impl Parser {
         };
         // println!("{:#?}", self.tokens.peek());
 
-        let name = match &unwrap_some!(self.tokens.peek()).type_ {
+        let name = match &self.peek().type_ {
             TokenType::Identifier(i) => i.clone(),
             _ => return Err("Syntax Error: expected Identifier after keyword 'class'".to_string()),
         };
-        self.advance();
-        self.tokens.next(); // eat the identifier
+        self.token_node(SyntaxKind::Name); // eat the identifier
 
         self.advance();
-        match unwrap_some!(self.tokens.next()).type_ {
+        match self.next_token().type_ {
             TokenType::LBrace => {}
             _ => return Err("Expected '{' in class".to_string()),
         }
 
-        while unwrap_some!(self.tokens.peek()).type_ != TokenType::RBrace {
+        while self.peek().type_ != TokenType::RBrace {
             // println!("{:#?}", self.tokens.peek());
-            match unwrap_some!(self.tokens.peek()).type_ {
+            match self.peek().type_ {
                 TokenType::Def => {}
-                _ => return Err(self.parser_error("SyntaxError: expected Function")),
-            }
-            match self.parse_function() {
-                Ok((f, p)) => fns.insert(fns.len(), (f, p)),
-                Err(e) => {
-                    println!("oops");
-                    return Err(e);
+                // Semicolons after methods
+                TokenType::Semicolon => {
+                    self.advance();
+                    self.next_token();
+                    continue;
+                }
+                TokenType::Eof => {
+                    self.advance();
+                    return Err(self.parser_error("Expected '}' to close class"));
                 }
+                _ => return Err(self.parser_error("SyntaxError: expected Function")),
             }
+            self.start_node(SyntaxKind::FnDef);
+            let (mut f, p) = self.parse_function()?;
+            self.finish_node();
+            f.class = Some(name.clone());
+            fns.push((f, p));
         }
         self.advance();
-        self.tokens.next(); // eat '}'
-        match self.tokens.peek() {
-            Some(t) if t.type_ == TokenType::Semicolon => {
-                self.advance();
-                self.tokens.next(); // Eat semicolon, if present
-            }
-            _ => {}
-        }
-        Ok((Class { name, fns }, start))
+        self.next_token(); // eat '}'
+        Ok((
+            Class {
+                name,
+                fns,
+                public: false,
+            },
+            start,
+        ))
     }
 }
@@ -1,16 +1,49 @@
-use crate::lexer::tokens::TokenType;
-use crate::parser::{ExprValue, NodePosition, Parser};
-use crate::{Result, unwrap_some};
+use crate::Result;
+use crate::lexer::Lexer;
+use crate::lexer::tokens::{StrPart, TokenType};
+use crate::parser::{ExprValue, Import, NodePosition, Parser, Pattern};
+use crate::syntax::SyntaxKind;
+
+use rowan::Checkpoint;
 
 impl Parser {
     pub fn parse_expression(&mut self) -> Result<(ExprValue, NodePosition)> {
         //trace!("Parsing expression");
-        let l_value: (ExprValue, NodePosition) = match unwrap_some!(self.tokens.peek()).type_ {
-            TokenType::LParen => {
-                self.tokens.next();
-                self.advance();
-                self.parse_paren_expression()?
-            }
+        let start = self.checkpoint();
+        let l_value = self.parse_primary()?;
+        // The functions below will eat the value, then we can proceed to check for a bin op.
+        self.parse_binop_rhs(0, l_value, start)
+    }
+
+    /// Parses a single value, without any binary operators following it.
+    fn parse_primary(&mut self) -> Result<(ExprValue, NodePosition)> {
+        let start = self.checkpoint();
+        let kind = match self.peek().type_ {
+            TokenType::Plus | TokenType::Minus | TokenType::Not => Some(SyntaxKind::PrefixExpr),
+            TokenType::If => Some(SyntaxKind::IfExpr),
+            TokenType::While => Some(SyntaxKind::WhileExpr),
+            TokenType::Let => Some(SyntaxKind::LetExpr),
+            TokenType::Return => Some(SyntaxKind::ReturnExpr),
+            TokenType::Use => Some(SyntaxKind::UseExpr),
+            TokenType::Do => Some(SyntaxKind::DoExpr),
+            TokenType::Extern => Some(SyntaxKind::ExternExpr),
+            TokenType::LBrack => Some(SyntaxKind::ArrayExpr),
+            TokenType::True
+            | TokenType::False
+            | TokenType::None
+            | TokenType::Integer(_)
+            | TokenType::BigInteger(_)
+            | TokenType::Double(_)
+            | TokenType::Str(_)
+            | TokenType::Interpolated(_) => Some(SyntaxKind::Literal),
+            // Parentheses, names and `{...}` make their own nodes.
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            self.start_node(kind);
+        }
+        let l_value: (ExprValue, NodePosition) = match self.peek().type_ {
+            TokenType::LParen => self.parse_paren_expression()?,
             // Unary
             TokenType::Plus | TokenType::Minus | TokenType::Not => self.parse_unop()?,
 
@@ -37,7 +70,7 @@ impl Parser {
             TokenType::None => self.parse_none()?,
 
             TokenType::Integer(i) => {
-                let nx = unwrap_some!(self.tokens.next());
+                let nx = self.next_token();
                 self.advance();
                 (
                     ExprValue::Integer(i),
@@ -49,8 +82,24 @@ impl Parser {
                 )
             }
 
+            TokenType::BigInteger(_) => {
+                let nx = self.next_token();
+                self.advance();
+                let TokenType::BigInteger(i) = nx.type_ else {
+                    unreachable!()
+                };
+                (
+                    ExprValue::BigInteger(i),
+                    NodePosition {
+                        pos: nx.pos,
+                        line_no: nx.line_no,
+                        file: nx.file,
+                    },
+                )
+            }
+
             TokenType::Double(f) => {
-                let nx = unwrap_some!(self.tokens.next());
+                let nx = self.next_token();
                 self.advance();
                 (
                     ExprValue::Double(f),
@@ -62,67 +111,103 @@ impl Parser {
                 )
             }
 
-            TokenType::Str(_) => self.parse_string()?,
+            TokenType::Str(_) | TokenType::Interpolated(_) => self.parse_string()?,
             TokenType::Async | TokenType::Await => panic!("yet to be implemented"),
             TokenType::LBrack => self.parse_array()?,
+            TokenType::LBrace => self.parse_dict_pattern()?,
+
+            TokenType::Eof => {
+                self.advance();
+                return Err(self.parser_error("Unexpected end of file"));
+            }
 
             _ => {
                 // println!("{:?}", x);
                 return Err(self.parser_error("Invalid expression"));
             }
         };
+        if kind.is_some() {
+            self.finish_node();
+        }
 
-        // The functions above will eat the value, then we can proceed to check for a bin op.
+        // `(a, b) = value` and `[a, b] = value`
+        let l_value = match l_value {
+            (expr @ (ExprValue::Tuple(_) | ExprValue::Array(_)), pos)
+                if self.peek().type_ == TokenType::Assign =>
+            {
+                let pattern = match Pattern::from_expr(expr) {
+                    Some(p) => p,
+                    None => return Err(self.parser_error("Invalid destructuring target")),
+                };
+                self.advance();
+                self.next_token(); // Eat '='
+                let value = Box::new(self.parse_expression()?.0);
+                self.wrap(start, SyntaxKind::AssignExpr);
+                return Ok((
+                    ExprValue::Destructure {
+                        pattern,
+                        value,
+                        declare: false,
+                    },
+                    pos,
+                ));
+            }
+            l_value => l_value,
+        };
+        Ok(l_value)
+    }
+
+    /// Precedence climbing over the binary operators following `l_value`.
+    ///
+    /// Only operators binding at least as tightly as `min_prec` are consumed.
+    /// `start` is where `l_value` starts in the syntax tree.
+    fn parse_binop_rhs(
+        &mut self,
+        min_prec: i32,
+        mut l_value: (ExprValue, NodePosition),
+        start: Option<Checkpoint>,
+    ) -> Result<(ExprValue, NodePosition)> {
         loop {
-            let op: TokenType = match unwrap_some!(self.tokens.peek()).type_ {
-                TokenType::Plus
-                | TokenType::Minus
-                | TokenType::Div
-                | TokenType::Mul
-                | TokenType::Less
-                | TokenType::Dot
-                | TokenType::LessEq
-                | TokenType::Greater
-                | TokenType::GreaterEq
-                | TokenType::Equal
-                | TokenType::NotEq => {
-                    self.advance();
-                    unwrap_some!(self.tokens.next()).type_
-                }
-                _ => return Ok(l_value),
+            let op = self.peek().type_.clone();
+            let prec = self.get_tok_precedence(&op);
+            if prec < min_prec {
+                return Ok(l_value);
+            }
+            let at = NodePosition {
+                pos: self.peek().pos,
+                line_no: self.peek().line_no,
+                file: self.file.clone(),
             };
-            let r_value = self.parse_expression()?;
-
-            match unwrap_some!(self.tokens.peek()).type_ {
-                TokenType::Plus
-                | TokenType::Minus
-                | TokenType::Div
-                | TokenType::Mul
-                | TokenType::Less
-                | TokenType::LessEq
-                | TokenType::Greater
-                | TokenType::GreaterEq
-                | TokenType::Equal
-                | TokenType::NotEq => continue, // Leave it at this stage, let the loop start with binop search again.
-                _ => {
-                    // println!("{:#?} {:?} {:#?}",l_value,op, r_value );
-                    return Ok((
-                        // todo: match to avoid unwrap
-                        ExprValue::BinOp(Box::new(l_value.0), Box::new(op), Box::new(r_value.0)),
-                        NodePosition {
-                            pos: self.pos,
-                            line_no: self.line_no,
-                            file: self.file.clone(),
-                        },
-                    ));
+            self.advance();
+            self.next_token(); // Eat the operator
+
+            let r_start = self.checkpoint();
+            let mut r_value = self.parse_primary()?;
+            loop {
+                let next = self.peek().type_.clone();
+                let next_prec = self.get_tok_precedence(&next);
+                if next_prec > prec || (next_prec == prec && next == TokenType::Pow) {
+                    r_value = self.parse_binop_rhs(next_prec, r_value, r_start)?;
+                } else {
+                    break;
                 }
-            };
+            }
+            self.wrap(start, SyntaxKind::BinExpr);
+
+            l_value = (
+                ExprValue::BinOp(Box::new(l_value.0), Box::new(op), Box::new(r_value.0), at),
+                NodePosition {
+                    pos: self.pos,
+                    line_no: self.line_no,
+                    file: self.file.clone(),
+                },
+            );
         }
     }
 
     pub fn parse_array(&mut self) -> Result<(ExprValue, NodePosition)> {
         self.advance();
-        let pos = unwrap_some!(self.tokens.next());
+        let pos = self.next_token();
         let mut pos = NodePosition {
             pos: pos.pos,
             line_no: pos.line_no,
@@ -130,19 +215,19 @@ impl Parser {
         };
         let mut expressions = vec![];
 
-        if unwrap_some!(self.tokens.peek()).type_ == TokenType::RBrack {
+        if self.peek().type_ == TokenType::RBrack {
             self.advance();
-            self.tokens.next(); // Eat ']'
+            self.next_token(); // Eat ']'
         } else {
             loop {
-                if unwrap_some!(self.tokens.peek()).type_ == TokenType::Comma {
+                if self.peek().type_ == TokenType::Comma {
                     self.advance();
-                    self.tokens.next(); // Eat ','
+                    self.next_token(); // Eat ','
                     continue;
                 }
-                if unwrap_some!(self.tokens.peek()).type_ == TokenType::RBrack {
+                if self.peek().type_ == TokenType::RBrack {
                     self.advance();
-                    self.tokens.next(); // Eat ']'
+                    self.next_token(); // Eat ']'
                     break;
                 }
                 let expr = self.parse_expression();
@@ -163,7 +248,7 @@ impl Parser {
     pub fn parse_unop(&mut self) -> Result<(ExprValue, NodePosition)> {
         //trace!("Parsing unop");
         // Eat the operator while working.
-        let nx = unwrap_some!(self.tokens.next());
+        let nx = self.next_token();
         let start = NodePosition {
             pos: nx.pos,
             line_no: nx.line_no,
@@ -172,17 +257,54 @@ impl Parser {
         self.advance();
         let t = nx.type_;
         let op = Box::new(t);
-        let expr = Box::new(self.parse_expression()?.0);
+        // Only `**` and `.` bind tighter than a unary operator.
+        let operand_start = self.checkpoint();
+        let operand = self.parse_primary()?;
+        let prec = self.get_tok_precedence(&TokenType::Pow);
+        let expr = Box::new(self.parse_binop_rhs(prec, operand, operand_start)?.0);
         Ok((ExprValue::UnOp(op, expr), start))
     }
 
+    /// Parses `(expr)` and tuples.
     pub fn parse_paren_expression(&mut self) -> Result<(ExprValue, NodePosition)> {
         //trace!("Parsing paren expr");
+        let start = self.checkpoint();
+        self.next_token(); // Eat '('
+        self.advance();
+        if self.peek().type_ == TokenType::RParen {
+            self.advance();
+            let nx = self.next_token(); // Eat ')'
+            self.wrap(start, SyntaxKind::TupleExpr);
+            return Ok((
+                ExprValue::Tuple(vec![]),
+                NodePosition {
+                    pos: nx.pos,
+                    line_no: nx.line_no,
+                    file: nx.file,
+                },
+            ));
+        }
         let expr = self.parse_expression();
-        let expr = expr?.0;
-        if unwrap_some!(self.tokens.peek()).type_ == TokenType::RParen {
+        let mut expr = expr?.0;
+        let mut kind = SyntaxKind::ParenExpr;
+        // `(a, b)` and `(a,)` are tuples
+        if self.peek().type_ == TokenType::Comma {
+            kind = SyntaxKind::TupleExpr;
+            let mut exprs = vec![expr];
+            while self.peek().type_ == TokenType::Comma {
+                self.advance();
+                self.next_token(); // Eat ','
+                if self.peek().type_ == TokenType::RParen {
+                    break;
+                }
+                exprs.push(self.parse_expression()?.0);
+            }
+            expr = ExprValue::Tuple(exprs);
+        }
+        if self.peek().type_ == TokenType::RParen {
             self.advance();
-            let nx = unwrap_some!(self.tokens.next()); // Eat ')'
+            let nx = self.next_token(); // Eat ')'
+            self.wrap(start, kind);
             Ok((
                 expr,
                 NodePosition {
@@ -196,13 +318,60 @@ impl Parser {
         }
     }
 
+    /// Parses `{name, age} = value`.
+    pub fn parse_dict_pattern(&mut self) -> Result<(ExprValue, NodePosition)> {
+        let start = self.checkpoint();
+        self.start_node(SyntaxKind::DictPattern);
+        self.advance();
+        let nx = self.next_token(); // Eat '{'
+        let mut names = vec![];
+        loop {
+            self.advance();
+            match self.peek().type_.clone() {
+                TokenType::Comma => {
+                    self.next_token();
+                }
+                TokenType::RBrace => {
+                    self.next_token();
+                    break;
+                }
+                TokenType::Identifier(n) => {
+                    self.token_node(SyntaxKind::Name);
+                    names.push(n);
+                }
+                _ => {
+                    self.next_token();
+                    return Err(self.parser_error("Expected Identifier or '}'"));
+                }
+            }
+        }
+        self.finish_node();
+        if self.peek().type_ != TokenType::Assign {
+            return Err(self.parser_error("Expected '=' after '{...}'"));
+        }
+        self.advance();
+        self.next_token(); // Eat '='
+        let value = Box::new(self.parse_expression()?.0);
+        self.wrap(start, SyntaxKind::AssignExpr);
+        Ok((
+            ExprValue::Destructure {
+                pattern: Pattern::Dict(names),
+                value,
+                declare: false,
+            },
+            NodePosition {
+                pos: nx.pos,
+                line_no: nx.line_no,
+                file: nx.file,
+            },
+        ))
+    }
+
     pub fn parse_do(&mut self) -> Result<(ExprValue, NodePosition)> {
         let mut exprs = vec![];
 
-        // println!("some {:?}", self.tokens.peek());
-
         self.advance();
-        self.tokens.next(); // eat 'do'
+        self.next_token(); // eat 'do'
 
         let pos = NodePosition {
             pos: self.pos,
@@ -211,64 +380,51 @@ impl Parser {
         };
 
         loop {
-            match self.parse_expression() {
-                Ok((expr, _)) => exprs.push(expr),
-                Err(e) if e == self.parser_error("Invalid expression") => {
-                    if unwrap_some!(self.tokens.peek()).type_ == TokenType::End
-                        || unwrap_some!(self.tokens.peek()).type_ == TokenType::Semicolon
-                    {
-                        break;
-                    } else {
-                        return Err(e);
-                    }
-                }
-                Err(e) => return Err(e),
-            }
-            // Eat the semicolons
-            match unwrap_some!(self.tokens.peek()).type_ {
+            match self.peek().type_ {
+                // Eat the semicolons
                 TokenType::Semicolon => {
                     self.advance();
-                    self.tokens.next();
-                    continue;
+                    self.next_token();
                 }
-                TokenType::End => break,
-                _ => {
-                    // return Err(self.parser_error("Expected ';' or 'end'"))
+                TokenType::End => {
+                    self.advance();
+                    self.next_token(); // Eat 'end'
+                    break;
                 }
+                TokenType::Eof => {
+                    self.advance();
+                    return Err(self.parser_error("Expected 'end' to close 'do'"));
+                }
+                _ => exprs.push(self.parse_expression()?.0),
             }
         }
 
-        if unwrap_some!(self.tokens.peek()).type_ == TokenType::End {
-            self.advance();
-            self.tokens.next(); // Eat 'end'
-        } // No other case
-
         Ok((ExprValue::Do(exprs), pos))
     }
 
     pub fn parse_if_else(&mut self) -> Result<(ExprValue, NodePosition)> {
         // //trace!("Parsing if else");
         self.advance();
-        let nx = unwrap_some!(self.tokens.next()); // Eat 'if'
+        let nx = self.next_token(); // Eat 'if'
         // let type_ = String::from("unavailable");
         // let hastype = !true;
 
         let cond = Box::new(self.parse_expression()?.0);
 
-        if unwrap_some!(self.tokens.peek()).type_ == TokenType::Then {
+        if self.peek().type_ == TokenType::Then {
             self.advance();
-            self.tokens.next(); // eat 'then'
+            self.next_token(); // eat 'then'
         } else {
-            // println!("{:?}", self.tokens.peek());
-            panic!("then expected after condition");
+            self.advance();
+            return Err(self.parser_error("Expected 'then' after condition"));
         }
 
         // println!("{:?} {:?}", self.pos, self.line_no);
         let (expression_if, _pos) = self.parse_expression()?;
 
-        if unwrap_some!(self.tokens.peek()).type_ == TokenType::Else {
+        if self.peek().type_ == TokenType::Else {
             self.advance();
-            self.tokens.next(); // Eat 'else'
+            self.next_token(); // Eat 'else'
 
             let (expression_else, _pos) = self.parse_expression()?;
 
@@ -302,7 +458,7 @@ impl Parser {
 
     pub fn parse_while(&mut self) -> Result<(ExprValue, NodePosition)> {
         self.advance();
-        let nx = unwrap_some!(self.tokens.next()); // Eat 'while'
+        let nx = self.next_token(); // Eat 'while'
         let condition = self.parse_expression()?.0; // todo: remove unwrap
         let expression = self.parse_expression()?.0;
 
@@ -318,20 +474,43 @@ impl Parser {
 
     pub fn parse_declaration(&mut self) -> Result<(ExprValue, NodePosition)> {
         self.advance();
-        let nx = unwrap_some!(self.tokens.next()); // Eat `let`
-        let name: String = match unwrap_some!(self.tokens.next()).type_ {
-            TokenType::Identifier(n) => n,
+        let nx = self.next_token(); // Eat `let`
+        if let TokenType::LParen | TokenType::LBrack | TokenType::LBrace = self.peek().type_ {
+            return match self.parse_expression()? {
+                (ExprValue::Destructure { pattern, value, .. }, _) => Ok((
+                    ExprValue::Destructure {
+                        pattern,
+                        value,
+                        declare: true,
+                    },
+                    NodePosition {
+                        pos: nx.pos,
+                        line_no: nx.line_no,
+                        file: nx.file,
+                    },
+                )),
+                _ => Err(self.parser_error("Expected '=' after pattern")),
+            };
+        }
+        let name: String = match self.peek().type_.clone() {
+            TokenType::Identifier(n) => {
+                self.token_node(SyntaxKind::Name);
+                n
+            }
             _ => return Err(self.parser_error("Expected an identifier after let")),
         };
-        if unwrap_some!(self.tokens.peek()).type_ == TokenType::Colon {
+        if self.peek().type_ == TokenType::Colon {
             self.advance();
-            self.tokens.next(); // Eat ':'
+            self.next_token(); // Eat ':'
         } else {
             return Err(self.parser_error("Missing ':'."));
         }
 
-        let type_ = match unwrap_some!(self.tokens.next()).type_ {
-            TokenType::Identifier(t) => t,
+        let type_ = match self.peek().type_.clone() {
+            TokenType::Identifier(t) => {
+                self.token_node(SyntaxKind::TypeRef);
+                t
+            }
             _ => return Err(self.parser_error("Expected an identifier")),
         };
         Ok((
@@ -346,7 +525,7 @@ impl Parser {
 
     pub fn parse_true(&mut self) -> Result<(ExprValue, NodePosition)> {
         self.advance();
-        let nx = unwrap_some!(self.tokens.next()); // Eat `true`
+        let nx = self.next_token(); // Eat `true`
         Ok((
             ExprValue::Boolean(true),
             NodePosition {
@@ -359,7 +538,7 @@ impl Parser {
 
     pub fn parse_false(&mut self) -> Result<(ExprValue, NodePosition)> {
         self.advance();
-        let nx = unwrap_some!(self.tokens.next()); // Eat `false`
+        let nx = self.next_token(); // Eat `false`
         Ok((
             ExprValue::Boolean(false),
             NodePosition {
@@ -372,7 +551,7 @@ impl Parser {
 
     pub fn parse_none(&mut self) -> Result<(ExprValue, NodePosition)> {
         self.advance();
-        let nx = unwrap_some!(self.tokens.next()); // Eat `true`
+        let nx = self.next_token(); // Eat `true`
         Ok((
             ExprValue::None,
             NodePosition {
@@ -384,9 +563,9 @@ impl Parser {
     }
 
     pub fn parse_identifier(&mut self) -> Result<(ExprValue, NodePosition)> {
-        self.advance();
+        let node = self.checkpoint();
         // Eat the identifier and work.
-        let nx = unwrap_some!(self.tokens.next());
+        let nx = self.token_node(SyntaxKind::NameRef);
         let start = NodePosition {
             pos: nx.pos,
             line_no: nx.line_no,
@@ -397,63 +576,90 @@ impl Parser {
             _ => unreachable!(),
         };
         // Check for assignment
-        match unwrap_some!(self.tokens.peek()).type_ {
+        match self.peek().type_ {
             TokenType::Assign => {
                 self.advance();
-                self.tokens.next(); // Eat '='
+                self.next_token(); // Eat '='
                 let value = Box::new(self.parse_expression()?.0); // todo: remove unwrap
+                self.wrap(node, SyntaxKind::AssignExpr);
                 return Ok((ExprValue::Assign { name, value }, start));
             }
+            TokenType::Walrus => {
+                self.advance();
+                self.next_token(); // Eat '=:'
+                let value = Box::new(self.parse_expression()?.0);
+                self.wrap(node, SyntaxKind::WalrusExpr);
+                return Ok((ExprValue::Walrus { name, value }, start));
+            }
             TokenType::PlusEq => {
                 self.advance();
-                let op = Box::new(unwrap_some!(self.tokens.next()).type_); // Eat '+='
+                let op = Box::new(self.next_token().type_); // Eat '+='
                 let value = Box::new(self.parse_expression()?.0); // todo: remove unwrap
+                self.wrap(node, SyntaxKind::AssignExpr);
                 return Ok((ExprValue::AugAssign { name, op, value }, start));
             }
             TokenType::MinusEq => {
                 self.advance();
-                let op = Box::new(unwrap_some!(self.tokens.next()).type_); // Eat '-='
+                let op = Box::new(self.next_token().type_); // Eat '-='
                 let value = Box::new(self.parse_expression()?.0); // todo: remove unwrap
+                self.wrap(node, SyntaxKind::AssignExpr);
                 return Ok((ExprValue::AugAssign { name, op, value }, start));
             }
             TokenType::DivEq => {
                 self.advance();
-                let op = Box::new(unwrap_some!(self.tokens.next()).type_); // Eat '/='
+                let op = Box::new(self.next_token().type_); // Eat '/='
                 let value = Box::new(self.parse_expression()?.0); // todo: remove unwrap
+                self.wrap(node, SyntaxKind::AssignExpr);
                 return Ok((ExprValue::AugAssign { name, op, value }, start));
             }
             TokenType::MulEq => {
                 self.advance();
-                let op = Box::new(unwrap_some!(self.tokens.next()).type_); // Eat '*='
+                let op = Box::new(self.next_token().type_); // Eat '*='
                 let value = Box::new(self.parse_expression()?.0); // todo: remove unwrap
+                self.wrap(node, SyntaxKind::AssignExpr);
                 return Ok((ExprValue::AugAssign { name, op, value }, start));
             }
             _ => {}
         }
         // Check for function call
-        if unwrap_some!(self.tokens.peek()).type_ == TokenType::LParen {
+        if self.peek().type_ == TokenType::LParen {
+            self.start_node(SyntaxKind::ArgList);
             self.advance();
-            self.tokens.next(); // Eat '('
+            self.next_token(); // Eat '('
             let mut values = Vec::new();
+            let mut keywords = Vec::new();
             loop {
-                match self.parse_expression() {
-                    Ok((expr, _)) => values.insert(values.len(), expr),
-                    Err(e) => {
-                        if unwrap_some!(self.tokens.peek()).type_ == TokenType::Comma {
-                            break;
-                        } else if unwrap_some!(self.tokens.peek()).type_ == TokenType::RParen {
-                            self.advance();
-                            self.tokens.next(); // Eat ')'
-                            return Ok((ExprValue::FnCall(name, values), start));
-                        } else {
-                            return Err(e);
-                        }
-                    }
+                if self.peek().type_ == TokenType::Comma {
+                    self.advance();
+                    self.next_token(); // Eat ','
+                    continue;
+                }
+                if self.peek().type_ == TokenType::RParen {
+                    self.advance();
+                    self.next_token(); // Eat ')'
+                    self.finish_node();
+                    self.wrap(node, SyntaxKind::CallExpr);
+                    let call = ExprValue::FnCall(name, values, keywords, start.clone());
+                    return Ok((call, start));
                 }
-                if unwrap_some!(self.tokens.peek()).type_ == TokenType::Comma {
+                // `name=value` inside a call is a keyword argument.
+                if let (Some(TokenType::Identifier(name)), Some(TokenType::Assign)) =
+                    (self.nth(0), self.nth(1))
+                {
+                    let name = name.clone();
+                    self.start_node(SyntaxKind::KeywordArg);
+                    self.token_node(SyntaxKind::Name);
                     self.advance();
-                    self.tokens.next(); // Eat ','
+                    self.next_token(); // Eat '='
+                    keywords.push((name, self.parse_expression()?.0));
+                    self.finish_node();
+                    continue;
+                }
+                let expr = self.parse_expression()?.0;
+                if !keywords.is_empty() {
+                    return Err(self.parser_error("Positional argument follows keyword argument"));
                 }
+                values.push(expr);
             }
         }
         Ok((ExprValue::Identifier(name), start))
@@ -461,7 +667,7 @@ impl Parser {
 
     pub fn parse_return(&mut self) -> Result<(ExprValue, NodePosition)> {
         self.advance();
-        let nx = unwrap_some!(self.tokens.next()); // Eat `return`
+        let nx = self.next_token(); // Eat `return`
         let expr = self.parse_expression()?.0; // todo: remove unwrap
         Ok((
             ExprValue::Return(Box::new(expr)),
@@ -475,7 +681,7 @@ impl Parser {
 
     pub fn parse_string(&mut self) -> Result<(ExprValue, NodePosition)> {
         self.advance();
-        let nx = unwrap_some!(self.tokens.next());
+        let nx = self.next_token();
         match nx.type_ {
             TokenType::Str(s) => Ok((
                 ExprValue::Str(s),
@@ -485,32 +691,139 @@ impl Parser {
                     file: nx.file,
                 },
             )),
+            TokenType::Interpolated(parts) => {
+                let mut exprs = vec![];
+                for part in parts {
+                    match part {
+                        StrPart::Lit(s) => exprs.push(ExprValue::Str(s)),
+                        StrPart::Expr {
+                            code,
+                            line_no,
+                            pos,
+                            lines,
+                        } => {
+                            let lexer =
+                                Lexer::from_text_at(&code, &nx.file, (line_no, pos), lines.clone());
+                            let tokens = lexer.collect::<Result<Vec<_>>>()?;
+                            let mut parser = Parser::new(tokens.into_iter().peekable(), &nx.file)
+                                .with_lines(lines, line_no);
+                            let expr = parser.parse_expression().and_then(|(e, _)| {
+                                if parser.peek().type_ != TokenType::Eof {
+                                    return Err(
+                                        parser.parser_error("Expected '}' after expression")
+                                    );
+                                }
+                                Ok(e)
+                            });
+                            match expr {
+                                Ok(e) => exprs.push(e),
+                                Err(e) => {
+                                    self.last_error = parser.last_error;
+                                    return Err(e);
+                                }
+                            }
+                        }
+                    }
+                }
+                Ok((
+                    ExprValue::Interpolation(exprs),
+                    NodePosition {
+                        pos: nx.pos,
+                        line_no: nx.line_no,
+                        file: nx.file,
+                    },
+                ))
+            }
             _ => unreachable!(),
         }
     }
 
     pub fn parse_use(&mut self) -> Result<(ExprValue, NodePosition)> {
         self.advance();
-        let nx = unwrap_some!(self.tokens.next()); // Eat `use`
+        let nx = self.next_token(); // Eat `use`
+        let position = NodePosition {
+            pos: nx.pos,
+            line_no: nx.line_no,
+            file: nx.file,
+        };
+
+        // use { name, name as alias } from "path"
+        if self.peek().type_ == TokenType::LBrace {
+            self.advance();
+            self.next_token(); // Eat '{'
+            let mut names = vec![];
+            loop {
+                self.advance();
+                match self.peek().type_.clone() {
+                    TokenType::RBrace => {
+                        self.next_token();
+                        break;
+                    }
+                    TokenType::Comma if !names.is_empty() => {
+                        self.next_token();
+                    }
+                    TokenType::Identifier(name) => {
+                        self.start_node(SyntaxKind::ImportItem);
+                        self.token_node(SyntaxKind::NameRef);
+                        let alias = self.parse_alias()?.unwrap_or_else(|| name.clone());
+                        self.finish_node();
+                        names.push((name, alias));
+                    }
+                    _ => {
+                        self.next_token();
+                        return Err(self.parser_error("Expected a name to import or '}'"));
+                    }
+                }
+            }
+            self.advance();
+            if self.next_token().type_ != TokenType::From {
+                return Err(self.parser_error("Expected 'from' after the imported names"));
+            }
+            self.advance();
+            return match self.next_token().type_ {
+                TokenType::Str(s) => Ok((ExprValue::Use(s, Import::Names(names)), position)),
+                _ => Err(self.parser_error("Expected the path of a module after 'from'")),
+            };
+        }
+
         self.advance();
-        match unwrap_some!(self.tokens.next()).type_ {
-            TokenType::Str(s) => Ok((
-                ExprValue::Use(s.to_string()),
-                NodePosition {
-                    pos: nx.pos,
-                    line_no: nx.line_no,
-                    file: nx.file,
-                },
-            )),
+        match self.next_token().type_ {
+            TokenType::Str(s) => {
+                let import = match self.parse_alias()? {
+                    Some(alias) => Import::Module(alias),
+                    None => Import::All,
+                };
+                Ok((ExprValue::Use(s, import), position))
+            }
             _ => Err(self.parser_error("Invalid 'use' expression")),
         }
     }
 
+    /// `as name`, if it comes next.
+    fn parse_alias(&mut self) -> Result<Option<String>> {
+        if self.peek().type_ != TokenType::As {
+            return Ok(None);
+        }
+        self.advance();
+        self.next_token(); // Eat 'as'
+        self.advance();
+        match self.peek().type_.clone() {
+            TokenType::Identifier(alias) => {
+                self.token_node(SyntaxKind::Name);
+                Ok(Some(alias))
+            }
+            _ => {
+                self.next_token();
+                Err(self.parser_error("Expected a name after 'as'"))
+            }
+        }
+    }
+
     pub fn parse_extern(&mut self) -> Result<(ExprValue, NodePosition)> {
         self.advance();
-        let nx = unwrap_some!(self.tokens.next()); // Eat `extern`
+        let nx = self.next_token(); // Eat `extern`
         self.advance();
-        match unwrap_some!(self.tokens.next()).type_ {
+        match self.next_token().type_ {
             TokenType::Str(s) => Ok((
                 ExprValue::Extern(s.to_string()),
                 NodePosition {
@@ -523,3 +836,130 @@ impl Parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{AstNode, ExprValue, Import, Pattern, parse_text as parse};
+
+    fn expr(src: &str) -> ExprValue {
+        match parse(src).unwrap().remove(0) {
+            AstNode::Expression(e) => e,
+            n => panic!("expected an expression, got {:?}", n),
+        }
+    }
+
+    #[test]
+    fn tuples() {
+        assert!(matches!(expr("(1, 2);"), ExprValue::Tuple(v) if v.len() == 2));
+        assert!(matches!(expr("(1,);"), ExprValue::Tuple(v) if v.len() == 1));
+        assert!(matches!(expr("();"), ExprValue::Tuple(v) if v.is_empty()));
+        assert!(matches!(expr("(1);"), ExprValue::Integer(1)));
+    }
+
+    #[test]
+    fn destructuring() {
+        let ExprValue::Destructure {
+            pattern, declare, ..
+        } = expr("let (x, [y, z]) = f();")
+        else {
+            panic!("expected destructuring");
+        };
+        assert!(declare);
+        assert_eq!(pattern.names(), vec!["x", "y", "z"]);
+
+        let ExprValue::Destructure { pattern, .. } = expr("[a, b] = arr;") else {
+            panic!("expected destructuring");
+        };
+        assert!(matches!(pattern, Pattern::Array(_)));
+
+        let ExprValue::Destructure { pattern, .. } = expr("{name, age} = dict;") else {
+            panic!("expected destructuring");
+        };
+        assert_eq!(pattern, Pattern::Dict(vec!["name".into(), "age".into()]));
+
+        assert!(parse("(1, b) = f();").is_err());
+    }
+
+    /// Render binary operators with explicit parentheses.
+    fn grouped(e: &ExprValue) -> String {
+        match e {
+            ExprValue::BinOp(l, op, r, _) => format!("({} {:?} {})", grouped(l), op, grouped(r)),
+            ExprValue::UnOp(op, e) => format!("({:?} {})", op, grouped(e)),
+            ExprValue::Identifier(n) => n.clone(),
+            ExprValue::Integer(i) => i.to_string(),
+            e => format!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(grouped(&expr("a - b - c;")), "((a Minus b) Minus c)");
+        assert_eq!(grouped(&expr("2 * 3 + 1;")), "((2 Mul 3) Plus 1)");
+        assert_eq!(
+            grouped(&expr("curr <= endval and ok or done;")),
+            "(((curr LessEq endval) And ok) Or done)"
+        );
+        assert_eq!(grouped(&expr("a ** b ** c;")), "(a Pow (b Pow c))");
+        assert_eq!(grouped(&expr("-a ** 2 % 3;")), "((Minus (a Pow 2)) Mod 3)");
+        assert_eq!(
+            grouped(&expr("1 | 2 ^ 3 & 4 << 5;")),
+            "(1 BitOr (2 BitXor (3 BitAnd (4 Shl 5))))"
+        );
+        assert_eq!(grouped(&expr("a.b == c;")), "((a Dot b) Equal c)");
+    }
+
+    #[test]
+    fn interpolation() {
+        let ExprValue::Interpolation(parts) = expr(r#""age: ${age}, next: ${age + 1}";"#) else {
+            panic!("expected interpolation");
+        };
+        assert!(matches!(
+            &parts[..],
+            [
+                ExprValue::Str(_),
+                ExprValue::Identifier(_),
+                ExprValue::Str(_),
+                ExprValue::BinOp(..)
+            ]
+        ));
+    }
+
+    #[test]
+    fn walrus() {
+        assert!(matches!(expr("x =: 1 + 2;"), ExprValue::Walrus { name, .. } if name == "x"));
+    }
+
+    #[test]
+    fn imports() {
+        let use_ = |src| match expr(src) {
+            ExprValue::Use(path, import) => (path, import),
+            e => panic!("expected a use, got {:?}", e),
+        };
+        assert_eq!(
+            use_("use \"std:json\""),
+            ("std:json".to_string(), Import::All)
+        );
+        assert_eq!(
+            use_("use \"std:json\" as json"),
+            ("std:json".to_string(), Import::Module("json".to_string()))
+        );
+        let names = |pairs: &[(&str, &str)]| {
+            let pairs = pairs.iter().map(|(a, b)| (a.to_string(), b.to_string()));
+            Import::Names(pairs.collect())
+        };
+        assert_eq!(
+            use_("use { JSON, dumps as d } from \"std:json\"").1,
+            names(&[("JSON", "JSON"), ("dumps", "d")])
+        );
+        assert_eq!(use_("use {} from \"x\"").1, names(&[]));
+
+        for src in [
+            "use { a } \"x\"",
+            "use { 1 } from \"x\"",
+            "use \"x\" as",
+            "use {a from \"x\"",
+        ] {
+            assert!(parse(src).is_err(), "{} should not parse", src);
+        }
+    }
+}
@@ -1,53 +1,45 @@
+use crate::Result;
 use crate::lexer::tokens::{Token, TokenType};
 use crate::parser::{Args, Function, NodePosition, Parser};
-use crate::{Result, unwrap_some};
+use crate::syntax::SyntaxKind;
 
 impl Parser {
     fn parse_type_annot(&mut self) -> Result<(String, String)> {
         // Check if Identifier exists, else return Err
-        match unwrap_some!(self.tokens.peek()) {
+        match self.peek() {
             Token {
                 type_: TokenType::Identifier(_),
-                pos: _,
-                line_no: _,
-                file: _,
+                ..
             } => {}
             _ => {
-                println!("{:?}", self.tokens.peek());
                 return Err(self.parser_error("Expected Identifier or ')'"));
             }
         }
         // Store identifier.
-        self.advance();
-        let name = match unwrap_some!(self.tokens.next()).type_ {
+        let name = match self.token_node(SyntaxKind::Name).type_ {
             TokenType::Identifier(s) => s,
             _ => unreachable!(),
         };
         // Check if colon exists.
-        match unwrap_some!(self.tokens.peek()) {
+        match self.peek() {
             Token {
                 type_: TokenType::Colon,
-                pos: _,
-                line_no: _,
-                file: _,
+                ..
             } => {}
             _ => return Err("expected ':' .".to_string()),
         }
         self.advance();
-        self.tokens.next(); // Eat ':'
+        self.next_token(); // Eat ':'
         // Check if type exists
-        match unwrap_some!(self.tokens.peek()) {
+        match self.peek() {
             Token {
                 type_: TokenType::Identifier(_),
-                pos: _,
-                line_no: _,
-                file: _,
+                ..
             } => {}
             _ => return Err("expected Identifier.".to_string()),
         }
-        self.advance();
         // Store type
-        let type_ = match unwrap_some!(self.tokens.next()).type_ {
+        let type_ = match self.token_node(SyntaxKind::TypeRef).type_ {
             TokenType::Identifier(s) => s,
             _ => unreachable!(),
         };
@@ -55,109 +47,172 @@ impl Parser {
     }
 
     pub fn parse_function(&mut self) -> Result<(Function, NodePosition)> {
+        let (name, args, return_type, start) = self.parse_signature()?;
+        let expression = self.parse_expression()?;
+        self.current_scope = "global".to_string();
+        Ok((
+            Function {
+                name,
+                args,
+                expression: Box::new(expression),
+                return_type,
+                public: false,
+                class: None,
+            },
+            start,
+        ))
+    }
+
+    /// `def name(params) -> return_type`, without a body: name, parameters,
+    /// return type and where the `def` is.
+    pub fn parse_signature(&mut self) -> Result<(String, Args, String, NodePosition)> {
         let name: String;
         let return_type: String;
-        let mut args = Args {
-            name: vec![],
-            type_: vec![],
-        };
-        match self.tokens.peek() {
-            Some(Token {
+        let mut args = Args::new();
+        match self.peek() {
+            Token {
                 type_: TokenType::Def,
                 pos,
                 line_no,
                 file,
-            }) => {
+                ..
+            } => {
                 let start = NodePosition {
                     pos: *pos,
                     line_no: *line_no,
                     file: file.to_string(),
                 };
                 self.advance();
-                self.tokens.next(); // Eat Def
+                self.next_token(); // Eat Def
 
-                match unwrap_some!(self.tokens.peek()) {
+                match self.peek() {
                     Token {
                         type_: TokenType::Identifier(_),
-                        pos: _,
-                        line_no: _,
-                        file: _,
+                        ..
                     } => {}
                     _ => return Err(self.parser_error("Expected Identifier after keyword 'def'")),
                 }
-                self.advance();
                 // Eat and store
-                match unwrap_some!(self.tokens.next()).type_ {
+                match self.token_node(SyntaxKind::Name).type_ {
                     TokenType::Identifier(n) => name = n, // Always matches
                     _ => unreachable!(),                  // never happens
                 }
                 self.current_scope = format!("{}.{}", self.current_scope, name.clone());
 
-                if unwrap_some!(self.tokens.peek()).type_ != TokenType::LParen {
+                if self.peek().type_ != TokenType::LParen {
                     return Err(self.parser_error("Expected '(' after Identifier"));
                 }
 
-                self.tokens.next(); // Eat '('
+                self.start_node(SyntaxKind::ParamList);
+                self.next_token(); // Eat '('
 
-                if unwrap_some!(self.tokens.peek()).type_ == TokenType::RParen {
-                    self.tokens.next(); // Eat ')'
+                if self.peek().type_ == TokenType::RParen {
+                    self.next_token(); // Eat ')'
                 } else {
                     loop {
-                        if unwrap_some!(self.tokens.peek()).type_ == TokenType::Comma {
-                            self.tokens.next(); // Eat ','
+                        if self.peek().type_ == TokenType::Comma {
+                            self.next_token(); // Eat ','
                             continue;
                         }
-                        if unwrap_some!(self.tokens.peek()).type_ == TokenType::RParen {
-                            self.tokens.next(); // Eat ')'
+                        if self.peek().type_ == TokenType::RParen {
+                            self.next_token(); // Eat ')'
                             break;
                         }
-                        let type_annot = self.parse_type_annot();
-                        match type_annot {
-                            Ok((n, t)) => {
-                                args.name.push(n);
-                                args.type_.push(t);
-                            }
-                            Err(e) => {
-                                return Err(e);
+                        if args.kwarg.is_some() {
+                            return Err(self.parser_error("No parameters allowed after '**'"));
+                        }
+                        // `*rest` or `**opts`
+                        if let TokenType::Mul | TokenType::Pow = self.peek().type_ {
+                            self.start_node(SyntaxKind::Param);
+                            self.advance();
+                            // Eat '*' or '**'
+                            let is_kwarg = self.next_token().type_ == TokenType::Pow;
+                            let annot = self.parse_type_annot()?;
+                            self.finish_node();
+                            if is_kwarg {
+                                args.kwarg = Some(annot);
+                            } else if args.vararg.is_some() {
+                                return Err(self.parser_error("Only one '*' parameter is allowed"));
+                            } else {
+                                args.vararg = Some(annot);
                             }
+                            continue;
+                        }
+                        if args.vararg.is_some() {
+                            return Err(
+                                self.parser_error("Expected '**' parameter after '*' parameter")
+                            );
+                        }
+                        self.start_node(SyntaxKind::Param);
+                        let (n, t) = self.parse_type_annot()?;
+                        let default = if self.peek().type_ == TokenType::Assign {
+                            self.advance();
+                            self.next_token(); // Eat '='
+                            Some(self.parse_expression()?.0)
+                        } else if args.default.iter().any(|d| d.is_some()) {
+                            return Err(self.parser_error(
+                                "Parameter without a default follows parameter with a default",
+                            ));
+                        } else {
+                            None
                         };
+                        self.finish_node();
+                        args.name.push(n);
+                        args.type_.push(t);
+                        args.default.push(default);
                     }
                 }
+                self.finish_node();
 
-                if unwrap_some!(self.tokens.peek()).type_ != TokenType::Arrow {
+                if self.peek().type_ != TokenType::Arrow {
                     return Err(self.parser_error("expected '->'"));
                 }
                 self.advance();
-                self.tokens.next(); // Eat '->'
+                self.next_token(); // Eat '->'
 
-                match &unwrap_some!(self.tokens.peek()).type_ {
+                match &self.peek().type_ {
                     TokenType::Identifier(n) => return_type = n.to_string(),
                     _ => return Err(self.parser_error("expected return type")),
                 }
-                self.advance();
-                self.tokens.next(); // Eat the return_type
-
-                let expression = self.parse_expression()?;
-
-                match self.tokens.peek() {
-                    Some(t) if t.type_ == TokenType::Semicolon => {
-                        self.advance();
-                        self.tokens.next(); // Eat semicolon, if present
-                    }
-                    _ => {}
-                }
-                self.current_scope = "global".to_string();
-                Ok((
-                    Function {
-                        name,
-                        args,
-                        expression: Box::new(expression),
-                        return_type,
-                    },
-                    start,
-                ))
+                self.token_node(SyntaxKind::TypeRef); // Eat the return_type
+                Ok((name, args, return_type, start))
             }
-            _ => Err("PASS".to_string()), // never happens
+            _ => Err(self.parser_error("Expected 'def'")), // never happens
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{AstNode, ExprValue, parse_text as parse};
+
+    #[test]
+    fn default_and_variadic_params() {
+        let ast = parse("def f(a: i32, b: i32 = 10, *rest: Any, **opts: Any) -> Any a;").unwrap();
+        let AstNode::FunctionDef(f) = &ast[0] else {
+            panic!("expected a function");
+        };
+        assert_eq!(f.args.name, vec!["a", "b"]);
+        assert!(matches!(
+            f.args.default[..],
+            [None, Some(ExprValue::Integer(10))]
+        ));
+        assert_eq!(f.args.vararg, Some(("rest".to_string(), "Any".to_string())));
+        assert_eq!(f.args.kwarg, Some(("opts".to_string(), "Any".to_string())));
+
+        assert!(parse("def f(a: i32 = 1, b: i32) -> Any a;").is_err());
+        assert!(parse("def f(**o: Any, a: i32) -> Any a;").is_err());
+    }
+
+    #[test]
+    fn keyword_arguments() {
+        let ast = parse("f(1, b=2);").unwrap();
+        let AstNode::Expression(ExprValue::FnCall(_, values, keywords, _)) = &ast[0] else {
+            panic!("expected a call");
+        };
+        assert_eq!(values.len(), 1);
+        assert_eq!(keywords[0].0, "b");
+
+        assert!(parse("f(b=2, 1);").is_err());
+    }
+}
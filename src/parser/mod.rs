@@ -1,12 +1,12 @@
-use crate::lexer::tokens::{Token, TokenType};
-use std::fs::read_to_string;
-// use std::io::BufRead;
+use crate::lexer::tokens::{Token, TokenType, TriviaKind};
+use crate::syntax::{SyntaxKind, TreeBuilder};
+use crate::{Result, format_error};
+use num_bigint::BigInt;
+use rowan::Checkpoint;
 use std::iter::Peekable;
 
 use std::vec::IntoIter;
 
-use owo_colors::OwoColorize;
-
 pub mod class;
 pub mod expression;
 pub mod function;
@@ -22,7 +22,7 @@ pub struct NodePosition {
 }
 
 //the top-level
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AstNode {
     Extern(External),
     FunctionDef(Function),
@@ -32,13 +32,24 @@ pub enum AstNode {
 
 #[derive(Debug, Clone)]
 pub enum ExprValue {
-    FnCall(String, Vec<ExprValue>),
+    /// Call with positional and `name=value` keyword arguments, and where
+    /// the name is.
+    FnCall(
+        String,
+        Vec<ExprValue>,
+        Vec<(String, ExprValue)>,
+        NodePosition,
+    ),
     UnOp(Box<TokenType>, Box<ExprValue>),
-    BinOp(Box<ExprValue>, Box<TokenType>, Box<ExprValue>),
+    /// Operands, operator and where the operator is.
+    BinOp(Box<ExprValue>, Box<TokenType>, Box<ExprValue>, NodePosition),
     Boolean(bool),
-    Integer(i32),
+    Integer(i64),
+    BigInteger(BigInt),
     Double(f64),
     Str(String),
+    /// `"...${expr}..."`, concatenating the string forms of its parts.
+    Interpolation(Vec<ExprValue>),
     Identifier(String),
     VarDecl {
         name: String,
@@ -59,18 +70,81 @@ pub enum ExprValue {
         value: Box<ExprValue>,
     },
     Return(Box<ExprValue>),
-    Use(String),
+    /// `use "path"`, with what it brings into scope.
+    Use(String, Import),
     Extern(String),
     None,
-    // Walrus(Box<ExprValue>, String, Box<ExprValue>),
+    /// `name =: value`, an assignment that evaluates to `value`.
+    Walrus {
+        name: String,
+        value: Box<ExprValue>,
+    },
     While(Box<ExprValue>, Box<ExprValue>),
     Do(Vec<ExprValue>),
     Array(Vec<ExprValue>),
+    Tuple(Vec<ExprValue>),
+    /// `let (x, y) = value`, `[a, b] = value` or `{a, b} = value`.
+    Destructure {
+        pattern: Pattern,
+        value: Box<ExprValue>,
+        declare: bool,
+    },
 }
 
-// 'extern' name (args) '->' return_type
-#[derive(Debug)]
+/// What a `use` brings into scope.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Import {
+    /// `use "path"`: everything the module exports.
+    All,
+    /// `use "path" as name`: the module, with its exports as attributes.
+    Module(String),
+    /// `use { name, name as alias } from "path"`: the listed exports, as
+    /// `(name, alias)` pairs.
+    Names(Vec<(String, String)>),
+}
+
+/// The left-hand side of a destructuring assignment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Identifier(String),
+    Tuple(Vec<Pattern>),
+    Array(Vec<Pattern>),
+    /// `{name, age}`, binding dict entries to variables of the same name.
+    Dict(Vec<String>),
+}
+
+impl Pattern {
+    /// Reinterpret an already parsed expression as an assignment target.
+    pub fn from_expr(expr: ExprValue) -> Option<Self> {
+        let all = |exprs: Vec<ExprValue>| {
+            exprs
+                .into_iter()
+                .map(Pattern::from_expr)
+                .collect::<Option<Vec<_>>>()
+        };
+        match expr {
+            ExprValue::Identifier(name) => Some(Pattern::Identifier(name)),
+            ExprValue::Tuple(exprs) => Some(Pattern::Tuple(all(exprs)?)),
+            ExprValue::Array(exprs) => Some(Pattern::Array(all(exprs)?)),
+            _ => None,
+        }
+    }
+
+    /// Names bound by this pattern, in order.
+    pub fn names(&self) -> Vec<String> {
+        match self {
+            Pattern::Identifier(name) => vec![name.clone()],
+            Pattern::Tuple(ps) | Pattern::Array(ps) => ps.iter().flat_map(|p| p.names()).collect(),
+            Pattern::Dict(names) => names.clone(),
+        }
+    }
+}
+
+// 'extern' library 'def' name (args) '->' return_type
+#[derive(Debug, Clone)]
 pub struct External {
+    /// The shared library the C function is in.
+    pub library: String,
     pub name: String,
     pub args: Args,
     pub return_type: String,
@@ -83,13 +157,19 @@ pub struct Function {
     pub args: Args,
     pub expression: Box<(ExprValue, NodePosition)>,
     pub return_type: String,
+    /// Marked `pub`, so other modules can import it.
+    pub public: bool,
+    /// The class the function is a method of.
+    pub class: Option<String>,
 }
 
 // 'class' name {functions}
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Class {
     pub name: String,
     pub fns: Vec<(Function, NodePosition)>,
+    /// Marked `pub`, so other modules can import it.
+    pub public: bool,
 }
 
 #[derive(Debug)]
@@ -99,81 +179,309 @@ pub struct Module {
     pub fns: Vec<(Function, NodePosition)>,
 }
 
-/// A parser that generates an abstract syntax tree.
+/// A parser that generates an abstract syntax tree, and the lossless syntax
+/// tree of [`crate::syntax`] when asked to.
 pub struct Parser {
-    tokens: TokenIter,
+    tokens: IntoIter<Token>,
     current_scope: String,
     pos: i32,
     line_no: i32,
     file: String,
+    /// The lines of the source, for error snippets.
+    lines: Vec<String>,
+    /// The line number of the first of `lines`.
+    first_line: i32,
+    /// Cause and `(line_no, pos)` of the last error, without the snippet.
+    last_error: Option<(String, (i32, i32))>,
+    /// The syntax tree of the tokens eaten so far, if one is being built.
+    tree: Option<TreeBuilder>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Args {
     pub name: Vec<String>,
     pub type_: Vec<String>,
+    /// Default value of each parameter in `name`, if any.
+    pub default: Vec<Option<ExprValue>>,
+    /// `*rest: Type`, collecting extra positional arguments.
+    pub vararg: Option<(String, String)>,
+    /// `**opts: Type`, collecting extra keyword arguments.
+    pub kwarg: Option<(String, String)>,
 } // I will  improvise this later.
 
+/// Call-site arguments matched against a function's [`Args`].
+#[derive(Debug, PartialEq)]
+pub struct BoundArgs<T> {
+    /// One slot per named parameter; `None` means the default should be used.
+    pub params: Vec<Option<T>>,
+    /// Extra positional arguments, collected by `*rest`.
+    pub rest: Vec<T>,
+    /// Extra keyword arguments, collected by `**opts`.
+    pub opts: Vec<(String, T)>,
+}
+
+impl Args {
+    pub fn new() -> Self {
+        Args {
+            name: vec![],
+            type_: vec![],
+            default: vec![],
+            vararg: None,
+            kwarg: None,
+        }
+    }
+
+    /// Number of parameters that must always be supplied.
+    pub fn required(&self) -> usize {
+        self.default.iter().filter(|d| d.is_none()).count()
+    }
+
+    /// Match positional and keyword arguments of a call to the parameters.
+    ///
+    /// Fails on too few or too many arguments, unknown keywords and
+    /// parameters given more than once.
+    pub fn bind<T>(&self, positional: Vec<T>, keywords: Vec<(String, T)>) -> Result<BoundArgs<T>> {
+        let mut params: Vec<Option<T>> = self.name.iter().map(|_| None).collect();
+        let mut rest = vec![];
+        let mut opts = vec![];
+
+        let given = positional.len();
+        for (i, value) in positional.into_iter().enumerate() {
+            if i < params.len() {
+                params[i] = Some(value);
+            } else if self.vararg.is_some() {
+                rest.push(value);
+            } else {
+                return Err(self.arity_error(given));
+            }
+        }
+
+        for (name, value) in keywords {
+            match self.name.iter().position(|n| *n == name) {
+                Some(i) if params[i].is_some() => {
+                    return Err(format!("got multiple values for argument `{}`", name));
+                }
+                Some(i) => params[i] = Some(value),
+                None if self.kwarg.is_some() => opts.push((name, value)),
+                None => return Err(format!("unexpected keyword argument `{}`", name)),
+            }
+        }
+
+        for (i, slot) in params.iter().enumerate() {
+            if slot.is_none() && self.default[i].is_none() {
+                return Err(format!("missing argument `{}`", self.name[i]));
+            }
+        }
+
+        Ok(BoundArgs { params, rest, opts })
+    }
+
+    fn arity_error(&self, given: usize) -> String {
+        let expected = if self.required() == self.name.len() {
+            format!("{}", self.name.len())
+        } else {
+            format!("{} to {}", self.required(), self.name.len())
+        };
+        let noun = if expected == "1" {
+            "argument"
+        } else {
+            "arguments"
+        };
+        let verb = if given == 1 { "was" } else { "were" };
+        format!(
+            "expected {} positional {} but {} {} given",
+            expected, noun, given, verb
+        )
+    }
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Parser {
     pub fn new(tokens: TokenIter, file_path: &str) -> Self {
         Parser {
-            tokens,
+            tokens: tokens.collect::<Vec<_>>().into_iter(),
             current_scope: "global".to_string(),
             pos: -1,
             line_no: 1,
             file: file_path.to_string(),
+            lines: vec![],
+            first_line: 1,
+            last_error: None,
+            tree: None,
         }
     }
 
-    pub fn get_tok_precedence(&mut self, tok: TokenType) -> i32 {
-        match tok {
-            TokenType::Equal
-            | TokenType::NotEq
-            | TokenType::Greater
-            | TokenType::GreaterEq
-            | TokenType::Less
-            | TokenType::LessEq => 0,
-            TokenType::Minus | TokenType::Plus => 1,
-            TokenType::DivEq | TokenType::Mul => 2,
-            any => panic!("Bad operator! Unknown {:?}", any),
+    /// Show lines of `text`, which the tokens were lexed from, in error
+    /// messages.
+    pub fn with_source(self, text: &str) -> Self {
+        self.with_lines(text.lines().map(String::from).collect(), 1)
+    }
+
+    /// Show `lines`, the first of which is line `first_line` of the file, in
+    /// error messages, like [`crate::lexer::Lexer::from_text_at`].
+    pub fn with_lines(self, lines: Vec<String>, first_line: i32) -> Self {
+        Parser {
+            lines,
+            first_line,
+            ..self
         }
     }
 
+    /// Also build the syntax tree of the tokens, which must come from a
+    /// lossless lexer. See [`Parser::parse_tree`].
+    pub(crate) fn building(self, tree: TreeBuilder) -> Self {
+        Parser {
+            tree: Some(tree),
+            ..self
+        }
+    }
+
+    /// Cause and `(line_no, pos)` of the last error, for tools that show
+    /// errors their own way.
+    pub fn last_error(&self) -> Option<&(String, (i32, i32))> {
+        self.last_error.as_ref()
+    }
+
+    /// Binding power of a binary operator, or -1 if `tok` is not one.
+    pub fn get_tok_precedence(&self, tok: &TokenType) -> i32 {
+        tok.precedence()
+    }
+
     fn advance(&mut self) {
-        self.pos = match self.tokens.peek() {
-            Some(t) => t,
-            None => panic!("Dunno"),
+        let t = self.peek();
+        (self.pos, self.line_no) = (t.pos, t.line_no);
+        // self.file = t.file.to_string();
+    }
+
+    /// The next token, which is [`TokenType::Eof`] once the input is exhausted.
+    fn peek(&mut self) -> &Token {
+        if self.tokens.as_slice().is_empty() {
+            // Token streams that didn't come from the lexer may lack an Eof.
+            let eof = Token::new(TokenType::Eof, self.pos + 1, self.line_no, &self.file);
+            self.tokens = vec![eof].into_iter();
         }
-        .pos;
-        self.line_no = match self.tokens.peek() {
-            Some(t) => t,
-            None => panic!("Dunno"),
+        &self.tokens.as_slice()[0]
+    }
+
+    /// The type of the token `n` tokens after the next one, if there is one.
+    fn nth(&self, n: usize) -> Option<&TokenType> {
+        self.tokens.as_slice().get(n).map(|t| &t.type_)
+    }
+
+    /// Eat and return the next token. [`TokenType::Eof`] is never eaten, so
+    /// it is returned by every call at the end of the input.
+    fn next_token(&mut self) -> Token {
+        if self.peek().type_ == TokenType::Eof {
+            return self.peek().clone();
         }
-        .line_no;
-        // self.file = match self.tokens.peek(){
-        //     Some(t)=>t,
-        //     None=> panic!("Dunno")
-        // }.file.to_string();
+        let token = self.tokens.next().expect("peek found a token");
+        if let Some(tree) = &mut self.tree {
+            tree.token(&token);
+        }
+        token
     }
 
-    fn parser_error(&self, cause: &str) -> String {
-        format!(
-            "
-{text}
-{pointy}
-{cause}
-
-    at {line}:{pos} in file `{file}`.",
-            text = read_to_string(self.file.clone())
-                .unwrap()
-                .lines()
-                .collect::<Vec<_>>()[(self.line_no - 1) as usize],
-            pointy = ("~".repeat(self.pos as usize) + "^").red(),
-            cause = cause.yellow(),
-            line = self.line_no.green(),
-            pos = self.pos.green(),
-            file = self.file.green()
-        )
-        .to_string()
+    /// Open a node of the syntax tree at the next token.
+    fn start_node(&mut self, kind: SyntaxKind) {
+        self.peek();
+        if let Some(tree) = &mut self.tree {
+            tree.start_node(kind, &self.tokens.as_slice()[0]);
+        }
+    }
+
+    fn finish_node(&mut self) {
+        if let Some(tree) = &mut self.tree {
+            tree.finish_node();
+        }
+    }
+
+    /// Where a node starting at the next token can be opened once its kind
+    /// is known, with [`Parser::wrap`].
+    fn checkpoint(&mut self) -> Option<Checkpoint> {
+        self.peek();
+        let next = &self.tokens.as_slice()[0];
+        self.tree.as_mut().map(|tree| tree.checkpoint(next))
+    }
+
+    /// Wrap everything parsed since `checkpoint` in a node.
+    fn wrap(&mut self, checkpoint: Option<Checkpoint>, kind: SyntaxKind) {
+        if let (Some(tree), Some(checkpoint)) = (&mut self.tree, checkpoint) {
+            tree.wrap(checkpoint, kind);
+        }
+    }
+
+    /// Eat the next token in a node of its own, like a [`SyntaxKind::Name`].
+    fn token_node(&mut self, kind: SyntaxKind) -> Token {
+        self.start_node(kind);
+        self.advance();
+        let token = self.next_token();
+        self.finish_node();
+        token
+    }
+
+    /// Report `error` in the syntax tree and skip the rest of the statement,
+    /// up to the next line starting in the first column with something else
+    /// than a closing bracket or `end`.
+    fn recover(&mut self, error: String) {
+        let (message, at) = match self.last_error.take() {
+            Some((cause, at)) => (cause, Some(at)),
+            None => (error, None),
+        };
+        self.peek();
+        let Some(tree) = &mut self.tree else {
+            return;
+        };
+        tree.error(message, at, &self.tokens.as_slice()[0]);
+        tree.close_nodes();
+        if self.peek().type_ == TokenType::Eof {
+            return;
+        }
+        self.start_node(SyntaxKind::Error);
+        loop {
+            self.next_token();
+            let next = self.peek();
+            let starts_line = next
+                .leading_trivia
+                .last()
+                .is_some_and(|t| t.kind == TriviaKind::Newline);
+            let closes = matches!(
+                next.type_,
+                TokenType::RParen | TokenType::RBrack | TokenType::RBrace | TokenType::End
+            );
+            if next.type_ == TokenType::Eof || (starts_line && !closes) {
+                break;
+            }
+        }
+        self.finish_node();
     }
+
+    fn parser_error(&mut self, cause: &str) -> String {
+        self.last_error = Some((cause.to_string(), (self.line_no, self.pos)));
+        let text = usize::try_from(self.line_no - self.first_line)
+            .ok()
+            .and_then(|i| self.lines.get(i))
+            .map_or("", String::as_str);
+        format_error(text, cause, self.line_no, self.pos, &self.file)
+    }
+}
+
+/// Lex and parse a whole program read from `file`.
+pub fn parse_source(text: &str, file: &str) -> Result<Vec<(AstNode, NodePosition)>> {
+    let tokens = crate::lexer::Lexer::from_text(text, file)
+        .tokenize()
+        .map_err(|errors| errors.join("\n"))?;
+    Parser::new(tokens.into_iter().peekable(), file)
+        .with_source(text)
+        .parse_program()
+}
+
+/// Lex and parse `src`, dropping node positions.
+#[cfg(test)]
+pub(crate) fn parse_text(src: &str) -> Result<Vec<AstNode>> {
+    parse_source(src, "<test>").map(|ast| ast.into_iter().map(|(n, _)| n).collect())
 }
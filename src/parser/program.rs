@@ -1,60 +1,155 @@
 use crate::Result;
 use crate::lexer::tokens::TokenType;
-use crate::parser::{AstNode, NodePosition, Parser};
+use crate::parser::{AstNode, ExprValue, External, NodePosition, Parser};
+use crate::syntax::{Parse, SyntaxKind};
 
 impl Parser {
     pub fn parse_program(&mut self) -> Result<Vec<(AstNode, NodePosition)>> {
         let mut ast: Vec<(AstNode, NodePosition)> = Vec::new();
-        loop {
-            match self.tokens.peek() {
-                Some(s) => match s.type_ {
-                    TokenType::Def => match self.parse_function() {
-                        Ok((result, pos)) => {
-                            ast.insert(ast.len(), (AstNode::FunctionDef(result), pos));
-                        }
-                        Err(e) if e == *"EOF".to_string() => return Ok(ast),
-                        Err(e) => return Err(e),
-                    },
-
-                    TokenType::Class => match self.parse_class() {
-                        Ok((result, pos)) => {
-                            ast.insert(ast.len(), (AstNode::Class(result), pos));
-                        }
-                        Err(e) if e == *"EOF".to_string() => return Ok(ast),
-                        Err(e) => return Err(e),
-                    },
-
-                    _ => {
-                        match self.parse_expression() {
-                            Ok((result, pos)) => {
-                                match self.tokens.peek() {
-                                    Some(t) if t.type_ == TokenType::Semicolon => {
-                                        self.tokens.next();
-                                    } // eat ';'
-                                    Some(_) => {
-                                        // continue
-                                        // let _pos = unwrap_some!(self.tokens.peek()).pos;
-                                        // let _line = unwrap_some!(self.tokens.peek()).line_no;
-                                        // return Err(self
-                                        //     .parser_error("Expected semicolon after expression"));
-                                    }
-                                    None => return Err("EOF".to_string()),
-                                };
-                                ast.insert(ast.len(), (AstNode::Expression(result), pos));
-                            }
-                            Err(e) if e == *"EOF".to_string() => return Ok(ast),
-                            Err(e) => {
-                                // println!("{:?}", e);
-                                return Err(e);
-                            }
-                        }
-                    } // {
-                      // 	println!("{:?}", self.tokens.peek());
-                      // 	return Err("Only functions or expressions allowed at top-level.".to_string())
-                      // }
-                },
-                None => return Ok(ast),
+        while self.peek().type_ != TokenType::Eof {
+            self.parse_item(&mut ast)?;
+        }
+        Ok(ast)
+    }
+
+    /// Parse the whole program into the syntax tree given to
+    /// [`Parser::building`], going on with the next line after an error.
+    pub(crate) fn parse_tree(mut self) -> Parse {
+        let mut ast = vec![];
+        while self.peek().type_ != TokenType::Eof {
+            if let Err(e) = self.parse_item(&mut ast) {
+                self.recover(e);
             }
         }
+        let eof = self.peek().clone();
+        let tree = self.tree.take().expect("a syntax tree is being built");
+        tree.finish(&eof)
+    }
+
+    /// Parse a definition or expression of the top level into `ast`.
+    fn parse_item(&mut self, ast: &mut Vec<(AstNode, NodePosition)>) -> Result<()> {
+        let extern_fn = self.nth(2) == Some(&TokenType::Def);
+        match self.peek().type_ {
+            TokenType::Def => {
+                self.start_node(SyntaxKind::FnDef);
+                let (result, pos) = self.parse_function()?;
+                self.finish_node();
+                ast.push((AstNode::FunctionDef(result), pos));
+            }
+
+            TokenType::Class => {
+                self.start_node(SyntaxKind::ClassDef);
+                let (result, pos) = self.parse_class()?;
+                self.finish_node();
+                ast.push((AstNode::Class(result), pos));
+            }
+
+            // Exported definitions
+            TokenType::Pub => {
+                match self.nth(1) {
+                    Some(TokenType::Def) => self.start_node(SyntaxKind::FnDef),
+                    Some(TokenType::Class) => self.start_node(SyntaxKind::ClassDef),
+                    _ => {}
+                }
+                self.advance();
+                self.next_token(); // Eat 'pub'
+                let node = match self.peek().type_ {
+                    TokenType::Def => {
+                        let (mut result, pos) = self.parse_function()?;
+                        result.public = true;
+                        (AstNode::FunctionDef(result), pos)
+                    }
+                    TokenType::Class => {
+                        let (mut result, pos) = self.parse_class()?;
+                        result.public = true;
+                        (AstNode::Class(result), pos)
+                    }
+                    _ => return Err(self.parser_error("Expected 'def' or 'class' after 'pub'")),
+                };
+                self.finish_node();
+                ast.push(node);
+            }
+
+            // `extern "library" def ...` declares a C function.
+            TokenType::Extern if extern_fn => {
+                self.start_node(SyntaxKind::FnDef);
+                let (library, pos) = self.parse_extern()?;
+                let ExprValue::Extern(library) = library else {
+                    unreachable!()
+                };
+                let (name, args, return_type, _) = self.parse_signature()?;
+                self.current_scope = "global".to_string();
+                self.finish_node();
+                if self.peek().type_ == TokenType::Semicolon {
+                    self.advance();
+                    self.next_token(); // eat ';'
+                }
+                ast.push((
+                    AstNode::Extern(External {
+                        library,
+                        name,
+                        args,
+                        return_type,
+                    }),
+                    pos,
+                ));
+            }
+
+            // Stray semicolons between expressions
+            TokenType::Semicolon => {
+                self.advance();
+                self.next_token();
+            }
+
+            _ => {
+                let (result, pos) = self.parse_expression()?;
+                if self.peek().type_ == TokenType::Semicolon {
+                    self.advance();
+                    self.next_token(); // eat ';'
+                }
+                ast.push((AstNode::Expression(result), pos));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{AstNode, ExprValue, parse_text as parse};
+
+    #[test]
+    fn end_of_file() {
+        assert_eq!(parse("").unwrap().len(), 0);
+        assert_eq!(parse("x = 1\ny = 2").unwrap().len(), 2);
+        assert_eq!(parse("def f() -> Any 1").unwrap().len(), 1);
+
+        for src in ["f(1", "do a", "class A {", "[1, 2", "let", "if a", "def f("] {
+            assert!(parse(src).is_err(), "{} should not parse", src);
+        }
+        assert!(parse("do a").unwrap_err().contains("Expected 'end'"));
+    }
+
+    #[test]
+    fn exports() {
+        let ast = parse("pub def f() -> Any 1\ndef g() -> Any 1\npub class C {}").unwrap();
+        assert!(matches!(&ast[0], AstNode::FunctionDef(f) if f.public));
+        assert!(matches!(&ast[1], AstNode::FunctionDef(f) if !f.public));
+        assert!(matches!(&ast[2], AstNode::Class(c) if c.public));
+        assert!(parse("pub x = 1").is_err());
+    }
+
+    #[test]
+    fn externs() {
+        let ast = parse("extern \"libm.so.6\" def pow(x: f64, y: f64) -> f64\nextern \"libx.so\"")
+            .unwrap();
+        let AstNode::Extern(f) = &ast[0] else {
+            panic!("expected an extern function");
+        };
+        assert_eq!((f.library.as_str(), f.name.as_str()), ("libm.so.6", "pow"));
+        assert_eq!(f.args.type_, ["f64", "f64"]);
+        assert_eq!(f.return_type, "f64");
+        assert!(matches!(&ast[1], AstNode::Expression(ExprValue::Extern(l)) if l == "libx.so"));
+        assert!(parse("extern \"libm.so.6\" def f(").is_err());
     }
 }
@@ -0,0 +1,212 @@
+//! The interactive prompt of `lyronc repl`.
+//!
+//! Each input is lexed, parsed and run in one [`Interpreter`], so globals,
+//! functions and classes stay defined for later inputs. Input that ends inside
+//! a `do` block, a class body, brackets or a string continues on the next line;
+//! an empty line runs it anyway. `:type` runs its expression too, so what it
+//! does and assigns stays done.
+
+use crate::Result;
+use crate::interpreter::{Interpreter, Value};
+use crate::lexer::Lexer;
+use crate::parser::parse_source;
+use crate::syntax;
+
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use std::fs;
+
+const HELP: &str = "\
+:tokens <code>   show the tokens of <code>
+:ast <code>      show the syntax tree of <code>
+:type <expr>     run <expr> and show the type of its value
+:load <file>     run <file> in this session
+:help            show this help
+:quit            leave the REPL";
+
+/// File name used in the errors of typed input.
+const INPUT: &str = "<repl>";
+
+pub struct Repl {
+    interpreter: Interpreter,
+    /// Set by `:quit`.
+    pub done: bool,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl {
+            interpreter: Interpreter::new(),
+            done: false,
+        }
+    }
+
+    /// Run one input, which is code or a command, and return what to show.
+    pub fn eval(&mut self, input: &str) -> Result<Option<String>> {
+        let Some(command) = input.trim().strip_prefix(':') else {
+            return match self.interpreter.run_text(input, INPUT)? {
+                Value::None => Ok(None),
+                value => Ok(Some(value.repr())),
+            };
+        };
+        let (name, arg) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let arg = arg.trim();
+        match name {
+            "tokens" => {
+                let tokens = Lexer::from_text(arg, INPUT)
+                    .tokenize()
+                    .map_err(|errors| errors.join("\n"))?;
+                let lines = tokens
+                    .iter()
+                    .map(|t| format!("{}:{} {:?}", t.line_no, t.pos, t.type_))
+                    .collect::<Vec<_>>();
+                Ok(Some(lines.join("\n")))
+            }
+            "ast" => {
+                let ast = parse_source(arg, INPUT)?;
+                let nodes = ast
+                    .iter()
+                    .map(|(n, _)| format!("{:#?}", n))
+                    .collect::<Vec<_>>();
+                Ok(Some(nodes.join("\n")))
+            }
+            "type" => {
+                let value = self.interpreter.run_text(arg, INPUT)?;
+                Ok(Some(value.type_name()))
+            }
+            "load" => {
+                let text = fs::read_to_string(arg).map_err(|e| format!("{}: {}", arg, e))?;
+                self.interpreter.run_text(&text, arg)?;
+                Ok(None)
+            }
+            "help" => Ok(Some(HELP.to_string())),
+            "quit" | "q" => {
+                self.done = true;
+                Ok(None)
+            }
+            _ => Err(format!("unknown command `:{}`, see :help", name)),
+        }
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `input` stops in the middle of a construct, so more lines should
+/// be read before running it.
+pub fn is_incomplete(input: &str) -> bool {
+    if input.trim_start().starts_with(':') {
+        return false;
+    }
+    let end = rowan::TextSize::of(input.trim_end());
+    syntax::parse(input).errors.iter().any(|e| {
+        e.range.start() >= end || (e.range.end() >= end && e.message.contains("unterminated"))
+    })
+}
+
+/// Read, evaluate and print until `:quit` or end of input.
+pub fn run() -> Result<()> {
+    let mut editor = DefaultEditor::new().map_err(|e| e.to_string())?;
+    let mut repl = Repl::new();
+    println!(
+        "Lyron {} - type :help for commands",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let mut input = String::new();
+    while !repl.done {
+        let prompt = if input.is_empty() { ">>> " } else { "... " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C drops the input being typed.
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.to_string()),
+        };
+        if input.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        input.push_str(&line);
+        input.push('\n');
+        if !line.trim().is_empty() && is_incomplete(&input) {
+            continue;
+        }
+
+        let _ = editor.add_history_entry(input.trim_end());
+        match repl.eval(&input) {
+            Ok(Some(output)) => println!("{}", output),
+            Ok(None) => {}
+            Err(e) => println!("{}", e),
+        }
+        input.clear();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Repl, is_incomplete};
+
+    #[test]
+    fn incomplete_input() {
+        for input in [
+            "def f() -> Any do\n",
+            "class A {\n",
+            "class A {\n  def A(self: Self) -> A do\n    self\n  end\n",
+            "f(1,\n",
+            "x = \"abc\n",
+            "1 +\n",
+            "if x then\n",
+        ] {
+            assert!(is_incomplete(input), "{:?}", input);
+        }
+        for input in [
+            "def f() -> Any do\n  1\nend\n",
+            "class A {}\n",
+            "x = 1 # comment\n",
+            "f(1))\n",
+            ":type 1 +\n",
+        ] {
+            assert!(!is_incomplete(input), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn state_and_commands() {
+        let mut repl = Repl::new();
+        let mut eval = |input: &str| repl.eval(input).map(|o| o.unwrap_or_default());
+
+        assert_eq!(eval("x = 20").unwrap(), "");
+        assert_eq!(eval("def double(n: i32) -> i32 n * 2").unwrap(), "");
+        assert_eq!(eval("class A { def A(self: Self) -> A self }").unwrap(), "");
+        assert_eq!(eval("double(x) + 2").unwrap(), "42");
+        assert_eq!(eval("\"s\"").unwrap(), "\"s\"");
+        assert_eq!(eval(":type A()").unwrap(), "A");
+        assert_eq!(eval(":type double").unwrap(), "Function");
+        assert_eq!(
+            eval(":tokens x + 1").unwrap(),
+            "1:0 Identifier(\"x\")\n1:2 Plus\n1:4 Integer(1)\n1:5 Eof"
+        );
+        assert!(eval(":ast f(1)").unwrap().contains("FnCall"));
+        assert!(eval("y").unwrap_err().contains("undefined variable `y`"));
+        // Errors show the line typed.
+        let e = eval("x = (1 + )").unwrap_err();
+        assert!(e.starts_with("\nx = (1 + )\n"), "{:?}", e);
+        assert!(eval(":nope").is_err());
+
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/vm_test.lyr");
+        assert_eq!(eval(&format!(":load {}", path.display())).unwrap(), "");
+        assert_eq!(eval("sq(4)").unwrap(), "16");
+
+        assert_eq!(eval(":quit").unwrap(), "");
+        assert!(repl.done);
+    }
+}
@@ -0,0 +1,298 @@
+use crate::Result;
+use crate::builtins::Registry;
+use crate::lexer::tokens::TokenType;
+use crate::parser::{Args, AstNode, ExprValue, Function, Import, NodePosition};
+
+use std::collections::{HashMap, HashSet};
+
+/// Checks calls to top-level functions and builtins against their signatures.
+pub struct Resolver {
+    functions: HashMap<String, Args>,
+    /// Variables of the code being resolved, which shadow functions and
+    /// builtins of the same name.
+    scope: HashSet<String>,
+    errors: Vec<(String, NodePosition)>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::with_builtins(&Registry::standard())
+    }
+
+    /// A resolver knowing the builtins of `registry`.
+    pub fn with_builtins(registry: &Registry) -> Self {
+        Resolver {
+            functions: registry
+                .iter()
+                .map(|b| (b.name.clone(), b.args()))
+                .collect(),
+            scope: HashSet::new(),
+            errors: vec![],
+        }
+    }
+
+    /// Resolve a whole program, returning every error found.
+    pub fn resolve_program(&mut self, ast: &[(AstNode, NodePosition)]) -> Result<()> {
+        let errors = self.check_program(ast);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors
+                .iter()
+                .map(|(e, pos)| {
+                    format!(
+                        "{}\n    at {}:{} in file `{}`.",
+                        e, pos.line_no, pos.pos, pos.file
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+    }
+
+    /// Like [`Resolver::resolve_program`], but keeps the position of each error
+    /// apart from its message.
+    pub fn check_program(
+        &mut self,
+        ast: &[(AstNode, NodePosition)],
+    ) -> Vec<(String, NodePosition)> {
+        for (node, _) in ast {
+            match node {
+                AstNode::FunctionDef(f) => {
+                    self.functions.insert(f.name.clone(), f.args.clone());
+                }
+                AstNode::Extern(f) => {
+                    self.functions.insert(f.name.clone(), f.args.clone());
+                }
+                _ => {}
+            }
+        }
+
+        // Functions see the variables of the top level too.
+        let mut globals = HashSet::new();
+        for (node, _) in ast {
+            if let AstNode::Expression(e) = node {
+                bound_names(e, &mut globals);
+            }
+        }
+
+        for (node, pos) in ast {
+            match node {
+                AstNode::FunctionDef(f) => self.resolve_function(f, pos, &globals),
+                AstNode::Class(c) => {
+                    for (f, pos) in &c.fns {
+                        self.resolve_function(f, pos, &globals);
+                    }
+                }
+                AstNode::Expression(e) => {
+                    self.scope.clone_from(&globals);
+                    self.resolve_expr(e, pos)
+                }
+                AstNode::Extern(_) => {}
+            }
+        }
+
+        std::mem::take(&mut self.errors)
+    }
+
+    fn resolve_function(&mut self, f: &Function, pos: &NodePosition, globals: &HashSet<String>) {
+        self.scope.clone_from(globals);
+        self.scope.extend(f.args.name.iter().cloned());
+        self.scope.extend(
+            f.args
+                .vararg
+                .iter()
+                .chain(&f.args.kwarg)
+                .map(|(n, _)| n.clone()),
+        );
+        bound_names(&f.expression.0, &mut self.scope);
+        for d in f.args.default.iter().flatten() {
+            self.resolve_expr(d, pos);
+        }
+        self.resolve_expr(&f.expression.0, &f.expression.1)
+    }
+
+    fn resolve_expr(&mut self, expr: &ExprValue, pos: &NodePosition) {
+        match expr {
+            ExprValue::FnCall(name, values, keywords, _) => {
+                if let Some(args) = self
+                    .functions
+                    .get(name)
+                    .filter(|_| !self.scope.contains(name))
+                {
+                    let keys = keywords.iter().map(|(k, _)| (k.clone(), ())).collect();
+                    if let Err(e) = args.bind(vec![(); values.len()], keys) {
+                        self.errors
+                            .push((format!("in call to `{}`: {}", name, e), pos.clone()));
+                    }
+                }
+                values.iter().for_each(|v| self.resolve_expr(v, pos));
+                keywords.iter().for_each(|(_, v)| self.resolve_expr(v, pos));
+            }
+            // Method calls are looked up on the receiver at runtime.
+            ExprValue::BinOp(l, op, r, _) if **op == TokenType::Dot => {
+                self.resolve_expr(l, pos);
+                if let ExprValue::FnCall(_, values, keywords, _) = &**r {
+                    values.iter().for_each(|v| self.resolve_expr(v, pos));
+                    keywords.iter().for_each(|(_, v)| self.resolve_expr(v, pos));
+                } else {
+                    self.resolve_expr(r, pos);
+                }
+            }
+            ExprValue::BinOp(l, _, r, _) | ExprValue::While(l, r) => {
+                self.resolve_expr(l, pos);
+                self.resolve_expr(r, pos);
+            }
+            ExprValue::UnOp(_, e)
+            | ExprValue::Return(e)
+            | ExprValue::Assign { value: e, .. }
+            | ExprValue::AugAssign { value: e, .. }
+            | ExprValue::Walrus { value: e, .. }
+            | ExprValue::Destructure { value: e, .. } => self.resolve_expr(e, pos),
+            ExprValue::IfElse { cond, if_, else_ } => {
+                self.resolve_expr(cond, pos);
+                self.resolve_expr(if_, pos);
+                self.resolve_expr(else_, pos);
+            }
+            ExprValue::Do(exprs)
+            | ExprValue::Array(exprs)
+            | ExprValue::Tuple(exprs)
+            | ExprValue::Interpolation(exprs) => {
+                exprs.iter().for_each(|e| self.resolve_expr(e, pos))
+            }
+            ExprValue::Boolean(_)
+            | ExprValue::Integer(_)
+            | ExprValue::BigInteger(_)
+            | ExprValue::Double(_)
+            | ExprValue::Str(_)
+            | ExprValue::Identifier(_)
+            | ExprValue::VarDecl { .. }
+            | ExprValue::Use(..)
+            | ExprValue::Extern(_)
+            | ExprValue::None => {}
+        }
+    }
+}
+
+/// Add the variables `expr` assigns or imports to `names`.
+fn bound_names(expr: &ExprValue, names: &mut HashSet<String>) {
+    match expr {
+        ExprValue::Assign { name, value }
+        | ExprValue::AugAssign { name, value, .. }
+        | ExprValue::Walrus { name, value } => {
+            names.insert(name.clone());
+            bound_names(value, names);
+        }
+        ExprValue::VarDecl { name, .. } => {
+            names.insert(name.clone());
+        }
+        ExprValue::Destructure { pattern, value, .. } => {
+            names.extend(pattern.names());
+            bound_names(value, names);
+        }
+        ExprValue::Use(_, Import::Module(name)) => {
+            names.insert(name.clone());
+        }
+        ExprValue::Use(_, Import::Names(imported)) => {
+            names.extend(imported.iter().map(|(_, alias)| alias.clone()));
+        }
+        ExprValue::FnCall(_, values, keywords, _) => {
+            values.iter().for_each(|v| bound_names(v, names));
+            keywords.iter().for_each(|(_, v)| bound_names(v, names));
+        }
+        ExprValue::BinOp(l, _, r, _) | ExprValue::While(l, r) => {
+            bound_names(l, names);
+            bound_names(r, names);
+        }
+        ExprValue::UnOp(_, e) | ExprValue::Return(e) => bound_names(e, names),
+        ExprValue::IfElse { cond, if_, else_ } => {
+            bound_names(cond, names);
+            bound_names(if_, names);
+            bound_names(else_, names);
+        }
+        ExprValue::Do(exprs)
+        | ExprValue::Array(exprs)
+        | ExprValue::Tuple(exprs)
+        | ExprValue::Interpolation(exprs) => exprs.iter().for_each(|e| bound_names(e, names)),
+        ExprValue::Boolean(_)
+        | ExprValue::Integer(_)
+        | ExprValue::BigInteger(_)
+        | ExprValue::Double(_)
+        | ExprValue::Str(_)
+        | ExprValue::Identifier(_)
+        | ExprValue::Use(_, Import::All)
+        | ExprValue::Extern(_)
+        | ExprValue::None => {}
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resolver;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn resolve(src: &str) -> crate::Result<()> {
+        let tokens = Lexer::from_text(src, "<test>").collect::<crate::Result<Vec<_>>>()?;
+        let ast = Parser::new(tokens.into_iter().peekable(), "<test>")
+            .with_source(src)
+            .parse_program()?;
+        Resolver::new().resolve_program(&ast)
+    }
+
+    #[test]
+    fn arity() {
+        let f = "def f(a: i32, b: i32 = 10, *rest: Any, **opts: Any) -> Any a;";
+        assert!(resolve(&format!("{} f(1);", f)).is_ok());
+        assert!(resolve(&format!("{} f(1, b=2, c=3);", f)).is_ok());
+        assert!(resolve(&format!("{} f(1, 2, 3, 4);", f)).is_ok());
+        assert!(resolve(&format!("{} f();", f)).is_err());
+        assert!(resolve(&format!("{} f(1, a=2);", f)).is_err());
+
+        let g = "def g(a: i32) -> Any a;";
+        assert!(resolve(&format!("{} g(1, 2);", g)).is_err());
+        assert!(resolve(&format!("{} g(b=1);", g)).is_err());
+
+        // Parameters and variables shadow functions of the same name.
+        let add = "def add(a: i32, b: i32) -> i32 a + b\n";
+        assert!(resolve(&format!("{}def apply(add: Any) -> Any add(1)", add)).is_ok());
+        assert!(resolve(&format!("{}def h() -> Any do add = g; add(1) end", add)).is_ok());
+        assert!(resolve(&format!("{}add = 1\nadd(1)", add)).is_ok());
+        assert!(resolve(&format!("{}def h(x: Any) -> Any add(1)", add)).is_err());
+        // Defaults of methods are resolved.
+        let e = resolve(&format!(
+            "{}class C {{ def m(a: i32 = add(1)) -> Any a }}",
+            add
+        ));
+        assert!(e.unwrap_err().contains("missing argument `b`"));
+    }
+
+    #[test]
+    fn builtins() {
+        assert!(resolve("print(); print(1, 2, 3); read_file(\"a\")").is_ok());
+        let e = resolve("write_file(\"a\")").unwrap_err();
+        assert!(
+            e.starts_with("in call to `write_file`: missing argument `data`"),
+            "{}",
+            e
+        );
+        assert!(resolve("json_parse(\"1\", 2)").is_err());
+        // A definition shadows the builtin.
+        assert!(resolve("def len(a: Any, b: Any) -> Any a\nlen(1, 2)").is_ok());
+        // So do parameters and variables.
+        assert!(resolve("def apply(len: Any) -> Any len(1, 2)").is_ok());
+        assert!(resolve("print = 1\nprint(1)").is_ok());
+        let e = resolve("def f(x: Any) -> Any len(1, 2)").unwrap_err();
+        assert!(
+            e.starts_with("in call to `len`: expected 1 positional argument but 2 were given"),
+            "{}",
+            e
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! Limits and capabilities for running code that isn't trusted.
+//!
+//! [`Limits`] bound what a run may use up: running past one stops the
+//! program with an error. [`Capability`]s guard the builtins reaching outside
+//! of the program, like files and sockets. An interpreter is given all of
+//! them unless it's sandboxed, in which case it only has the ones granted
+//! and calling any other builtin needing one is an error.
+
+use crate::Result;
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How much a run may use up; `None` is no limit. A run is a program, or a
+/// call from the host into the code run before.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    /// Expressions evaluated.
+    pub instructions: Option<u64>,
+    /// How deep calls may nest. Without this limit, calls nest up to 10000
+    /// deep.
+    pub call_depth: Option<usize>,
+    /// Containers (arrays, dicts, objects and tuples) alive at once.
+    pub heap: Option<usize>,
+    /// Bytes of strings, big integers and array elements made, counted before
+    /// making them.
+    pub memory: Option<usize>,
+    /// Wall-clock time.
+    pub time: Option<Duration>,
+}
+
+impl Limits {
+    /// Whether there is any limit, so runs need metering.
+    pub fn any(&self) -> bool {
+        *self != Limits::default()
+    }
+}
+
+/// Something a builtin does outside of the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// `read_file`.
+    ReadFiles,
+    /// `write_file`.
+    WriteFiles,
+    /// `start_tcp_server`.
+    Network,
+    /// Loading shared libraries with `extern` and calling C functions.
+    Native,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::ReadFiles,
+        Capability::WriteFiles,
+        Capability::Network,
+        Capability::Native,
+    ];
+
+    /// The name flags and messages use.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::ReadFiles => "read-files",
+            Capability::WriteFiles => "write-files",
+            Capability::Network => "network",
+            Capability::Native => "native",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Capability::ALL
+            .into_iter()
+            .find(|c| c.name() == s)
+            .ok_or_else(|| format!("no capability `{}`", s))
+    }
+}
+
+/// What the current run has used of its [`Limits`].
+#[derive(Debug)]
+pub(crate) struct Meter {
+    steps: u64,
+    bytes: usize,
+    started: Instant,
+}
+
+impl Meter {
+    pub(crate) fn new() -> Self {
+        Meter {
+            steps: 0,
+            bytes: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Count a step of the run, failing once it's over the instruction or
+    /// time limit.
+    pub(crate) fn step(&mut self, limits: &Limits) -> Result<()> {
+        self.steps += 1;
+        if let Some(max) = limits.instructions
+            && self.steps > max
+        {
+            return Err(format!("instruction limit of {} exceeded", max));
+        }
+        // Looking at the clock every step would slow runs down.
+        if self.steps.is_multiple_of(256) {
+            self.check_time(limits)?;
+        }
+        Ok(())
+    }
+
+    /// Fail if making `bytes` more of strings, big integers and array
+    /// elements would go over the memory limit, or if the run is out of time,
+    /// so a large value isn't made at all.
+    pub(crate) fn afford(&self, bytes: usize, limits: &Limits) -> Result<()> {
+        if let Some(max) = limits.memory
+            && self.bytes.saturating_add(bytes) > max
+        {
+            return Err(format!("memory limit of {} bytes exceeded", max));
+        }
+        if bytes > 0 {
+            self.check_time(limits)?;
+        }
+        Ok(())
+    }
+
+    /// Count `bytes` of strings, big integers and array elements made.
+    pub(crate) fn charge(&mut self, bytes: usize, limits: &Limits) -> Result<()> {
+        self.afford(bytes, limits)?;
+        self.bytes += bytes;
+        Ok(())
+    }
+
+    fn check_time(&self, limits: &Limits) -> Result<()> {
+        match limits.time {
+            Some(max) if self.started.elapsed() > max => {
+                Err(format!("time limit of {:?} exceeded", max))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capability, Limits};
+    use crate::engine::Engine;
+    use crate::interpreter::Interpreter;
+    use std::time::Duration;
+
+    fn limited(limits: Limits) -> Interpreter {
+        Interpreter::new().with_limits(limits)
+    }
+
+    #[test]
+    fn limits() {
+        let forever = "while true none";
+        let e = limited(Limits {
+            instructions: Some(1000),
+            ..Limits::default()
+        })
+        .run_text(forever, "<test>")
+        .unwrap_err();
+        assert!(e.contains("instruction limit of 1000 exceeded"), "{}", e);
+
+        let e = limited(Limits {
+            time: Some(Duration::from_millis(50)),
+            ..Limits::default()
+        })
+        .run_text(forever, "<test>")
+        .unwrap_err();
+        assert!(e.contains("time limit of 50ms exceeded"), "{}", e);
+
+        // A single large value is stopped before it's made.
+        let e = limited(Limits {
+            time: Some(Duration::ZERO),
+            ..Limits::default()
+        })
+        .run_text("\"ab\" * 100000000", "<test>")
+        .unwrap_err();
+        assert!(e.contains("time limit of 0ns exceeded"), "{}", e);
+
+        let memory = Limits {
+            memory: Some(1000),
+            ..Limits::default()
+        };
+        for src in [
+            "\"ab\" * 9223372036854775807",
+            "2 ** 100000",
+            "s = \"ab\"\nwhile true s = \"${s}${s}\"",
+            "s = \"ab\"\nwhile true s += s",
+            "a = [0]\ni = 0\nwhile i < 40 do\na = a + a\ni += 1\nend",
+        ] {
+            let e = limited(memory).run_text(src, "<test>").unwrap_err();
+            assert!(e.contains("memory limit of 1000 bytes exceeded"), "{}", e);
+        }
+        assert_eq!(
+            limited(memory)
+                .run_text("\"ab\" * 500", "<test>")
+                .unwrap()
+                .size(),
+            1000
+        );
+
+        let src = "def r(n: i32) -> i32 do r(n + 1); 1 end\nr(0)";
+        let e = limited(Limits {
+            call_depth: Some(10),
+            ..Limits::default()
+        })
+        .run_text(src, "<test>")
+        .unwrap_err();
+        assert!(e.contains("maximum call depth exceeded"), "{}", e);
+        // The limit can be raised past the default as well.
+        let src = "def f(n: i32) -> Any if n == 0 then 0 else 1 + f(n - 1)\nf(12000)";
+        let mut interpreter = limited(Limits {
+            call_depth: Some(20000),
+            ..Limits::default()
+        });
+        assert_eq!(interpreter.run_text(src, "<test>").unwrap().repr(), "12000");
+
+        let src = "
+            class Node {}
+            nodes = []
+            i = 0
+            while i < 1000 do
+                nodes = [Node(), nodes]
+                i += 1
+            end
+        ";
+        let e = limited(Limits {
+            heap: Some(100),
+            ..Limits::default()
+        })
+        .run_text(src, "<test>")
+        .unwrap_err();
+        assert!(e.contains("heap limit of 100 containers exceeded"), "{}", e);
+        // Garbage doesn't count.
+        let src = "i = 0\nwhile i < 1000 do\n[i]\ni += 1\nend\ni";
+        let mut interpreter = limited(Limits {
+            heap: Some(100),
+            ..Limits::default()
+        });
+        assert_eq!(interpreter.run_text(src, "<test>").unwrap().repr(), "1000");
+    }
+
+    #[test]
+    fn capabilities() {
+        let mut interpreter = Interpreter::new().with_capabilities([Capability::ReadFiles]);
+        let e = interpreter
+            .run_text("write_file(\"/nowhere/x\", \"\")", "<test>")
+            .unwrap_err();
+        assert!(
+            e.contains("`write_file` needs the `write-files` capability"),
+            "{}",
+            e
+        );
+        let e = interpreter
+            .run_text("read_file(\"/nowhere/x\")", "<test>")
+            .unwrap_err();
+        assert!(e.contains("/nowhere/x"), "{}", e);
+        let e = interpreter
+            .run_text("extern \"./libnothing.so\"", "<test>")
+            .unwrap_err();
+        assert!(e.contains("the `native` capability"), "{}", e);
+
+        // Only the standard library can be used without reading files, and
+        // what isn't in it isn't shown.
+        let dir = std::env::temp_dir().join(format!("lyron-sandbox-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let secret = dir.join("secret");
+        std::fs::write(&secret, "root:x:0:0 secret").unwrap();
+        let src = format!("use \"{}\"", secret.display());
+        let mut engine = Engine::new().with_capabilities([]);
+        let e = engine.eval(&src).unwrap_err().to_string();
+        assert!(e.contains("needs the `read-files` capability"), "{}", e);
+        assert!(!e.contains("root:x"), "{}", e);
+        let e = engine
+            .eval("use \"std:../secret\"")
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("needs the `read-files` capability"), "{}", e);
+        engine.eval("use \"std:json\"").unwrap();
+        let mut engine = Engine::new().with_capabilities([Capability::ReadFiles]);
+        let e = engine.eval(&src).unwrap_err().to_string();
+        assert!(!e.contains("capability"), "{}", e);
+
+        assert_eq!("network".parse(), Ok(Capability::Network));
+        assert!("disk".parse::<Capability>().is_err());
+    }
+}
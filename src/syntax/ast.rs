@@ -0,0 +1,371 @@
+//! Typed views over the syntax tree.
+//!
+//! Each view wraps a [`SyntaxNode`] of one kind and has accessors for its parts.
+//! Accessors return `None` when the source is missing that part.
+
+use crate::syntax::{SyntaxKind, SyntaxNode, SyntaxToken};
+
+/// A typed wrapper around a [`SyntaxNode`] of a specific kind.
+pub trait TypedNode: Sized {
+    fn can_cast(kind: SyntaxKind) -> bool;
+
+    fn cast(syntax: SyntaxNode) -> Option<Self>;
+
+    fn syntax(&self) -> &SyntaxNode;
+}
+
+macro_rules! typed_node {
+    ($($(#[$doc:meta])* $name:ident),* $(,)?) => {
+        $(
+            $(#[$doc])*
+            #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+            pub struct $name {
+                pub(crate) syntax: SyntaxNode,
+            }
+
+            impl TypedNode for $name {
+                fn can_cast(kind: SyntaxKind) -> bool {
+                    kind == SyntaxKind::$name
+                }
+
+                fn cast(syntax: SyntaxNode) -> Option<Self> {
+                    Self::can_cast(syntax.kind()).then_some($name { syntax })
+                }
+
+                fn syntax(&self) -> &SyntaxNode {
+                    &self.syntax
+                }
+            }
+        )*
+    };
+}
+
+typed_node!(
+    SourceFile,
+    FnDef,
+    ParamList,
+    Param,
+    ClassDef,
+    /// `name(args)`
+    CallExpr,
+    ArgList,
+    KeywordArg,
+    /// `do ... end`
+    DoExpr,
+    /// `use "path"`, `use "path" as name` or `use { items } from "path"`
+    UseExpr,
+    ImportItem,
+);
+
+fn child<N: TypedNode>(parent: &SyntaxNode) -> Option<N> {
+    parent.children().find_map(N::cast)
+}
+
+fn children<N: TypedNode>(parent: &SyntaxNode) -> impl Iterator<Item = N> {
+    parent.children().filter_map(N::cast)
+}
+
+/// The first token of `kind` among the direct children of the `node_kind` child.
+fn token_in(parent: &SyntaxNode, node_kind: SyntaxKind, kind: SyntaxKind) -> Option<SyntaxToken> {
+    parent
+        .children()
+        .find(|n| n.kind() == node_kind)?
+        .children_with_tokens()
+        .filter_map(|e| e.into_token())
+        .find(|t| t.kind() == kind)
+}
+
+fn token(parent: &SyntaxNode, kind: SyntaxKind) -> Option<SyntaxToken> {
+    parent
+        .children_with_tokens()
+        .filter_map(|e| e.into_token())
+        .find(|t| t.kind() == kind)
+}
+
+/// Expressions are kept untyped: their [`SyntaxKind`] tells them apart.
+fn is_expr(kind: SyntaxKind) -> bool {
+    use SyntaxKind as K;
+    matches!(
+        kind,
+        K::Literal
+            | K::NameRef
+            | K::CallExpr
+            | K::BinExpr
+            | K::PrefixExpr
+            | K::ParenExpr
+            | K::TupleExpr
+            | K::ArrayExpr
+            | K::DictPattern
+            | K::DoExpr
+            | K::IfExpr
+            | K::WhileExpr
+            | K::LetExpr
+            | K::AssignExpr
+            | K::WalrusExpr
+            | K::ReturnExpr
+            | K::UseExpr
+            | K::ExternExpr
+            | K::Error
+    )
+}
+
+/// A top-level item of a [`SourceFile`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Item {
+    FnDef(FnDef),
+    ClassDef(ClassDef),
+    Expr(SyntaxNode),
+}
+
+impl SourceFile {
+    pub fn items(&self) -> impl Iterator<Item = Item> {
+        self.syntax.children().filter_map(|n| match n.kind() {
+            SyntaxKind::FnDef => Some(Item::FnDef(FnDef { syntax: n })),
+            SyntaxKind::ClassDef => Some(Item::ClassDef(ClassDef { syntax: n })),
+            k if is_expr(k) => Some(Item::Expr(n)),
+            _ => None,
+        })
+    }
+
+    pub fn fns(&self) -> impl Iterator<Item = FnDef> {
+        children(&self.syntax)
+    }
+
+    pub fn classes(&self) -> impl Iterator<Item = ClassDef> {
+        children(&self.syntax)
+    }
+}
+
+impl FnDef {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        token_in(&self.syntax, SyntaxKind::Name, SyntaxKind::Ident)
+    }
+
+    pub fn is_pub(&self) -> bool {
+        token(&self.syntax, SyntaxKind::PubKw).is_some()
+    }
+
+    /// The library string of an `extern` function, quotes included.
+    pub fn extern_library(&self) -> Option<SyntaxToken> {
+        token(&self.syntax, SyntaxKind::ExternKw)?;
+        token(&self.syntax, SyntaxKind::Str)
+    }
+
+    pub fn param_list(&self) -> Option<ParamList> {
+        child(&self.syntax)
+    }
+
+    pub fn return_type(&self) -> Option<SyntaxToken> {
+        token_in(&self.syntax, SyntaxKind::TypeRef, SyntaxKind::Ident)
+    }
+
+    pub fn body(&self) -> Option<SyntaxNode> {
+        self.syntax.children().find(|n| is_expr(n.kind()))
+    }
+}
+
+impl ParamList {
+    pub fn params(&self) -> impl Iterator<Item = Param> {
+        children(&self.syntax)
+    }
+}
+
+impl Param {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        token_in(&self.syntax, SyntaxKind::Name, SyntaxKind::Ident)
+    }
+
+    pub fn type_name(&self) -> Option<SyntaxToken> {
+        token_in(&self.syntax, SyntaxKind::TypeRef, SyntaxKind::Ident)
+    }
+
+    pub fn default(&self) -> Option<SyntaxNode> {
+        self.syntax.children().find(|n| is_expr(n.kind()))
+    }
+
+    /// `*rest: Type`
+    pub fn is_variadic(&self) -> bool {
+        token(&self.syntax, SyntaxKind::Mul).is_some()
+    }
+
+    /// `**opts: Type`
+    pub fn is_keywords(&self) -> bool {
+        token(&self.syntax, SyntaxKind::Pow).is_some()
+    }
+}
+
+impl ClassDef {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        token_in(&self.syntax, SyntaxKind::Name, SyntaxKind::Ident)
+    }
+
+    pub fn is_pub(&self) -> bool {
+        token(&self.syntax, SyntaxKind::PubKw).is_some()
+    }
+
+    pub fn fns(&self) -> impl Iterator<Item = FnDef> {
+        children(&self.syntax)
+    }
+}
+
+impl CallExpr {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        token_in(&self.syntax, SyntaxKind::NameRef, SyntaxKind::Ident)
+    }
+
+    pub fn arg_list(&self) -> Option<ArgList> {
+        child(&self.syntax)
+    }
+}
+
+impl ArgList {
+    /// Positional arguments.
+    pub fn args(&self) -> impl Iterator<Item = SyntaxNode> {
+        self.syntax.children().filter(|n| is_expr(n.kind()))
+    }
+
+    pub fn keyword_args(&self) -> impl Iterator<Item = KeywordArg> {
+        children(&self.syntax)
+    }
+}
+
+impl KeywordArg {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        token_in(&self.syntax, SyntaxKind::Name, SyntaxKind::Ident)
+    }
+
+    pub fn value(&self) -> Option<SyntaxNode> {
+        self.syntax.children().find(|n| is_expr(n.kind()))
+    }
+}
+
+impl DoExpr {
+    pub fn exprs(&self) -> impl Iterator<Item = SyntaxNode> {
+        self.syntax.children().filter(|n| is_expr(n.kind()))
+    }
+}
+
+impl UseExpr {
+    /// The string naming the module, quotes included.
+    pub fn path(&self) -> Option<SyntaxToken> {
+        token(&self.syntax, SyntaxKind::Str)
+    }
+
+    /// `name` in `use "path" as name`.
+    pub fn alias(&self) -> Option<SyntaxToken> {
+        token_in(&self.syntax, SyntaxKind::Name, SyntaxKind::Ident)
+    }
+
+    /// The names in `use { items } from "path"`.
+    pub fn items(&self) -> impl Iterator<Item = ImportItem> {
+        children(&self.syntax)
+    }
+}
+
+impl ImportItem {
+    /// The name the module exports.
+    pub fn name(&self) -> Option<SyntaxToken> {
+        token_in(&self.syntax, SyntaxKind::NameRef, SyntaxKind::Ident)
+    }
+
+    /// `alias` in `name as alias`.
+    pub fn alias(&self) -> Option<SyntaxToken> {
+        token_in(&self.syntax, SyntaxKind::Name, SyntaxKind::Ident)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Item, TypedNode};
+    use crate::syntax::{SyntaxKind, parse};
+
+    #[test]
+    fn views() {
+        let result = parse(
+            "def f(a: i32, b: Str = \"x\", *rest: Any) -> Any do a end\nclass C { def m(self: Self) -> None none }\nf(1, b=2)",
+        );
+        let file = result.source_file();
+        let items = file.items().collect::<Vec<_>>();
+        assert_eq!(items.len(), 3);
+
+        let Item::FnDef(f) = &items[0] else {
+            panic!("expected a function")
+        };
+        assert_eq!(f.name().unwrap().text(), "f");
+        assert_eq!(f.return_type().unwrap().text(), "Any");
+        assert_eq!(f.body().unwrap().kind(), SyntaxKind::DoExpr);
+        let params = f.param_list().unwrap().params().collect::<Vec<_>>();
+        assert_eq!(params.len(), 3);
+        assert_eq!(params[1].type_name().unwrap().text(), "Str");
+        assert_eq!(params[1].default().unwrap().text().to_string(), "\"x\"");
+        assert!(params[2].is_variadic());
+
+        let class = file.classes().next().unwrap();
+        assert_eq!(class.name().unwrap().text(), "C");
+        assert_eq!(class.fns().next().unwrap().name().unwrap().text(), "m");
+
+        let Item::Expr(call) = &items[2] else {
+            panic!("expected an expression")
+        };
+        let call = super::CallExpr::cast(call.clone()).unwrap();
+        assert_eq!(call.name().unwrap().text(), "f");
+        let args = call.arg_list().unwrap();
+        assert_eq!(args.args().count(), 1);
+        assert_eq!(
+            args.keyword_args().next().unwrap().name().unwrap().text(),
+            "b"
+        );
+    }
+
+    #[test]
+    fn imports() {
+        let result = parse(
+            "use \"std:json\" as json\nuse { JSON, dumps as d } from \"std:json\"\npub def f() -> Any 1\npub class C {}",
+        );
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let file = result.source_file();
+        let uses = file
+            .syntax()
+            .children()
+            .filter_map(super::UseExpr::cast)
+            .collect::<Vec<_>>();
+        assert_eq!(uses[0].path().unwrap().text(), "\"std:json\"");
+        assert_eq!(uses[0].alias().unwrap().text(), "json");
+        assert_eq!(uses[0].items().count(), 0);
+
+        assert!(uses[1].alias().is_none());
+        let items = uses[1]
+            .items()
+            .map(|i| {
+                let alias = i.alias().map(|a| a.text().to_string());
+                (i.name().unwrap().text().to_string(), alias)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            [
+                ("JSON".to_string(), None),
+                ("dumps".to_string(), Some("d".to_string()))
+            ]
+        );
+
+        assert!(file.fns().next().unwrap().is_pub());
+        assert!(file.classes().next().unwrap().is_pub());
+        assert!(!parse("pub x = 1").errors.is_empty());
+        assert!(!parse("use { a } \"x\"").errors.is_empty());
+    }
+
+    #[test]
+    fn externs() {
+        let result =
+            parse("extern \"libm.so.6\" def cos(x: f64) -> f64\nextern \"libx.so\"\ncos(0)");
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let file = result.source_file();
+        let f = file.fns().next().unwrap();
+        assert_eq!(f.extern_library().unwrap().text(), "\"libm.so.6\"");
+        assert_eq!(f.name().unwrap().text(), "cos");
+        assert!(f.body().is_none());
+        assert_eq!(file.items().count(), 3);
+        assert!(!parse("extern def f() -> Any").errors.is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+use crate::lexer::tokens::{Token, Trivia, TriviaKind};
+use crate::syntax::{Parse, SyntaxError, SyntaxKind};
+
+use rowan::{Checkpoint, GreenNodeBuilder, TextRange, TextSize};
+
+/// Builds the syntax tree from the tokens [`crate::parser::Parser`] eats, so
+/// the tree follows the one grammar there is.
+///
+/// Trivia is added to the tree before the next node or token, so it sits
+/// outside the nodes around it. Text the lexer rejected is kept in
+/// [`SyntaxKind::ErrorToken`]s.
+pub(crate) struct TreeBuilder {
+    builder: GreenNodeBuilder<'static>,
+    chars: Vec<char>,
+    /// Number of characters of the source already in the tree.
+    covered: usize,
+    /// Byte offset of the end of the tree so far.
+    offset: TextSize,
+    /// Trivia after the last token, not in the tree yet.
+    pending: Vec<Trivia>,
+    /// Whether the leading trivia of the next token is in the tree already.
+    leading_done: bool,
+    /// Lexer errors, with the offset of the token following them.
+    lexer_errors: Vec<(usize, String)>,
+    /// `(line_no, pos)` and range of the last token, to place parser errors.
+    last: Option<((i32, i32), TextRange)>,
+    /// Number of nodes open.
+    depth: usize,
+    errors: Vec<SyntaxError>,
+}
+
+impl TreeBuilder {
+    pub(crate) fn new(text: &str, lexer_errors: Vec<(usize, String)>) -> Self {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind::SourceFile.into());
+        TreeBuilder {
+            builder,
+            chars: text.chars().collect(),
+            covered: 0,
+            offset: 0.into(),
+            pending: vec![],
+            leading_done: false,
+            lexer_errors,
+            last: None,
+            depth: 1,
+            errors: vec![],
+        }
+    }
+
+    fn push(&mut self, kind: SyntaxKind, text: &str) {
+        self.builder.token(kind.into(), text);
+        self.offset += TextSize::of(text);
+        self.covered += text.chars().count();
+    }
+
+    /// Add the trivia and rejected text up to `next`.
+    fn trivia(&mut self, next: &Token) {
+        for t in std::mem::take(&mut self.pending) {
+            self.push(trivia_kind(t.kind), &t.text);
+        }
+        if self.leading_done {
+            return;
+        }
+        self.leading_done = true;
+        let leading = next
+            .leading_trivia
+            .iter()
+            .map(|t| t.text.chars().count())
+            .sum::<usize>();
+        let start = next.offset - leading;
+        if start > self.covered {
+            let text = self.chars[self.covered..start].iter().collect::<String>();
+            let range = TextRange::at(self.offset, TextSize::of(text.as_str()));
+            let count = self
+                .lexer_errors
+                .iter()
+                .take_while(|(at, _)| *at <= next.offset)
+                .count();
+            self.errors.extend(
+                self.lexer_errors
+                    .drain(..count)
+                    .map(|(_, message)| SyntaxError { message, range }),
+            );
+            self.push(SyntaxKind::ErrorToken, &text);
+        }
+        for t in &next.leading_trivia {
+            self.push(trivia_kind(t.kind), &t.text);
+        }
+    }
+
+    /// Add `token`, which the parser just ate.
+    pub(crate) fn token(&mut self, token: &Token) {
+        self.trivia(token);
+        let range = TextRange::at(self.offset, TextSize::of(token.text.as_str()));
+        self.push(SyntaxKind::from_token(&token.type_), &token.text);
+        self.last = Some(((token.line_no, token.pos), range));
+        self.pending = token.trailing_trivia.clone();
+        self.leading_done = false;
+    }
+
+    /// Open a node starting at `next`.
+    pub(crate) fn start_node(&mut self, kind: SyntaxKind, next: &Token) {
+        self.trivia(next);
+        self.builder.start_node(kind.into());
+        self.depth += 1;
+    }
+
+    pub(crate) fn finish_node(&mut self) {
+        self.builder.finish_node();
+        self.depth -= 1;
+    }
+
+    /// Where a node starting at `next` can be opened later on.
+    pub(crate) fn checkpoint(&mut self, next: &Token) -> Checkpoint {
+        self.trivia(next);
+        self.builder.checkpoint()
+    }
+
+    /// Wrap everything since `checkpoint` in a node.
+    pub(crate) fn wrap(&mut self, checkpoint: Checkpoint, kind: SyntaxKind) {
+        self.builder.start_node_at(checkpoint, kind.into());
+        self.builder.finish_node();
+    }
+
+    /// Report an error at `at`, the `(line_no, pos)` of either the last
+    /// token or `next`, or at `next` when it isn't known.
+    pub(crate) fn error(&mut self, message: String, at: Option<(i32, i32)>, next: &Token) {
+        let start = self.chars[..next.offset]
+            .iter()
+            .map(|c| c.len_utf8() as u32)
+            .sum::<u32>();
+        let next_range = TextRange::at(start.into(), TextSize::of(next.text.as_str()));
+        let range = match self.last {
+            Some((last, range)) if at == Some(last) && at != Some((next.line_no, next.pos)) => {
+                range
+            }
+            _ => next_range,
+        };
+        self.errors.push(SyntaxError { message, range });
+    }
+
+    /// Close every node but the [`SyntaxKind::SourceFile`].
+    pub(crate) fn close_nodes(&mut self) {
+        while self.depth > 1 {
+            self.finish_node();
+        }
+    }
+
+    /// Add the trivia before `eof` and close the tree.
+    pub(crate) fn finish(mut self, eof: &Token) -> Parse {
+        self.trivia(eof);
+        let end = TextRange::empty(self.offset);
+        self.errors
+            .extend(self.lexer_errors.drain(..).map(|(_, message)| SyntaxError {
+                message,
+                range: end,
+            }));
+        while self.depth > 0 {
+            self.finish_node();
+        }
+        Parse {
+            green: self.builder.finish(),
+            errors: self.errors,
+        }
+    }
+}
+
+fn trivia_kind(kind: TriviaKind) -> SyntaxKind {
+    match kind {
+        TriviaKind::Whitespace => SyntaxKind::Whitespace,
+        TriviaKind::Newline => SyntaxKind::Newline,
+        TriviaKind::Comment => SyntaxKind::Comment,
+    }
+}
@@ -0,0 +1,362 @@
+//! A lossless concrete syntax tree for tooling.
+//!
+//! [`parse`] builds a tree that keeps every character of the source, including
+//! comments, whitespace, parentheses and keywords, so formatters and editors can
+//! work on the exact source and print it back unchanged. It is built by the
+//! parser of [`crate::parser`] alongside the AST, which compilation goes
+//! through; [`ast`] offers typed views over this tree.
+
+pub mod ast;
+mod builder;
+
+pub(crate) use builder::TreeBuilder;
+
+use crate::lexer::Lexer;
+use crate::lexer::tokens::TokenType;
+use crate::parser::Parser;
+
+use rowan::{GreenNode, TextRange};
+
+/// Kinds of tokens and nodes in the syntax tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u16)]
+pub enum SyntaxKind {
+    // Trivia
+    Whitespace,
+    Newline,
+    Comment,
+    /// Text the lexer couldn't make sense of.
+    ErrorToken,
+
+    // Tokens
+    Ident,
+    Integer,
+    Double,
+    Str,
+    IfKw,
+    ElseKw,
+    LetKw,
+    DefKw,
+    ClassKw,
+    ExternKw,
+    UseKw,
+    PubKw,
+    AsKw,
+    FromKw,
+    ReturnKw,
+    TrueKw,
+    FalseKw,
+    ModKw,
+    WhileKw,
+    DoKw,
+    EndKw,
+    NoneKw,
+    ThenKw,
+    AndKw,
+    OrKw,
+    AsyncKw,
+    AwaitKw,
+    Semicolon,
+    Colon,
+    Comma,
+    LParen,
+    RParen,
+    LBrack,
+    RBrack,
+    LBrace,
+    RBrace,
+    Arrow,
+    Minus,
+    Plus,
+    Div,
+    Mul,
+    Dot,
+    Assign,
+    Less,
+    Greater,
+    LessEq,
+    GreaterEq,
+    Equal,
+    Not,
+    NotEq,
+    Percent,
+    Pow,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    PlusEq,
+    MinusEq,
+    MulEq,
+    DivEq,
+    Walrus,
+
+    // Nodes
+    SourceFile,
+    /// `def name(params) -> Type body`, maybe after `pub`, or a C function
+    /// `extern "library" def name(params) -> Type`, which has no body
+    FnDef,
+    ParamList,
+    /// `name: Type`, `name: Type = default`, `*name: Type` or `**name: Type`
+    Param,
+    /// The name being defined by a function, class or parameter.
+    Name,
+    TypeRef,
+    /// `class Name { fns }`, maybe after `pub`
+    ClassDef,
+    /// `true`, `1`, `"str"`, `none`...
+    Literal,
+    /// A use of a variable.
+    NameRef,
+    /// `name(args)`
+    CallExpr,
+    ArgList,
+    /// `name=value` inside an [`SyntaxKind::ArgList`]
+    KeywordArg,
+    BinExpr,
+    PrefixExpr,
+    ParenExpr,
+    TupleExpr,
+    ArrayExpr,
+    /// `{a, b}`, only valid as an assignment target.
+    DictPattern,
+    /// `do exprs end`
+    DoExpr,
+    /// `if cond then a else b`
+    IfExpr,
+    WhileExpr,
+    /// `let name: Type` or `let (a, b) = value`
+    LetExpr,
+    /// `target = value`, `target += value`...
+    AssignExpr,
+    /// `name =: value`
+    WalrusExpr,
+    ReturnExpr,
+    /// `use "path"`, `use "path" as name` or `use { items } from "path"`
+    UseExpr,
+    /// `name` or `name as alias` in the braces of a [`SyntaxKind::UseExpr`]
+    ImportItem,
+    ExternExpr,
+    /// Tokens that don't fit the grammar.
+    Error,
+}
+
+impl SyntaxKind {
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::Whitespace | SyntaxKind::Newline | SyntaxKind::Comment
+        )
+    }
+
+    fn from_token(tok: &TokenType) -> Self {
+        use SyntaxKind as K;
+        match tok {
+            TokenType::Identifier(_) => K::Ident,
+            TokenType::Integer(_) | TokenType::BigInteger(_) => K::Integer,
+            TokenType::Double(_) => K::Double,
+            TokenType::Str(_) | TokenType::Interpolated(_) => K::Str,
+            TokenType::If => K::IfKw,
+            TokenType::Else => K::ElseKw,
+            TokenType::Let => K::LetKw,
+            TokenType::Def => K::DefKw,
+            TokenType::Class => K::ClassKw,
+            TokenType::Extern => K::ExternKw,
+            TokenType::Use => K::UseKw,
+            TokenType::Pub => K::PubKw,
+            TokenType::As => K::AsKw,
+            TokenType::From => K::FromKw,
+            TokenType::Return => K::ReturnKw,
+            TokenType::True => K::TrueKw,
+            TokenType::False => K::FalseKw,
+            TokenType::Module => K::ModKw,
+            TokenType::While => K::WhileKw,
+            TokenType::Do => K::DoKw,
+            TokenType::End => K::EndKw,
+            TokenType::None => K::NoneKw,
+            TokenType::Then => K::ThenKw,
+            TokenType::And => K::AndKw,
+            TokenType::Or => K::OrKw,
+            TokenType::Async => K::AsyncKw,
+            TokenType::Await => K::AwaitKw,
+            TokenType::Semicolon => K::Semicolon,
+            TokenType::Colon => K::Colon,
+            TokenType::Comma => K::Comma,
+            TokenType::LParen => K::LParen,
+            TokenType::RParen => K::RParen,
+            TokenType::LBrack => K::LBrack,
+            TokenType::RBrack => K::RBrack,
+            TokenType::LBrace => K::LBrace,
+            TokenType::RBrace => K::RBrace,
+            TokenType::Arrow => K::Arrow,
+            TokenType::Minus => K::Minus,
+            TokenType::Plus => K::Plus,
+            TokenType::Div => K::Div,
+            TokenType::Mul => K::Mul,
+            TokenType::Dot => K::Dot,
+            TokenType::Assign => K::Assign,
+            TokenType::Less => K::Less,
+            TokenType::Greater => K::Greater,
+            TokenType::LessEq => K::LessEq,
+            TokenType::GreaterEq => K::GreaterEq,
+            TokenType::Equal => K::Equal,
+            TokenType::Not => K::Not,
+            TokenType::NotEq => K::NotEq,
+            TokenType::Mod => K::Percent,
+            TokenType::Pow => K::Pow,
+            TokenType::BitAnd => K::BitAnd,
+            TokenType::BitOr => K::BitOr,
+            TokenType::BitXor => K::BitXor,
+            TokenType::Shl => K::Shl,
+            TokenType::Shr => K::Shr,
+            TokenType::PlusEq => K::PlusEq,
+            TokenType::MinusEq => K::MinusEq,
+            TokenType::MulEq => K::MulEq,
+            TokenType::DivEq => K::DivEq,
+            TokenType::Walrus => K::Walrus,
+            // Never stored in the tree.
+            TokenType::Eof => K::ErrorToken,
+        }
+    }
+}
+
+impl From<SyntaxKind> for rowan::SyntaxKind {
+    fn from(kind: SyntaxKind) -> Self {
+        rowan::SyntaxKind(kind as u16)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Lyron {}
+
+impl rowan::Language for Lyron {
+    type Kind = SyntaxKind;
+
+    fn kind_from_raw(raw: rowan::SyntaxKind) -> SyntaxKind {
+        assert!(raw.0 <= SyntaxKind::Error as u16);
+        // SAFETY: `SyntaxKind` is `repr(u16)` and `raw` is in range.
+        unsafe { std::mem::transmute::<u16, SyntaxKind>(raw.0) }
+    }
+
+    fn kind_to_raw(kind: SyntaxKind) -> rowan::SyntaxKind {
+        kind.into()
+    }
+}
+
+pub type SyntaxNode = rowan::SyntaxNode<Lyron>;
+pub type SyntaxToken = rowan::SyntaxToken<Lyron>;
+pub type SyntaxElement = rowan::SyntaxElement<Lyron>;
+
+/// A problem found while building the tree. The tree is still complete.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    /// Byte range of the offending text.
+    pub range: TextRange,
+}
+
+/// The result of [`parse`].
+pub struct Parse {
+    green: GreenNode,
+    pub errors: Vec<SyntaxError>,
+}
+
+impl Parse {
+    /// The root [`SyntaxKind::SourceFile`] node.
+    pub fn syntax(&self) -> SyntaxNode {
+        SyntaxNode::new_root(self.green.clone())
+    }
+
+    /// Typed view of the root node.
+    pub fn source_file(&self) -> ast::SourceFile {
+        ast::SourceFile {
+            syntax: self.syntax(),
+        }
+    }
+}
+
+/// Build the lossless syntax tree of `text`. Never fails: problems are reported
+/// in [`Parse::errors`] and the offending text is kept in error tokens and nodes.
+///
+/// The tree is built by [`crate::parser::Parser`], which goes on with the next
+/// line after an error.
+pub fn parse(text: &str) -> Parse {
+    let mut tokens = vec![];
+    let mut lexer_errors = vec![];
+    let mut pending = vec![];
+    for t in Lexer::from_text(text, "<syntax>").lossless() {
+        match t {
+            // Errors are placed at the text rejected before the next token.
+            Ok(t) => {
+                lexer_errors.extend(pending.drain(..).map(|e| (t.offset, e)));
+                tokens.push(t);
+            }
+            Err(e) => pending.push(e),
+        }
+    }
+    Parser::new(tokens.into_iter().peekable(), "<syntax>")
+        .with_source(text)
+        .building(TreeBuilder::new(text, lexer_errors))
+        .parse_tree()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SyntaxKind, parse};
+
+    #[test]
+    fn round_trip() {
+        let src = "# add\ndef add(a: i32, b: i32 = 1) -> i32 do\n\ta + b # sum\nend\n\nclass A {\n  def A(self: Self) -> A self\n}\n(1, [2, 3]) @ x = \"s\"\n";
+        let result = parse(src);
+        assert_eq!(result.syntax().text().to_string(), src);
+        // Only the '@' is wrong.
+        assert_eq!(result.errors.len(), 1);
+        for (i, _) in src.char_indices() {
+            assert_eq!(parse(&src[..i]).syntax().text().to_string(), &src[..i]);
+        }
+
+        for (path, src) in crate::example_sources() {
+            let tree = parse(&src).syntax();
+            assert_eq!(tree.text().to_string(), src, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn recovery() {
+        let result =
+            parse("def f(a i32) -> Any a\nclass A {\n  def m( -> Any 1\n}\ndef g() -> Any 1\n");
+        let messages = result
+            .errors
+            .iter()
+            .map(|e| e.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["expected ':' .", "Expected Identifier or ')'"]);
+        // What was parsed before an error is kept, and parsing goes on with
+        // the next line.
+        let file = result.source_file();
+        let names = file.fns().map(|f| f.name().unwrap().text().to_string());
+        assert_eq!(names.collect::<Vec<_>>(), ["f", "g"]);
+        assert_eq!(file.classes().next().unwrap().name().unwrap().text(), "A");
+    }
+
+    #[test]
+    fn structure() {
+        let parse = parse("def f(a: i32) -> Any a + b * c\nf(1, b=2)");
+        assert!(parse.errors.is_empty(), "{:?}", parse.errors);
+        let kinds = parse
+            .syntax()
+            .descendants()
+            .map(|n| n.kind())
+            .collect::<Vec<_>>();
+        use SyntaxKind::*;
+        assert_eq!(
+            kinds,
+            vec![
+                SourceFile, FnDef, Name, ParamList, Param, Name, TypeRef, TypeRef, BinExpr,
+                NameRef, BinExpr, NameRef, NameRef, CallExpr, NameRef, ArgList, Literal,
+                KeywordArg, Name, Literal
+            ]
+        );
+    }
+}